@@ -20,14 +20,19 @@ export type { BatchFileError } from './models/BatchFileError';
 export type { FileData } from './models/FileData';
 export type { FilePage } from './models/FilePage';
 export type { FileStats } from './models/FileStats';
+export type { ForgotPasswordForm } from './models/ForgotPasswordForm';
 export type { LoginRedirectUrl } from './models/LoginRedirectUrl';
 export type { MessageResponse } from './models/MessageResponse';
 export { OAuthProvider } from './models/OAuthProvider';
 export type { OAuthProviders } from './models/OAuthProviders';
+export type { OidcProviderInfo } from './models/OidcProviderInfo';
 export type { OAuthRequest } from './models/OAuthRequest';
+export type { PasswordResetForm } from './models/PasswordResetForm';
 export type { RegistrationKeyData } from './models/RegistrationKeyData';
 export type { RegistrationKeyPage } from './models/RegistrationKeyPage';
 export type { TokenResponse } from './models/TokenResponse';
+export type { TwoFactorChallenge } from './models/TwoFactorChallenge';
+export type { TwoFactorLoginForm } from './models/TwoFactorLoginForm';
 export type { UnlinkAuthMethod } from './models/UnlinkAuthMethod';
 export type { UpdateUserSettings } from './models/UpdateUserSettings';
 export type { UploadConflict } from './models/UploadConflict';
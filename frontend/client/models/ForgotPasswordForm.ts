@@ -0,0 +1,7 @@
+/* istanbul ignore file */
+/* tslint:disable */
+
+export type ForgotPasswordForm = {
+    email: string;
+};
+
@@ -1,9 +1,15 @@
 /* istanbul ignore file */
 /* tslint:disable */
 
+import type { OidcProviderInfo } from './OidcProviderInfo';
+
 export type OAuthProviders = {
     discord: boolean;
     github: boolean;
     google: boolean;
+    /**
+     * OpenID Connect providers configured on the server.
+     */
+    oidc: Array<OidcProviderInfo>;
 };
 
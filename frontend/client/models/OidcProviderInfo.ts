@@ -0,0 +1,14 @@
+/* istanbul ignore file */
+/* tslint:disable */
+
+export type OidcProviderInfo = {
+    /**
+     * Name shown to users.
+     */
+    displayName: string;
+    /**
+     * Name used in login and callback URLs.
+     */
+    name: string;
+};
+
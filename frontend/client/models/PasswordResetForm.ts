@@ -0,0 +1,11 @@
+/* istanbul ignore file */
+/* tslint:disable */
+
+export type PasswordResetForm = {
+    /**
+     * Code sent in the password reset email
+     */
+    code: string;
+    password: string;
+};
+
@@ -6,5 +6,9 @@
  */
 export type TokenResponse = {
     token: string;
+    /**
+     * Used to get a new token when it expires, only present for logins
+     */
+    refreshToken?: string;
 };
 
@@ -0,0 +1,13 @@
+/* istanbul ignore file */
+/* tslint:disable */
+
+/**
+ * Returned instead of a token when the user has two-factor authentication enabled
+ */
+export type TwoFactorChallenge = {
+    /**
+     * Sent with a code to complete the login
+     */
+    challenge: string;
+};
+
@@ -0,0 +1,14 @@
+/* istanbul ignore file */
+/* tslint:disable */
+
+/**
+ * Complete a login with a two-factor code
+ */
+export type TwoFactorLoginForm = {
+    challenge: string;
+    /**
+     * TOTP code or recovery code
+     */
+    code: string;
+};
+
@@ -6,6 +6,8 @@ import type { LoginRedirectUrl } from '../models/LoginRedirectUrl';
 import type { OAuthProvider } from '../models/OAuthProvider';
 import type { OAuthRequest } from '../models/OAuthRequest';
 import type { TokenResponse } from '../models/TokenResponse';
+import type { TwoFactorChallenge } from '../models/TwoFactorChallenge';
+import type { TwoFactorLoginForm } from '../models/TwoFactorLoginForm';
 import type { UnlinkAuthMethod } from '../models/UnlinkAuthMethod';
 
 import type { CancelablePromise } from '../core/CancelablePromise';
@@ -20,11 +22,12 @@ export class AuthenticationService {
      *
      * @param requestBody
      * @returns TokenResponse
+     * @returns TwoFactorChallenge Two-factor code required
      * @throws ApiError
      */
     public basic(
         requestBody: BasicAuthForm,
-    ): CancelablePromise<TokenResponse> {
+    ): CancelablePromise<TokenResponse | TwoFactorChallenge> {
         return this.httpRequest.request({
             method: 'POST',
             url: '/api/auth/basic',
@@ -32,6 +35,30 @@ export class AuthenticationService {
             mediaType: 'application/json',
             errors: {
                 400: `Invalid credentials`,
+                429: `Too many login attempts or the account is locked`,
+            },
+        });
+    }
+
+    /**
+     * Complete a login with a TOTP code or recovery code.
+     *
+     * @param requestBody
+     * @returns TokenResponse
+     * @throws ApiError
+     */
+    public twoFactor(
+        requestBody: TwoFactorLoginForm,
+    ): CancelablePromise<TokenResponse> {
+        return this.httpRequest.request({
+            method: 'POST',
+            url: '/api/auth/two-factor',
+            body: requestBody,
+            mediaType: 'application/json',
+            errors: {
+                400: `Invalid code`,
+                401: `Challenge expired`,
+                429: `Too many codes were entered`,
             },
         });
     }
@@ -60,7 +87,7 @@ export class AuthenticationService {
      * @throws ApiError
      */
     public oauthLogin(
-        provider: OAuthProvider,
+        provider: OAuthProvider | string,
         includeToken: boolean,
         redirect?: string,
     ): CancelablePromise<LoginRedirectUrl> {
@@ -1,6 +1,8 @@
 /* istanbul ignore file */
 /* tslint:disable */
+import type { ForgotPasswordForm } from '../models/ForgotPasswordForm';
 import type { MessageResponse } from '../models/MessageResponse';
+import type { PasswordResetForm } from '../models/PasswordResetForm';
 import type { UpdateUserSettings } from '../models/UpdateUserSettings';
 import type { UserCreateForm } from '../models/UserCreateForm';
 import type { UserData } from '../models/UserData';
@@ -159,4 +161,54 @@ export class UserService {
         });
     }
 
+    /**
+     * Request a password reset email
+     *
+     * The response is the same whether or not an account has the email.
+     * This will be disabled if `smtp` is disabled in server settings
+     *
+     * @param requestBody
+     * @returns MessageResponse
+     * @throws ApiError
+     */
+    public forgotPassword(
+        requestBody: ForgotPasswordForm,
+    ): CancelablePromise<MessageResponse> {
+        return this.httpRequest.request({
+            method: 'POST',
+            url: '/api/user/password/forgot',
+            body: requestBody,
+            mediaType: 'application/json',
+            errors: {
+                409: `SMTP is disabled`,
+            },
+        });
+    }
+
+    /**
+     * Set a new password using a password reset code
+     *
+     * Every session of the user is logged out.
+     * This will be disabled if `smtp` is disabled in server settings
+     *
+     * @param requestBody
+     * @returns MessageResponse
+     * @throws ApiError
+     */
+    public resetPassword(
+        requestBody: PasswordResetForm,
+    ): CancelablePromise<MessageResponse> {
+        return this.httpRequest.request({
+            method: 'POST',
+            url: '/api/user/password/reset',
+            body: requestBody,
+            mediaType: 'application/json',
+            errors: {
+                400: `Invalid reset code or password`,
+                409: `SMTP is disabled`,
+                429: `Too many codes were entered`,
+            },
+        });
+    }
+
 }
@@ -66,6 +66,7 @@ const Header: React.FC = () => {
   const User = observer(() => {
     const onLogout = async () => {
       localStorage.removeItem("token")
+      localStorage.removeItem("refreshToken")
       store?.setUserInfo(undefined)
       Router.push("/")
     }
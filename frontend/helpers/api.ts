@@ -3,7 +3,55 @@ import getConfig from "next/config"
 
 const { publicRuntimeConfig } = getConfig()
 
+/** Seconds before expiry when a login token is refreshed. */
+const REFRESH_MARGIN = 30
+
+let refreshing: Promise<string | undefined> | null = null
+
+function tokenExpiry(token: string): number | undefined {
+	try {
+		return JSON.parse(atob(token.split(".")[1].replace(/-/g, "+").replace(/_/g, "/"))).exp
+	} catch {
+		return undefined
+	}
+}
+
+/** Exchange the stored refresh token for a new login token. */
+async function refreshToken(refreshToken: string): Promise<string | undefined> {
+	const res = await fetch(`${publicRuntimeConfig.apiRoot}/api/auth/refresh`, {
+		method: "POST",
+		headers: { "Content-Type": "application/json" },
+		body: JSON.stringify({ refreshToken })
+	})
+
+	if (!res.ok) {
+		localStorage.removeItem("token")
+		localStorage.removeItem("refreshToken")
+		return undefined
+	}
+
+	const tokenRes = await res.json()
+	localStorage.setItem("token", tokenRes.token)
+	localStorage.setItem("refreshToken", tokenRes.refreshToken)
+	return tokenRes.token
+}
+
+async function getToken(): Promise<string | undefined> {
+	const token = localStorage.getItem("token") || undefined
+	const refresh = localStorage.getItem("refreshToken")
+	if (token == null || refresh == null)
+		return token
+
+	const exp = tokenExpiry(token)
+	if (exp != null && exp - REFRESH_MARGIN > Date.now() / 1000)
+		return token
+
+	// Refresh tokens can only be used once, so concurrent requests share a refresh.
+	refreshing ??= refreshToken(refresh).finally(() => { refreshing = null })
+	return refreshing
+}
+
 export default new BackpackClient({
 	BASE: publicRuntimeConfig.apiRoot,
-	TOKEN: async () => localStorage.getItem("token") || undefined as unknown as string
+	TOKEN: async () => await getToken() as string
 })
@@ -32,7 +32,7 @@ import GithubSVG from "assets/icons/github.svg"
 import DiscordSVG from "assets/icons/discord.svg"
 
 import styles from "styles/login.module.scss"
-import { BasicAuthForm, OAuthProvider } from "@/client"
+import { BasicAuthForm, OAuthProvider, TokenResponse } from "@/client"
 import api from "helpers/api"
 import { useAppInfo } from "helpers/info"
 import { useStore } from "helpers/store"
@@ -40,27 +40,39 @@ import { observer } from "mobx-react-lite"
 
 const Login: NextPage = observer(() => {
     const [postLoginUnverifiedEmail, setPostLoginUnverifiedEmail] = React.useState<string | null>(null)
+    // Set when a two-factor code is needed to complete the login
+    const [challenge, setChallenge] = React.useState<string | null>(null)
     const router = useRouter()
     const appInfo = useAppInfo()
 
-    const { token } = router.query
-
     const { register, handleSubmit } = useForm()
+    const { register: registerCode, handleSubmit: handleCodeSubmit } = useForm()
     const toast = useToast()
 
     const store = useStore()
 
     React.useEffect(() => {
+        // OAuth logins pass the tokens or a two-factor challenge in the URL fragment
+        const params = new URLSearchParams(window.location.hash.slice(1))
+        const token = params.get("token")
+        const fragmentChallenge = params.get("challenge")
+        if (token != null || fragmentChallenge != null)
+            window.history.replaceState(null, "", window.location.pathname + window.location.search)
+
         if (token != null)
-            tokenLogin(token as string)
+            tokenLogin(token, params.get("refreshToken") ?? undefined)
+        else if (fragmentChallenge != null)
+            setChallenge(fragmentChallenge)
         
         if (store?.userData != null) {
             router.replace("/user/uploads")
         }
     }, [store?.userData])
 
-    const tokenLogin = React.useCallback((token: string) => {
+    const tokenLogin = React.useCallback((token: string, refreshToken?: string) => {
         localStorage.setItem("token", token)
+        if (refreshToken != null)
+            localStorage.setItem("refreshToken", refreshToken)
 
         api.user.info().then(userInfo => {
             store?.setUserInfo(userInfo)
@@ -78,23 +90,40 @@ const Login: NextPage = observer(() => {
         })
     }, [])
 
+    const authError = React.useCallback((error: any) => {
+        toast({
+            title: "Authentication Error",
+            description: error.body.message,
+            status: "error",
+            duration: 5000,
+            isClosable: true
+        })
+    }, [])
+
     const formSubmit = (data: BasicAuthForm) => {
         api.authentication.basic(data)
-            .then(tokenRes => {
-                tokenLogin(tokenRes.token)
+            .then(res => {
+                // Accounts with two-factor authentication get a challenge instead of a token
+                if ("challenge" in res)
+                    setChallenge(res.challenge)
+                else
+                    tokenLogin(res.token, res.refreshToken)
             })
+            .catch(authError)
+    }
+
+    const codeSubmit = (data: { code: string }) => {
+        api.authentication.twoFactor({ challenge: challenge as string, code: data.code.trim() })
+            .then((tokenRes: TokenResponse) => tokenLogin(tokenRes.token, tokenRes.refreshToken))
             .catch(error => {
-                toast({
-                    title: "Authentication Error",
-                    description: error.body.message,
-                    status: "error",
-                    duration: 5000,
-                    isClosable: true
-                })
+                // The challenge expired, start over with the password
+                if (error.status === 401)
+                    setChallenge(null)
+                authError(error)
             })
     }
 
-    const oauthSignIn = React.useCallback((provider: OAuthProvider) => {
+    const oauthSignIn = React.useCallback((provider: OAuthProvider | string) => {
         api.authentication.oauthLogin(provider, true, `${window.location.origin}/user/login`)
             .then(res => window.location.replace(res.url))
             .catch(error => {
@@ -173,15 +202,53 @@ const Login: NextPage = observer(() => {
                                     </Center>
                                 </Button>
                             }
+                            { appInfo?.oauthProviders.oidc.map(provider =>
+                                <Button 
+                                    key={provider.name}
+                                    w="full" 
+                                    variant="outline" 
+                                    onClick={() => oauthSignIn(provider.name)}
+                                >
+                                    <Center>
+                                        <Text>Sign in with {provider.displayName}</Text>
+                                    </Center>
+                                </Button>
+                            )}
                         </Stack>
-                        { Object.values(appInfo?.oauthProviders as any).some(v => v === true) && 
+                        { (Object.values(appInfo?.oauthProviders as any).some(v => v === true) || !!appInfo?.oauthProviders.oidc.length) && 
                             <Box className={styles.separator}>
                                 <Divider borderColor="white.500" />
                                 <chakra.span>or</chakra.span>
                                 <Divider borderColor="white.500" />
                             </Box> 
                         }
-                        <form onSubmit={handleSubmit(formSubmit as any)}>
+                        { challenge != null ? <form onSubmit={handleCodeSubmit(codeSubmit as any)}>
+                            <Stack spacing={5}>
+                                <FormControl>
+                                    <FormLabel>Two-factor code</FormLabel>
+                                    <Input
+                                        {...registerCode("code", { required: true })}
+                                        autoComplete="one-time-code"
+                                        autoFocus
+                                    />
+                                </FormControl>
+                                <Text fontSize="sm" color="gray.500">
+                                    Enter the code from your authenticator app or a recovery code
+                                </Text>
+                                <Button
+                                    bg="primary.500"
+                                    type="submit"
+                                    color="white"
+                                    _hover={{
+                                        bg: "primary.600"
+                                    }}>
+                                    Verify
+                                </Button>
+                                <Link textAlign="center" color="primary.300" onClick={() => setChallenge(null)}>
+                                    Back to sign in
+                                </Link>
+                            </Stack>
+                        </form> : <form onSubmit={handleSubmit(formSubmit as any)}>
                             <Stack spacing={5}>
                                 <Stack spacing={2}>
                                     <FormControl>
@@ -192,6 +259,11 @@ const Login: NextPage = observer(() => {
                                         <FormLabel>Password</FormLabel>
                                         <Input {...register("password", { required: true })} type="password" />
                                     </FormControl>
+                                    { appInfo?.smtp &&
+                                        <Text textAlign="right" fontSize="sm">
+                                            <RouterLink href="/user/reset"><Link color="primary.300">Forgot password?</Link></RouterLink>
+                                        </Text>
+                                    }
                                 </Stack>
                                 <Button
                                     bg="primary.500"
@@ -206,7 +278,7 @@ const Login: NextPage = observer(() => {
                                     Dont have an account? <RouterLink href="/user/create"><Link color="primary.300">Sign up</Link></RouterLink>
                                 </Text>
                             </Stack>
-                        </form>
+                        </form> }
                     </Box>
                 </Stack>
             </Flex>
@@ -0,0 +1,146 @@
+import * as React from "react"
+import { default as RouterLink } from "next/link"
+import { useForm } from "react-hook-form"
+import { Page } from "layouts/Page"
+
+import {
+    Flex,
+    Link,
+    Heading,
+    Stack,
+    useToast,
+    Text,
+    Box,
+    useColorModeValue,
+    FormControl,
+    FormLabel,
+    Input,
+    Button
+} from "@chakra-ui/react"
+
+import { NextPage } from "next"
+import { useRouter } from "next/router"
+import { ForgotPasswordForm } from "@/client"
+import api from "helpers/api"
+
+const PasswordReset: NextPage = () => {
+    const { register, handleSubmit } = useForm()
+    const toast = useToast()
+    const router = useRouter()
+
+    // The code is sent in the link of the password reset email
+    const { code } = router.query
+
+    const errorToast = (error: any) => toast({
+        title: "Error",
+        description: error.body.message,
+        status: "error",
+        duration: 5000,
+        isClosable: true
+    })
+
+    const requestSubmit = (data: ForgotPasswordForm) => {
+        api.user.forgotPassword(data)
+            .then(res => toast({
+                title: "Email sent",
+                description: res.message,
+                status: "success",
+                duration: 5000,
+                isClosable: true
+            }))
+            .catch(errorToast)
+    }
+
+    const resetSubmit = (data: { password: string, confirmPassword: string }) => {
+        if (data.password !== data.confirmPassword)
+            return toast({
+                title: "Error",
+                description: "Passwords do not match",
+                status: "error",
+                duration: 5000,
+                isClosable: true
+            })
+
+        api.user.resetPassword({ code: code as string, password: data.password })
+            .then(res => {
+                toast({
+                    title: "Password reset",
+                    description: res.message,
+                    status: "success",
+                    duration: 5000,
+                    isClosable: true
+                })
+                router.replace("/user/login")
+            })
+            .catch(errorToast)
+    }
+
+    return <Page title="Reset password">
+        <Flex
+            minH="100vh"
+            align="center"
+            justify="center">
+            <Stack spacing={8} mx="auto" maxW="lg" py={12} px={6}>
+                <Stack align="center" textAlign="center">
+                    <Heading fontSize="4xl">Reset your password</Heading>
+                    <Text fontSize="lg" color="gray.600">
+                        { code == null
+                            ? "We will send a reset link to your email"
+                            : "Choose a new password for your account" }
+                    </Text>
+                </Stack>
+                <Box
+                    rounded="lg"
+                    bg={useColorModeValue("white", "gray.700")}
+                    boxShadow="lg"
+                    w={["full", 400]}
+                    p={8}>
+                    { code == null ? <form onSubmit={handleSubmit(requestSubmit as any)}>
+                        <Stack spacing={5}>
+                            <FormControl isRequired>
+                                <FormLabel>Email</FormLabel>
+                                <Input type="email" {...register("email")} />
+                            </FormControl>
+                            <Button
+                                bg="primary.500"
+                                type="submit"
+                                color="white"
+                                _hover={{
+                                    bg: "primary.600"
+                                }}>
+                                Send reset link
+                            </Button>
+                        </Stack>
+                    </form> : <form onSubmit={handleSubmit(resetSubmit as any)}>
+                        <Stack spacing={5}>
+                            <Stack spacing={2}>
+                                <FormControl isRequired>
+                                    <FormLabel>New password</FormLabel>
+                                    <Input type="password" autoComplete="new-password" {...register("password")} />
+                                </FormControl>
+                                <FormControl isRequired>
+                                    <FormLabel>Confirm password</FormLabel>
+                                    <Input type="password" autoComplete="new-password" {...register("confirmPassword")} />
+                                </FormControl>
+                            </Stack>
+                            <Button
+                                bg="primary.500"
+                                type="submit"
+                                color="white"
+                                _hover={{
+                                    bg: "primary.600"
+                                }}>
+                                Reset password
+                            </Button>
+                        </Stack>
+                    </form> }
+                    <Text textAlign="center" mt={5}>
+                        Remembered it? <RouterLink href="/user/login"><Link color="primary.300">Sign in</Link></RouterLink>
+                    </Text>
+                </Box>
+            </Stack>
+        </Flex>
+    </Page>
+}
+
+export default PasswordReset
@@ -4,6 +4,23 @@ mod extensions;
 mod m20220101_000001_initial_structure;
 mod m20220810_114915_settings_table;
 mod m20220920_105037_auth_methods;
+mod m20221004_120000_blobs;
+mod m20221011_090000_file_visibility;
+mod m20221018_100000_file_expiry;
+mod m20221020_140000_storage_quotas;
+mod m20221024_093000_user_suspension;
+mod m20221027_120000_settings_toggles;
+mod m20221031_100000_application_scopes;
+mod m20221103_110000_application_tokens;
+mod m20221107_090000_sessions;
+mod m20221110_100000_two_factor;
+mod m20221114_100000_password_resets;
+mod m20221117_100000_oidc_providers;
+mod m20221121_100000_ldap;
+mod m20221124_100000_audit_log;
+mod m20221128_100000_webhooks;
+mod m20221201_100000_uploads;
+mod m20221205_100000_image_variants;
 
 pub struct Migrator;
 
@@ -14,6 +31,23 @@ impl MigratorTrait for Migrator {
             Box::new(m20220101_000001_initial_structure::Migration),
             Box::new(m20220810_114915_settings_table::Migration),
             Box::new(m20220920_105037_auth_methods::Migration),
+            Box::new(m20221004_120000_blobs::Migration),
+            Box::new(m20221011_090000_file_visibility::Migration),
+            Box::new(m20221018_100000_file_expiry::Migration),
+            Box::new(m20221020_140000_storage_quotas::Migration),
+            Box::new(m20221024_093000_user_suspension::Migration),
+            Box::new(m20221027_120000_settings_toggles::Migration),
+            Box::new(m20221031_100000_application_scopes::Migration),
+            Box::new(m20221103_110000_application_tokens::Migration),
+            Box::new(m20221107_090000_sessions::Migration),
+            Box::new(m20221110_100000_two_factor::Migration),
+            Box::new(m20221114_100000_password_resets::Migration),
+            Box::new(m20221117_100000_oidc_providers::Migration),
+            Box::new(m20221121_100000_ldap::Migration),
+            Box::new(m20221124_100000_audit_log::Migration),
+            Box::new(m20221128_100000_webhooks::Migration),
+            Box::new(m20221201_100000_uploads::Migration),
+            Box::new(m20221205_100000_image_variants::Migration),
         ]
     }
 }
@@ -0,0 +1,303 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{ConnectionTrait, DbBackend, Statement},
+};
+
+use crate::extensions::ColumnExtension;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Stored objects, identical files share a single blob.
+        manager
+            .create_table(
+                Table::create()
+                    .table(Blobs::Table)
+                    .col(
+                        ColumnDef::new(Blobs::Id)
+                            .sonyflake()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Blobs::Name)
+                            .string_len(32)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Blobs::Hash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Blobs::Size).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(Blobs::HasThumbnail)
+                            .boolean()
+                            .default(false)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Blobs::RefCount)
+                            .integer()
+                            .default(0)
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Objects of duplicates are no longer referenced once they point to the oldest object.
+        let duplicates = query_count(
+            manager,
+            "SELECT COUNT(*) AS count FROM files WHERE id NOT IN (SELECT MIN(id) FROM files GROUP BY hash)",
+        )
+        .await?;
+
+        if duplicates > 0 {
+            println!(
+                "{} duplicate files now share a blob, run `backpack --check-storage --repair` to delete their old objects",
+                duplicates
+            );
+        }
+
+        // Create a blob for every stored object.
+        // Files with the same hash could be uploaded by different users before this, duplicates are pointed to the oldest object.
+        for sql in [
+            r#"INSERT INTO blobs (id, name, hash, size, has_thumbnail, ref_count)
+                SELECT id, name, hash, size, has_thumbnail, 0 FROM files
+                WHERE id IN (SELECT MIN(id) FROM files GROUP BY hash)"#,
+            "UPDATE blobs SET ref_count = (SELECT COUNT(*) FROM files WHERE files.hash = blobs.hash)",
+            "UPDATE files SET name = (SELECT name FROM blobs WHERE blobs.hash = files.hash)",
+        ] {
+            manager
+                .get_connection()
+                .execute(Statement::from_string(
+                    manager.get_database_backend(),
+                    sql.to_owned(),
+                ))
+                .await?;
+        }
+
+        // File names are no longer unique since files can share a blob.
+        // Constraint names depend on how the table was created, so they are looked up.
+        match manager.get_database_backend() {
+            DbBackend::Postgres => {
+                for name in query_names(
+                    manager,
+                    r#"SELECT con.conname AS name FROM pg_constraint con
+                        JOIN pg_class rel ON rel.oid = con.conrelid
+                        JOIN pg_attribute att ON att.attrelid = con.conrelid AND att.attnum = ANY(con.conkey)
+                        WHERE rel.relname = 'files' AND pg_table_is_visible(rel.oid)
+                        AND con.contype = 'u' AND att.attname = 'name' AND cardinality(con.conkey) = 1"#,
+                )
+                .await?
+                {
+                    execute(
+                        manager,
+                        &format!("ALTER TABLE files DROP CONSTRAINT \"{}\"", name),
+                    )
+                    .await?;
+                }
+            }
+            DbBackend::MySql => {
+                for name in query_names(
+                    manager,
+                    r#"SELECT INDEX_NAME AS name FROM information_schema.STATISTICS
+                        WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'files' AND NON_UNIQUE = 0
+                        GROUP BY INDEX_NAME HAVING COUNT(*) = 1 AND MAX(COLUMN_NAME) = 'name'"#,
+                )
+                .await?
+                {
+                    execute(manager, &format!("ALTER TABLE files DROP INDEX `{}`", name)).await?;
+                }
+            }
+            DbBackend::Sqlite => {
+                // SQLite can't drop constraints, the table needs to be recreated without it.
+                manager
+                    .create_table(
+                        Table::create()
+                            .table(Alias::new("files_new"))
+                            .col(
+                                ColumnDef::new(Files::Id)
+                                    .sonyflake()
+                                    .primary_key()
+                                    .not_null(),
+                            )
+                            .col(ColumnDef::new(Files::Name).string_len(32).not_null())
+                            .col(
+                                ColumnDef::new(Files::OriginalName)
+                                    .string_len(256)
+                                    .not_null(),
+                            )
+                            .col(ColumnDef::new(Files::Uploader).sonyflake().not_null())
+                            .col(ColumnDef::new(Files::Hash).string_len(64).not_null())
+                            .col(
+                                ColumnDef::new(Files::Uploaded)
+                                    .timestamp_with_time_zone()
+                                    .not_null()
+                                    .extra("DEFAULT CURRENT_TIMESTAMP".into()),
+                            )
+                            .col(ColumnDef::new(Files::Size).big_integer().not_null())
+                            .col(
+                                ColumnDef::new(Files::HasThumbnail)
+                                    .boolean()
+                                    .default(false)
+                                    .not_null(),
+                            )
+                            .foreign_key(
+                                ForeignKey::create()
+                                    .from(Alias::new("files_new"), Files::Uploader)
+                                    .to(Users::Table, Users::Id)
+                                    .on_delete(ForeignKeyAction::Cascade),
+                            )
+                            .to_owned(),
+                    )
+                    .await?;
+
+                for sql in [
+                    r#"INSERT INTO files_new (id, name, original_name, uploader, hash, uploaded, size, has_thumbnail)
+                        SELECT id, name, original_name, uploader, hash, uploaded, size, has_thumbnail FROM files"#,
+                    "DROP TABLE files",
+                ] {
+                    manager
+                        .get_connection()
+                        .execute(Statement::from_string(
+                            manager.get_database_backend(),
+                            sql.to_owned(),
+                        ))
+                        .await?;
+                }
+
+                manager
+                    .rename_table(
+                        Table::rename()
+                            .table(Alias::new("files_new"), Files::Table)
+                            .to_owned(),
+                    )
+                    .await?;
+
+                // The index was dropped with the old table.
+                manager
+                    .create_index(
+                        Index::create()
+                            .unique()
+                            .name("files_user_hash_uindex")
+                            .table(Files::Table)
+                            .col(Files::Uploader)
+                            .col(Files::Hash)
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Files sharing a blob have the same name, which can't be unique again until they are deleted.
+        let shared = query_count(
+            manager,
+            "SELECT COUNT(*) AS count FROM (SELECT name FROM files GROUP BY name HAVING COUNT(*) > 1) shared",
+        )
+        .await?;
+
+        if shared > 0 {
+            return Err(DbErr::Custom(format!(
+                "{} blobs are shared by multiple files, delete the duplicates before reverting",
+                shared
+            )));
+        }
+
+        manager
+            .create_index(
+                Index::create()
+                    .unique()
+                    .name("files_name_key")
+                    .table(Files::Table)
+                    .col(Files::Name)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Blobs::Table).to_owned())
+            .await
+    }
+}
+
+async fn execute(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_owned(),
+        ))
+        .await?;
+
+    Ok(())
+}
+
+/// Run a query which selects a `count`.
+async fn query_count(manager: &SchemaManager<'_>, sql: &str) -> Result<i64, DbErr> {
+    match manager
+        .get_connection()
+        .query_one(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_owned(),
+        ))
+        .await?
+    {
+        Some(row) => row.try_get("", "count"),
+        None => Ok(0),
+    }
+}
+
+/// Run a query which selects a `name` column.
+async fn query_names(manager: &SchemaManager<'_>, sql: &str) -> Result<Vec<String>, DbErr> {
+    manager
+        .get_connection()
+        .query_all(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_owned(),
+        ))
+        .await?
+        .iter()
+        .map(|row| row.try_get("", "name"))
+        .collect()
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Files {
+    Table,
+    Id,
+    Name,
+    OriginalName,
+    HasThumbnail,
+    Uploader,
+    Hash,
+    Uploaded,
+    Size,
+}
+
+#[derive(Iden)]
+enum Blobs {
+    Table,
+    Id,
+    Name,
+    Hash,
+    Size,
+    HasThumbnail,
+    RefCount,
+}
@@ -0,0 +1,105 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() == DbBackend::Postgres {
+            manager
+                .create_type(
+                    Type::create()
+                        .as_enum(Visibility::Type)
+                        .values(vec![
+                            Visibility::Public,
+                            Visibility::Private,
+                            Visibility::Unlisted,
+                        ])
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // Existing files were all public.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .add_column(
+                        ColumnDef::new(Files::Visibility)
+                            .enumeration("visibility", ["public", "private", "unlisted"])
+                            .default("public")
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Whether the objects of a blob can be read directly from storage.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Blobs::Table)
+                    .add_column(
+                        ColumnDef::new(Blobs::Public)
+                            .boolean()
+                            .default(true)
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Blobs::Table)
+                    .drop_column(Blobs::Public)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .drop_column(Files::Visibility)
+                    .to_owned(),
+            )
+            .await?;
+
+        if manager.get_database_backend() == DbBackend::Postgres {
+            manager
+                .drop_type(Type::drop().name(Visibility::Type).to_owned())
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Files {
+    Table,
+    Visibility,
+}
+
+#[derive(Iden)]
+enum Blobs {
+    Table,
+    Public,
+}
+
+#[derive(Iden)]
+enum Visibility {
+    #[iden = "visibility"]
+    Type,
+    Public,
+    Private,
+    Unlisted,
+}
@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .add_column(
+                        ColumnDef::new(Settings::RegistrationOpen)
+                            .boolean()
+                            .default(true)
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Sizes are stored in bytes, a null quota is unlimited.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .add_column(ColumnDef::new(Settings::DefaultQuota).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Left empty so the server can seed it on startup,
+        // the initial limits come from the server config rather than the migration.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .add_column(ColumnDef::new(Settings::FileSizeLimit).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            Settings::RegistrationOpen,
+            Settings::DefaultQuota,
+            Settings::FileSizeLimit,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Settings::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Settings {
+    Table,
+    RegistrationOpen,
+    DefaultQuota,
+    FileSizeLimit,
+}
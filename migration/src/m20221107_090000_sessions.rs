@@ -0,0 +1,97 @@
+use sea_orm_migration::prelude::*;
+
+use crate::extensions::ColumnExtension;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Sessions::Table)
+                    .col(
+                        ColumnDef::new(Sessions::Id)
+                            .sonyflake()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Sessions::UserId).sonyflake().not_null())
+                    // SHA256 hash of the refresh token, the token itself is never stored.
+                    .col(
+                        ColumnDef::new(Sessions::RefreshToken)
+                            .string_len(64)
+                            .unique_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Sessions::Ip).string())
+                    .col(ColumnDef::new(Sessions::UserAgent).string())
+                    .col(
+                        ColumnDef::new(Sessions::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP".into()),
+                    )
+                    .col(
+                        ColumnDef::new(Sessions::LastAccessed)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP".into()),
+                    )
+                    .col(
+                        ColumnDef::new(Sessions::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Sessions::Table, Sessions::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("sessions_user_id_index")
+                    .table(Sessions::Table)
+                    .col(Sessions::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Sessions::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Sessions {
+    Table,
+    Id,
+    UserId,
+    RefreshToken,
+    Ip,
+    UserAgent,
+    Created,
+    LastAccessed,
+    ExpiresAt,
+}
@@ -0,0 +1,146 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend, sea_query::extension::postgres::Type};
+
+use crate::extensions::ColumnExtension;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DbBackend::Postgres => {
+                manager
+                    .alter_type(
+                        Type::alter()
+                            .name(AuthMethod::Type)
+                            .add_value(AuthMethod::Totp)
+                            .to_owned(),
+                    )
+                    .await?
+            }
+            DbBackend::MySql => {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(AuthMethods::Table)
+                            .modify_column(
+                                ColumnDef::new(AuthMethods::AuthMethod)
+                                    .enumeration(
+                                        "auth_method",
+                                        ["password", "google", "github", "discord", "totp"],
+                                    )
+                                    .not_null(),
+                            )
+                            .to_owned(),
+                    )
+                    .await?
+            }
+            // SQLite stores enums as text.
+            DbBackend::Sqlite => {}
+        }
+
+        // TOTP methods are only used once the user has confirmed a code.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AuthMethods::Table)
+                    .add_column(
+                        ColumnDef::new(AuthMethods::Confirmed)
+                            .boolean()
+                            .default(true)
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecoveryCodes::Table)
+                    .col(
+                        ColumnDef::new(RecoveryCodes::Id)
+                            .sonyflake()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(RecoveryCodes::UserId).sonyflake().not_null())
+                    // Argon2 hash of the code.
+                    .col(ColumnDef::new(RecoveryCodes::Code).text().not_null())
+                    .col(
+                        ColumnDef::new(RecoveryCodes::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP".into()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(RecoveryCodes::Table, RecoveryCodes::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("recovery_codes_user_id_index")
+                    .table(RecoveryCodes::Table)
+                    .col(RecoveryCodes::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RecoveryCodes::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AuthMethods::Table)
+                    .drop_column(AuthMethods::Confirmed)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Enum values can't be removed from Postgres types, existing TOTP methods are left unusable.
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum AuthMethods {
+    Table,
+    AuthMethod,
+    Confirmed,
+}
+
+#[derive(Iden)]
+enum AuthMethod {
+    #[iden = "auth_method"]
+    Type,
+    Totp,
+}
+
+#[derive(Iden)]
+enum RecoveryCodes {
+    Table,
+    Id,
+    UserId,
+    Code,
+    Created,
+}
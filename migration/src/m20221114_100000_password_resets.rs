@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+use crate::extensions::ColumnExtension;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PasswordResets::Table)
+                    .col(
+                        ColumnDef::new(PasswordResets::Id)
+                            .sonyflake()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    // A user can only have one reset code at a time.
+                    .col(
+                        ColumnDef::new(PasswordResets::UserId)
+                            .sonyflake()
+                            .unique_key()
+                            .not_null(),
+                    )
+                    // SHA256 hash of the code, the code itself is only sent by email.
+                    .col(
+                        ColumnDef::new(PasswordResets::Code)
+                            .string_len(64)
+                            .unique_key()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasswordResets::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP".into()),
+                    )
+                    .col(
+                        ColumnDef::new(PasswordResets::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(PasswordResets::Table, PasswordResets::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PasswordResets::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum PasswordResets {
+    Table,
+    Id,
+    UserId,
+    Code,
+    Created,
+    ExpiresAt,
+}
@@ -0,0 +1,130 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DbBackend::Postgres => {
+                manager
+                    .alter_type(
+                        Type::alter()
+                            .name(AuthMethod::Type)
+                            .add_value(AuthMethod::Oidc)
+                            .to_owned(),
+                    )
+                    .await?
+            }
+            DbBackend::MySql => {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(AuthMethods::Table)
+                            .modify_column(
+                                ColumnDef::new(AuthMethods::AuthMethod)
+                                    .enumeration(
+                                        "auth_method",
+                                        ["password", "google", "github", "discord", "totp", "oidc"],
+                                    )
+                                    .not_null(),
+                            )
+                            .to_owned(),
+                    )
+                    .await?
+            }
+            // SQLite stores enums as text.
+            DbBackend::Sqlite => {}
+        }
+
+        // Name of the OpenID Connect provider, this is empty for every other method.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AuthMethods::Table)
+                    .add_column(
+                        ColumnDef::new(AuthMethods::Provider)
+                            .string()
+                            .default("")
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // A user may link one account of every OpenID Connect provider.
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("auth_methods_uindex")
+                    .table(AuthMethods::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .unique()
+                    .name("auth_methods_uindex")
+                    .table(AuthMethods::Table)
+                    .col(AuthMethods::UserId)
+                    .col(AuthMethods::AuthMethod)
+                    .col(AuthMethods::Provider)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("auth_methods_uindex")
+                    .table(AuthMethods::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .unique()
+                    .name("auth_methods_uindex")
+                    .table(AuthMethods::Table)
+                    .col(AuthMethods::UserId)
+                    .col(AuthMethods::AuthMethod)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AuthMethods::Table)
+                    .drop_column(AuthMethods::Provider)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum AuthMethods {
+    Table,
+    UserId,
+    AuthMethod,
+    Provider,
+}
+
+#[derive(Iden)]
+enum AuthMethod {
+    #[iden = "auth_method"]
+    Type,
+    Oidc,
+}
@@ -0,0 +1,64 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DbBackend::Postgres => {
+                manager
+                    .alter_type(
+                        Type::alter()
+                            .name(AuthMethod::Type)
+                            .add_value(AuthMethod::Ldap)
+                            .to_owned(),
+                    )
+                    .await?
+            }
+            DbBackend::MySql => {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(AuthMethods::Table)
+                            .modify_column(
+                                ColumnDef::new(AuthMethods::AuthMethod)
+                                    .enumeration(
+                                        "auth_method",
+                                        [
+                                            "password", "google", "github", "discord", "totp",
+                                            "oidc", "ldap",
+                                        ],
+                                    )
+                                    .not_null(),
+                            )
+                            .to_owned(),
+                    )
+                    .await?
+            }
+            // SQLite stores enums as text.
+            DbBackend::Sqlite => {}
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // Postgres can't remove enum values.
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum AuthMethods {
+    Table,
+    AuthMethod,
+}
+
+#[derive(Iden)]
+enum AuthMethod {
+    #[iden = "auth_method"]
+    Type,
+    Ldap,
+}
@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+use crate::extensions::ColumnExtension;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Entries have no foreign keys, so they are kept after the users and resources they mention are deleted.
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditLog::Table)
+                    .col(
+                        ColumnDef::new(AuditLog::Id)
+                            .sonyflake()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditLog::Action).string_len(64).not_null())
+                    .col(ColumnDef::new(AuditLog::ActorId).sonyflake())
+                    .col(ColumnDef::new(AuditLog::TargetType).string_len(32))
+                    .col(ColumnDef::new(AuditLog::TargetId).string())
+                    .col(ColumnDef::new(AuditLog::Details).text())
+                    .col(ColumnDef::new(AuditLog::Ip).string())
+                    .col(
+                        ColumnDef::new(AuditLog::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP".into()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("audit_log_actor_id_index")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::ActorId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("audit_log_target_index")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::TargetType)
+                    .col(AuditLog::TargetId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditLog::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum AuditLog {
+    Table,
+    Id,
+    Action,
+    ActorId,
+    TargetType,
+    TargetId,
+    Details,
+    Ip,
+    Created,
+}
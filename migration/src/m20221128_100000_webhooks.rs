@@ -0,0 +1,192 @@
+use sea_orm_migration::prelude::*;
+
+use crate::extensions::ColumnExtension;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Webhooks::Table)
+                    .col(
+                        ColumnDef::new(Webhooks::Id)
+                            .sonyflake()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Webhooks::UserId).sonyflake().not_null())
+                    // Only events caused by this application are sent, all events of the user are sent if this is null.
+                    .col(ColumnDef::new(Webhooks::ApplicationId).sonyflake())
+                    .col(ColumnDef::new(Webhooks::Url).string().not_null())
+                    // Key used to sign deliveries.
+                    .col(ColumnDef::new(Webhooks::Secret).string_len(64).not_null())
+                    // Space separated list of events.
+                    .col(ColumnDef::new(Webhooks::Events).string().not_null())
+                    .col(
+                        ColumnDef::new(Webhooks::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP".into()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Webhooks::Table, Webhooks::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Webhooks::Table, Webhooks::ApplicationId)
+                            .to(Applications::Table, Applications::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("webhooks_user_id_index")
+                    .table(Webhooks::Table)
+                    .col(Webhooks::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookDeliveries::Table)
+                    .col(
+                        ColumnDef::new(WebhookDeliveries::Id)
+                            .sonyflake()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDeliveries::WebhookId)
+                            .sonyflake()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDeliveries::Event)
+                            .string_len(32)
+                            .not_null(),
+                    )
+                    // JSON body which is sent, this is kept so failed deliveries can be retried.
+                    .col(ColumnDef::new(WebhookDeliveries::Payload).text().not_null())
+                    .col(
+                        ColumnDef::new(WebhookDeliveries::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDeliveries::Delivered)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    // Response status of the last attempt.
+                    .col(ColumnDef::new(WebhookDeliveries::StatusCode).integer())
+                    // Error of the last attempt.
+                    .col(ColumnDef::new(WebhookDeliveries::Error).text())
+                    .col(
+                        ColumnDef::new(WebhookDeliveries::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP".into()),
+                    )
+                    // Null once the delivery succeeded or ran out of attempts.
+                    .col(
+                        ColumnDef::new(WebhookDeliveries::NextAttemptAt).timestamp_with_time_zone(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(WebhookDeliveries::Table, WebhookDeliveries::WebhookId)
+                            .to(Webhooks::Table, Webhooks::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("webhook_deliveries_webhook_id_index")
+                    .table(WebhookDeliveries::Table)
+                    .col(WebhookDeliveries::WebhookId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("webhook_deliveries_next_attempt_at_index")
+                    .table(WebhookDeliveries::Table)
+                    .col(WebhookDeliveries::NextAttemptAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebhookDeliveries::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Webhooks::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Applications {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Webhooks {
+    Table,
+    Id,
+    UserId,
+    ApplicationId,
+    Url,
+    Secret,
+    Events,
+    Created,
+}
+
+#[derive(Iden)]
+enum WebhookDeliveries {
+    Table,
+    Id,
+    WebhookId,
+    Event,
+    Payload,
+    Attempts,
+    Delivered,
+    StatusCode,
+    Error,
+    Created,
+    NextAttemptAt,
+}
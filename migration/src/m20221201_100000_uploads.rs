@@ -0,0 +1,117 @@
+use sea_orm_migration::prelude::*;
+
+use crate::extensions::ColumnExtension;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Uploads::Table)
+                    .col(
+                        ColumnDef::new(Uploads::Id)
+                            .sonyflake()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Uploads::UserId).sonyflake().not_null())
+                    // Application which created the upload, used for webhook events.
+                    .col(ColumnDef::new(Uploads::ApplicationId).sonyflake())
+                    .col(ColumnDef::new(Uploads::Filename).string().not_null())
+                    .col(
+                        ColumnDef::new(Uploads::Visibility)
+                            .enumeration("visibility", ["public", "private", "unlisted"])
+                            .default("public")
+                            .not_null(),
+                    )
+                    // Seconds until the file expires once the upload is complete.
+                    .col(ColumnDef::new(Uploads::Expiration).big_integer())
+                    // Total size declared when the upload was created.
+                    .col(ColumnDef::new(Uploads::Length).big_integer().not_null())
+                    // Bytes received so far.
+                    .col(
+                        ColumnDef::new(Uploads::Offset)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(Uploads::Created)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .extra("DEFAULT CURRENT_TIMESTAMP".into()),
+                    )
+                    // Unfinished uploads are removed after this.
+                    .col(
+                        ColumnDef::new(Uploads::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Uploads::Table, Uploads::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Uploads::Table, Uploads::ApplicationId)
+                            .to(Applications::Table, Applications::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("uploads_expires_at_index")
+                    .table(Uploads::Table)
+                    .col(Uploads::ExpiresAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Uploads::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Applications {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Uploads {
+    Table,
+    Id,
+    UserId,
+    ApplicationId,
+    Filename,
+    Visibility,
+    Expiration,
+    Length,
+    Offset,
+    Created,
+    ExpiresAt,
+}
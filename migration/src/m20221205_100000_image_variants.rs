@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Space separated names of the resized copies which are stored for a blob.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Blobs::Table)
+                    .add_column(
+                        ColumnDef::new(Blobs::Variants)
+                            .string()
+                            .default("")
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Copied from the blob like `has_thumbnail`.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .add_column(
+                        ColumnDef::new(Files::Variants)
+                            .string()
+                            .default("")
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .drop_column(Files::Variants)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Blobs::Table)
+                    .drop_column(Blobs::Variants)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Blobs {
+    Table,
+    Variants,
+}
+
+#[derive(Iden)]
+enum Files {
+    Table,
+    Variants,
+}
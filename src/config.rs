@@ -1,8 +1,10 @@
 use dotenv::dotenv;
+use ipnet::IpNet;
 use rusoto_core::Region;
 use std::{
     env,
     fmt::Debug,
+    net::IpAddr,
     path::{Path, PathBuf},
     str::FromStr,
 };
@@ -16,14 +18,31 @@ pub struct Config {
     pub database_url: String,
     pub worker_id: u16,
     pub jwt_key: String,
-    pub file_size_limit: usize,
+    pub file_signing_key: String,
+    pub expiry_sweep_interval: u64,
+    /// Initial file size limit in megabytes, admins can change it in the server settings.
+    pub file_size_limit: i64,
+    /// Initial default storage quota in megabytes, 0 is unlimited.
+    pub storage_quota: i64,
+    /// Directory where resumable uploads are stored until they are complete.
+    pub upload_temp_path: PathBuf,
     pub storage_provider: StorageConfig,
+    /// Storage provider objects are copied from with `--migrate-storage`.
+    pub migration_source: Option<StorageConfig>,
+    /// Processing of uploaded images, uploads are stored unchanged if this is not set.
+    pub image_processing: Option<ImageConfig>,
     pub smtp_config: Option<SMTPConfig>,
     pub invite_only: bool,
+    /// Proxies allowed to set the client IP with `X-Forwarded-For`.
+    pub trusted_proxies: Vec<IpNet>,
+    /// Allow webhooks to be sent to private, loopback and link-local addresses.
+    pub webhook_allow_local: bool,
     pub run_migrations: bool,
     pub google_oauth: Option<OAuthConfig>,
     pub github_oauth: Option<OAuthConfig>,
     pub discord_oauth: Option<OAuthConfig>,
+    pub oidc_providers: Vec<OidcConfig>,
+    pub ldap: Option<LdapConfig>,
 }
 
 #[derive(Clone)]
@@ -32,6 +51,47 @@ pub struct OAuthConfig {
     pub client_secret: String,
 }
 
+#[derive(Clone)]
+pub struct OidcConfig {
+    /// Used in URLs and to link accounts, this should never change.
+    pub name: String,
+    /// Name shown to users.
+    pub display_name: String,
+    /// Issuer URL, the discovery document is read from `/.well-known/openid-configuration`.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scopes: Vec<String>,
+    /// Claim used as the username.
+    pub username_claim: String,
+    /// Claim used as the email.
+    pub email_claim: String,
+    /// Treat emails as verified when the provider doesn't include `email_verified`.
+    pub trust_email: bool,
+}
+
+#[derive(Clone)]
+pub struct LdapConfig {
+    /// Server URL, `ldap://` or `ldaps://`.
+    pub url: String,
+    /// Upgrade `ldap://` connections with StartTLS.
+    pub starttls: bool,
+    /// Account used to search for users, the search is anonymous if this is not set.
+    pub bind_dn: Option<String>,
+    pub bind_password: Option<String>,
+    /// Users are searched for below this DN.
+    pub base_dn: String,
+    /// Search filter, `{username}` is replaced by the escaped username or email used to log in.
+    pub user_filter: String,
+    pub username_attribute: String,
+    pub email_attribute: String,
+    /// Attribute holding the DNs of groups the user is a member of.
+    pub group_attribute: String,
+    /// Members of this group are admins.
+    /// Roles of directory users are not changed if this is not set.
+    pub admin_group: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct S3Config {
     pub bucket: String,
@@ -47,6 +107,38 @@ pub struct LocalConfig {
     // Should local provider directory be served by the application
     // This can be disable if someone wants to serve using some other webserver
     pub serve: bool,
+
+    // Store objects in hash-prefixed subdirectories instead of a single directory
+    pub sharded: bool,
+}
+
+#[derive(Clone)]
+pub struct WebDavConfig {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct ImageConfig {
+    /// Remove EXIF and text metadata, only the orientation is kept.
+    pub strip_metadata: bool,
+    /// Format images are converted to, images keep their format if this is not set.
+    pub format: Option<ImageFormat>,
+    /// Quality of converted images and variants from 1 to 100.
+    pub quality: u8,
+    /// Names and largest dimension of the resized copies created for every image.
+    pub variants: Vec<(String, u32)>,
+    /// Largest image in bytes which can be uploaded, images are held in memory while they are processed.
+    pub max_size: usize,
+    /// Largest width or height of images which are decoded.
+    pub max_dimension: u32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    WebP,
+    Avif,
 }
 
 #[derive(Clone)]
@@ -60,6 +152,7 @@ pub struct SMTPConfig {
 pub enum StorageConfig {
     Local(LocalConfig),
     S3(S3Config),
+    WebDav(WebDavConfig),
 }
 
 impl Config {
@@ -70,34 +163,44 @@ impl Config {
             storage_url: get_env("STORAGE_URL"),
             database_url: get_env("DATABASE_URL"),
             jwt_key: get_env("JWT_KEY"),
+            file_signing_key: match get_env_or("FILE_SIGNING_KEY", String::new()) {
+                key if key.is_empty() => get_env("JWT_KEY"),
+                key => key,
+            },
             api_url: get_env("API_URL"),
             client_url: get_env("CLIENT_URL"),
+            expiry_sweep_interval: get_env_or("EXPIRY_SWEEP_INTERVAL", 60),
             file_size_limit: get_env_or("FILE_SIZE_LIMIT", 100),
+            storage_quota: get_env_or("STORAGE_QUOTA", 0),
+            upload_temp_path: match get_env_or("UPLOAD_TEMP_PATH", String::new()) {
+                path if path.is_empty() => env::temp_dir().join("backpack-uploads"),
+                path => PathBuf::from(path),
+            },
             worker_id: get_env::<u16>("WORKER_ID"),
             invite_only: get_env_or("INVITE_ONLY", false),
+            trusted_proxies: get_env_or("TRUSTED_PROXIES", String::new())
+                .split(',')
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(|v| match v.parse::<IpNet>() {
+                    Ok(net) => net,
+                    // Single addresses are accepted without a prefix length.
+                    Err(_) => IpNet::from(
+                        v.parse::<IpAddr>()
+                            .expect(&format!("Unable to parse {} in TRUSTED_PROXIES", v)),
+                    ),
+                })
+                .collect(),
+            webhook_allow_local: get_env_or("WEBHOOK_ALLOW_LOCAL", false),
             run_migrations: get_env_or("RUN_MIGRATIONS", true),
-            storage_provider: {
-                match get_env::<String>("STORAGE_PROVIDER").as_str() {
-                    "local" => StorageConfig::Local(LocalConfig {
-                        path: Path::new(
-                            get_env_or::<String>("LOCAL_PATH", "./uploads".to_string()).as_str(),
-                        )
-                        .to_path_buf(),
-                        serve: get_env_or("LOCAL_SERVE", true),
-                    }),
-                    "s3" => StorageConfig::S3(S3Config {
-                        bucket: get_env("S3_BUCKET"),
-                        access_key: get_env("S3_ACCESS_KEY"),
-                        secret_key: get_env("S3_SECRET_KEY"),
-                        region: Region::Custom {
-                            name: get_env("S3_REGION"),
-                            endpoint: get_env("S3_ENDPOINT"),
-                        },
-                    }),
-                    _ => {
-                        panic!("Invalid storage provider for environment variable STORAGE_PROVIDER")
-                    }
-                }
+            storage_provider: storage_config(""),
+            migration_source: match env::var("MIGRATE_FROM_STORAGE_PROVIDER") {
+                Ok(_) => Some(storage_config("MIGRATE_FROM_")),
+                Err(_) => None,
+            },
+            image_processing: match get_env_or("IMAGE_PROCESSING_ENABLED", false) {
+                true => Some(image_config()),
+                false => None,
             },
             smtp_config: {
                 match get_env_or("SMTP_ENABLED", false) {
@@ -136,6 +239,184 @@ impl Config {
                     false => None,
                 }
             },
+            oidc_providers: get_env_or("OIDC_PROVIDERS", String::new())
+                .split(',')
+                .map(|name| name.trim().to_lowercase())
+                .filter(|name| !name.is_empty())
+                .map(|name| oidc_config(&name))
+                .collect(),
+            ldap: {
+                match get_env_or("LDAP_ENABLED", false) {
+                    true => Some(LdapConfig {
+                        url: get_env("LDAP_URL"),
+                        starttls: get_env_or("LDAP_STARTTLS", false),
+                        bind_dn: match get_env_or("LDAP_BIND_DN", String::new()) {
+                            bind_dn if bind_dn.is_empty() => None,
+                            bind_dn => Some(bind_dn),
+                        },
+                        bind_password: match get_env_or("LDAP_BIND_PASSWORD", String::new()) {
+                            password if password.is_empty() => None,
+                            password => Some(password),
+                        },
+                        base_dn: get_env("LDAP_BASE_DN"),
+                        user_filter: get_env_or(
+                            "LDAP_USER_FILTER",
+                            "(|(uid={username})(mail={username}))".to_owned(),
+                        ),
+                        username_attribute: get_env_or("LDAP_USERNAME_ATTRIBUTE", "uid".to_owned()),
+                        email_attribute: get_env_or("LDAP_EMAIL_ATTRIBUTE", "mail".to_owned()),
+                        group_attribute: get_env_or("LDAP_GROUP_ATTRIBUTE", "memberOf".to_owned()),
+                        admin_group: match get_env_or("LDAP_ADMIN_GROUP", String::new()) {
+                            group if group.is_empty() => None,
+                            group => Some(group),
+                        },
+                    }),
+                    false => None,
+                }
+            },
+        }
+    }
+}
+
+/// Read the configuration of a named OpenID Connect provider.
+/// Every environment variable is prefixed by `OIDC_{NAME}_`.
+fn oidc_config(name: &str) -> OidcConfig {
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        || ["google", "github", "discord"].contains(&name)
+    {
+        panic!(
+            "Invalid OpenID Connect provider name {} in OIDC_PROVIDERS",
+            name
+        );
+    }
+
+    let prefix = format!("OIDC_{}_", name.to_uppercase().replace('-', "_"));
+
+    OidcConfig {
+        name: name.to_owned(),
+        display_name: get_env_or(&format!("{}DISPLAY_NAME", prefix), name.to_owned()),
+        issuer: get_env::<String>(&format!("{}ISSUER", prefix))
+            .trim_end_matches('/')
+            .to_owned(),
+        client_id: get_env(&format!("{}CLIENT_ID", prefix)),
+        client_secret: get_env(&format!("{}CLIENT_SECRET", prefix)),
+        scopes: get_env_or(
+            &format!("{}SCOPES", prefix),
+            "openid email profile".to_owned(),
+        )
+        .split_whitespace()
+        .map(|scope| scope.to_owned())
+        .collect(),
+        username_claim: get_env_or(
+            &format!("{}USERNAME_CLAIM", prefix),
+            "preferred_username".to_owned(),
+        ),
+        email_claim: get_env_or(&format!("{}EMAIL_CLAIM", prefix), "email".to_owned()),
+        trust_email: get_env_or(&format!("{}TRUST_EMAIL", prefix), false),
+    }
+}
+
+/// Read the image processing configuration.
+fn image_config() -> ImageConfig {
+    let variants = get_env_or(
+        "IMAGE_VARIANTS",
+        "small:320,medium:800,large:1600".to_owned(),
+    )
+    .split(',')
+    .map(str::trim)
+    .filter(|variant| !variant.is_empty())
+    .map(|variant| {
+        let (name, size) = variant.split_once(':').expect(&format!(
+            "Invalid image variant {} in IMAGE_VARIANTS",
+            variant
+        ));
+
+        // Names are part of object keys.
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        {
+            panic!("Invalid image variant name {} in IMAGE_VARIANTS", name);
+        }
+
+        let size = match size.parse::<u32>() {
+            Ok(size) if size > 0 => size,
+            _ => panic!("Invalid image variant size {} in IMAGE_VARIANTS", size),
+        };
+
+        (name.to_owned(), size)
+    })
+    .collect::<Vec<(String, u32)>>();
+
+    for (i, (name, _)) in variants.iter().enumerate() {
+        if variants[..i].iter().any(|(other, _)| other == name) {
+            panic!("Duplicate image variant {} in IMAGE_VARIANTS", name);
+        }
+    }
+
+    ImageConfig {
+        strip_metadata: get_env_or("IMAGE_STRIP_METADATA", true),
+        format: match get_env_or("IMAGE_FORMAT", String::new()).as_str() {
+            "" => None,
+            "webp" => Some(ImageFormat::WebP),
+            "avif" if cfg!(feature = "avif") => Some(ImageFormat::Avif),
+            "avif" => panic!("IMAGE_FORMAT avif requires building with the avif feature"),
+            _ => panic!("Invalid image format for environment variable IMAGE_FORMAT"),
+        },
+        quality: match get_env_or::<u8>("IMAGE_QUALITY", 80) {
+            quality @ 1..=100 => quality,
+            _ => panic!("IMAGE_QUALITY must be between 1 and 100"),
+        },
+        variants,
+        max_size: get_env_or::<usize>("IMAGE_MAX_SIZE", 20) * 1000 * 1000,
+        max_dimension: match get_env_or::<u32>("IMAGE_MAX_DIMENSION", 10000) {
+            0 => panic!("IMAGE_MAX_DIMENSION must be larger than 0"),
+            dimension => dimension,
+        },
+    }
+}
+
+/// Read a storage provider configuration.
+/// Every environment variable is prefixed by `prefix`, so more than one provider can be configured.
+fn storage_config(prefix: &str) -> StorageConfig {
+    match get_env::<String>(&format!("{}STORAGE_PROVIDER", prefix)).as_str() {
+        provider @ ("local" | "local_sharded") => StorageConfig::Local(LocalConfig {
+            path: Path::new(
+                get_env_or::<String>(&format!("{}LOCAL_PATH", prefix), "./uploads".to_string())
+                    .as_str(),
+            )
+            .to_path_buf(),
+            serve: get_env_or(&format!("{}LOCAL_SERVE", prefix), true),
+            sharded: provider == "local_sharded",
+        }),
+        "s3" => StorageConfig::S3(S3Config {
+            bucket: get_env(&format!("{}S3_BUCKET", prefix)),
+            access_key: get_env(&format!("{}S3_ACCESS_KEY", prefix)),
+            secret_key: get_env(&format!("{}S3_SECRET_KEY", prefix)),
+            region: Region::Custom {
+                name: get_env(&format!("{}S3_REGION", prefix)),
+                endpoint: get_env(&format!("{}S3_ENDPOINT", prefix)),
+            },
+        }),
+        "webdav" => StorageConfig::WebDav(WebDavConfig {
+            url: get_env(&format!("{}WEBDAV_URL", prefix)),
+            username: match get_env_or(&format!("{}WEBDAV_USERNAME", prefix), String::new()) {
+                username if username.is_empty() => None,
+                username => Some(username),
+            },
+            password: match get_env_or(&format!("{}WEBDAV_PASSWORD", prefix), String::new()) {
+                password if password.is_empty() => None,
+                password => Some(password),
+            },
+        }),
+        _ => {
+            panic!(
+                "Invalid storage provider for environment variable {}STORAGE_PROVIDER",
+                prefix
+            )
         }
     }
 }
@@ -13,6 +13,12 @@ pub struct Model {
     pub name: String,
     pub last_accessed: DateTimeUtc,
     pub created: DateTimeUtc,
+    pub default_expiration: Option<i64>,
+    pub scopes: String,
+    pub token_version: i32,
+    pub expires_at: Option<DateTimeUtc>,
+    pub last_ip: Option<String>,
+    pub last_user_agent: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
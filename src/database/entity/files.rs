@@ -1,5 +1,6 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.9.1
 
+use super::sea_orm_active_enums::Visibility;
 use sea_orm::{entity::prelude::*, Set};
 
 use super::DB_SONYFLAKE;
@@ -9,7 +10,6 @@ use super::DB_SONYFLAKE;
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: String,
-    #[sea_orm(unique)]
     pub name: String,
     pub original_name: String,
     pub uploader: String,
@@ -17,6 +17,9 @@ pub struct Model {
     pub uploaded: DateTimeWithTimeZone,
     pub size: i64,
     pub has_thumbnail: bool,
+    pub variants: String,
+    pub visibility: Visibility,
+    pub expires_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -2,16 +2,26 @@
 
 use crate::database::sonyflake::Sonyflake;
 
+// Entities are used through their modules, the generated prelude is kept for reference.
+#[allow(unused_imports)]
 pub mod prelude;
 
 pub mod applications;
+pub mod audit_log;
 pub mod auth_methods;
+pub mod blobs;
 pub mod files;
+pub mod password_resets;
+pub mod recovery_codes;
 pub mod registration_keys;
 pub mod sea_orm_active_enums;
+pub mod sessions;
 pub mod settings;
+pub mod uploads;
 pub mod users;
 pub mod verifications;
+pub mod webhook_deliveries;
+pub mod webhooks;
 
 lazy_static! {
     pub static ref DB_SONYFLAKE: Sonyflake =
@@ -37,8 +37,14 @@ pub enum AuthMethod {
     Github,
     #[sea_orm(string_value = "google")]
     Google,
+    #[sea_orm(string_value = "ldap")]
+    Ldap,
+    #[sea_orm(string_value = "oidc")]
+    Oidc,
     #[sea_orm(string_value = "password")]
     Password,
+    #[sea_orm(string_value = "totp")]
+    Totp,
 }
 
 #[derive(Debug, Clone, PartialEq, EnumIter, DeriveActiveEnum)]
@@ -49,3 +55,14 @@ pub enum Role {
     #[sea_orm(string_value = "user")]
     User,
 }
+
+#[derive(Debug, Clone, PartialEq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "visibility")]
+pub enum Visibility {
+    #[sea_orm(string_value = "private")]
+    Private,
+    #[sea_orm(string_value = "public")]
+    Public,
+    #[sea_orm(string_value = "unlisted")]
+    Unlisted,
+}
@@ -12,6 +12,9 @@ pub struct Model {
     #[sea_orm(column_type = "Text")]
     pub app_description: String,
     pub color: ThemeColor,
+    pub registration_open: bool,
+    pub default_quota: Option<i64>,
+    pub file_size_limit: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
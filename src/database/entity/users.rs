@@ -18,6 +18,9 @@ pub struct Model {
     pub verified: bool,
     pub role: Role,
     pub registered: bool,
+    pub default_expiration: Option<i64>,
+    pub storage_quota: Option<i64>,
+    pub suspended: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -30,6 +33,16 @@ pub enum Relation {
     Verifications,
     #[sea_orm(has_many = "super::auth_methods::Entity")]
     AuthMethods,
+    #[sea_orm(has_many = "super::sessions::Entity")]
+    Sessions,
+    #[sea_orm(has_many = "super::recovery_codes::Entity")]
+    RecoveryCodes,
+    #[sea_orm(has_one = "super::password_resets::Entity")]
+    PasswordResets,
+    #[sea_orm(has_many = "super::webhooks::Entity")]
+    Webhooks,
+    #[sea_orm(has_many = "super::uploads::Entity")]
+    Uploads,
 }
 
 impl Related<super::applications::Entity> for Entity {
@@ -56,6 +69,36 @@ impl Related<super::auth_methods::Entity> for Entity {
     }
 }
 
+impl Related<super::sessions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Sessions.def()
+    }
+}
+
+impl Related<super::recovery_codes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecoveryCodes.def()
+    }
+}
+
+impl Related<super::password_resets::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasswordResets.def()
+    }
+}
+
+impl Related<super::webhooks::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Webhooks.def()
+    }
+}
+
+impl Related<super::uploads::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Uploads.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {
     fn new() -> Self {
         Self {
@@ -7,9 +7,11 @@ use utoipa::OpenApi;
 
 use crate::models::*;
 
+use crate::models::admin::file::StorageReport;
 use crate::models::admin::registration_key::RegistrationKeyData;
+use crate::models::admin::settings::{SettingsData, UpdateSettings};
+use crate::models::admin::user::{StorageQuotaForm, UserRoleForm, UserSuspensionForm};
 use crate::routes;
-use crate::services::auth::oauth::OAuthProvider;
 
 /// Backpack API Documentation
 #[derive(OpenApi)]
@@ -18,31 +20,73 @@ use crate::services::auth::oauth::OAuthProvider;
         routes::info,
         routes::user::info,
         routes::user::settings,
+        routes::user::retention,
         routes::user::create,
         routes::user::verify,
         routes::user::resend_verify,
+        routes::user::forgot_password,
+        routes::user::reset_password,
         routes::user::delete,
         routes::user::register_key,
+        routes::user::audit,
         routes::file::upload,
         routes::file::stats,
         routes::file::list,
+        routes::file::content,
+        routes::file::link,
+        routes::file::set_visibility,
         routes::file::info,
         routes::file::delete_file,
         routes::file::delete_files,
+        routes::upload::discover,
+        routes::upload::create,
+        routes::upload::upload_offset,
+        routes::upload::append,
         routes::application::token,
+        routes::application::rotate,
         routes::application::list,
         routes::application::info,
         routes::application::create,
         routes::application::delete,
+        routes::application::retention,
+        routes::application::scopes,
+        routes::application::uploader,
+        routes::webhook::list,
+        routes::webhook::info,
+        routes::webhook::create,
+        routes::webhook::delete,
+        routes::webhook::test,
+        routes::webhook::deliveries,
         routes::admin::registration_key::create,
         routes::admin::registration_key::list,
         routes::admin::registration_key::get_one,
         routes::admin::registration_key::delete,
+        routes::admin::file::check,
+        routes::admin::settings::info,
+        routes::admin::settings::update,
+        routes::admin::user::list,
+        routes::admin::user::info,
+        routes::admin::user::stats,
+        routes::admin::user::role,
+        routes::admin::user::verify,
+        routes::admin::user::suspend,
+        routes::admin::user::quota,
+        routes::admin::user::delete,
+        routes::admin::audit_log::list,
         routes::auth::basic,
         routes::auth::oauth_login,
         routes::auth::oauth_callback,
         routes::auth::enabled_methods,
         routes::auth::unlink_method,
+        routes::auth::two_factor,
+        routes::auth::totp_enroll,
+        routes::auth::totp_confirm,
+        routes::auth::totp_disable,
+        routes::auth::recovery_codes,
+        routes::auth::refresh,
+        routes::auth::sessions,
+        routes::auth::revoke_session,
+        routes::auth::revoke_sessions,
     ),
     components(
         schemas(
@@ -53,32 +97,66 @@ use crate::services::auth::oauth::OAuthProvider;
             UpdateUserSettings,
             UserCreateForm,
             UserDeleteForm,
+            ForgotPasswordForm,
+            PasswordResetForm,
             UploadFile,
             UploadConflict,
             FileData,
             FileStats,
             FilePage,
+            FileVisibility,
+            FileVisibilityForm,
+            FileLink,
+            RetentionPolicy,
             ApplicationData,
             TokenResponse,
             ApplicationCreate,
+            ApplicationScope,
+            ApplicationScopesForm,
+            UploaderTool,
             BasicAuthForm,
             OAuthRequest,
             RegistrationKeyData,
+            SettingsData,
+            StorageReport,
+            UpdateSettings,
+            StorageQuotaForm,
+            UserRoleForm,
+            UserSuspensionForm,
+            UserPage,
             BatchDeleteRequest,
             BatchDeleteResponse,
             BatchFileError,
             OAuthProviders,
+            OidcProviderInfo,
             AuthMethods,
             UnlinkAuthMethod,
-            OAuthProvider,
-            LoginRedirectUrl
+            RefreshTokenForm,
+            SessionData,
+            TwoFactorChallenge,
+            TwoFactorLoginForm,
+            TotpEnrollment,
+            TotpCodeForm,
+            RecoveryCodes,
+            LoginRedirectUrl,
+            AuditAction,
+            AuditLogData,
+            AuditLogPage,
+            WebhookEvent,
+            WebhookData,
+            WebhookCreate,
+            WebhookDeliveryData,
+            WebhookPage,
+            WebhookDeliveryPage
         )
     ),
     tags(
         (name = "server", description = "Server information endpoints."),
         (name = "user", description = "User management endpoints."),
         (name = "file", description = "File management endpoints."),
+        (name = "upload", description = "Resumable upload endpoints using the tus protocol."),
         (name = "application", description = "Application and token management endpoints."),
+        (name = "webhook", description = "Webhook management endpoints."),
         (name = "authentication", description = "User authentication endpoints."),
         (name = "admin", description = "Server administration endpoints."),
     ),
@@ -5,11 +5,12 @@
 //! https://github.com/rust-lang/rust/issues/84737
 
 use actix_web::{web::Data, Error, FromRequest, HttpRequest};
-use std::ops::Deref;
+use ipnet::IpNet;
+use std::{net::IpAddr, ops::Deref};
 
 use crate::{
-    database::entity::users,
-    models::UserRole,
+    database::entity::{applications, users},
+    models::{ApplicationScope, UserRole},
     services::{auth::AuthService, ServiceError},
 };
 
@@ -54,24 +55,54 @@ macro_rules! define_option {
 }
 
 define_option!(VerifiedOpt, AllowUnverified, DenyUnverified);
-define_option!(ApplicationOpt, AllowApplication, DenyApplication);
 define_option!(RegisteredOpt, AllowUnregistered, DenyUnregistered);
 
+/// Application token option which can be used in generic parameters.
+/// Application tokens are only allowed if they have [`ApplicationOpt::SCOPE`].
+pub trait ApplicationOpt {
+    const SCOPE: Option<ApplicationScope>;
+}
+
+pub struct DenyApplication;
+impl ApplicationOpt for DenyApplication {
+    const SCOPE: Option<ApplicationScope> = None;
+}
+
+macro_rules! define_scope {
+    ($name:ident, $variant:expr) => {
+        pub struct $name;
+        impl $crate::internal::auth::ApplicationOpt for $name {
+            const SCOPE: Option<$crate::models::application::ApplicationScope> = Some($variant);
+        }
+    };
+}
+
+// Define all application scopes
+pub mod app_scope {
+    use crate::models::application::ApplicationScope;
+
+    define_scope!(FileUpload, ApplicationScope::FileUpload);
+    define_scope!(FileRead, ApplicationScope::FileRead);
+    define_scope!(FileWrite, ApplicationScope::FileWrite);
+    define_scope!(FileDelete, ApplicationScope::FileDelete);
+    define_scope!(UserRead, ApplicationScope::UserRead);
+}
+
 /// Actix parameter based middleware for authentication with options.
 ///
 /// # Arguments
 ///
 /// * `R` - The users role. Greater roles in the underlying enum of [`auth_role`] will access to lower role access level.
 /// * `VOpt` - Allow the user to be unverified. This is one of [`AllowUnverified`] or [`DenyVerified`]. This is deny by default.
-/// * `AOpt` - Allow the token to be from an application. This is one of [`AllowApplication`] or [`DenyApplication`]. This is deny by default.
+/// * `AOpt` - Allow the token to be from an application with a scope. This is one of [`app_scope`] or [`DenyApplication`]. This is deny by default.
 ///
 /// # Examples
 ///
 /// ```
 /// async fn route(
-///     user: Auth<auth_role::User, AllowUnverified, AllowApplication>
+///     user: Auth<auth_role::User, AllowUnverified, app_scope::FileRead>
 /// ) -> Response<impl Responder> {
-///     "This will permit the user to be unverified and for the token to be an application token."
+///     "This will permit the user to be unverified and for the token to be an application token with the file:read scope."
 /// }
 /// ```
 pub struct Auth<
@@ -81,6 +112,10 @@ pub struct Auth<
     ROpt: RegisteredOpt = DenyUnregistered,
 > {
     pub user: users::Model,
+    /// Application the token belongs to, if it is an application token.
+    pub application: Option<applications::Model>,
+    /// Session the token belongs to, if it is a login token.
+    pub session_id: Option<String>,
     _markers: (
         std::marker::PhantomData<R>,
         std::marker::PhantomData<VOpt>,
@@ -116,23 +151,38 @@ impl<R: Role, VOpt: VerifiedOpt, AOpt: ApplicationOpt, ROpt: RegisteredOpt> From
 
             let jwt_token = get_token(&req).ok_or(Error::from(ServiceError::unauthorized()))?;
 
-            let (user, application) = match auth_service
-                .validate_jwt(VOpt::ALLOW as bool, &jwt_token)
+            let (user, application, scopes, session_id) = match auth_service
+                .validate_jwt(
+                    VOpt::ALLOW as bool,
+                    &jwt_token,
+                    &ClientInfo::from_request(&req),
+                )
                 .await
             {
                 Ok(v) => v,
                 Err(e) => return Err(Error::from(e)),
             };
 
-            if (application.is_some() && !AOpt::ALLOW)
+            if (application.is_some() && AOpt::SCOPE.is_none())
                 || (UserRole::from(user.role.clone()) < R::LEVEL)
                 || !user.registered && !(ROpt::ALLOW as bool)
             {
                 return Err(Error::from(ServiceError::unauthorized()));
             }
 
+            if let Some(scope) = AOpt::SCOPE {
+                if application.is_some() && !scopes.contains(&scope) {
+                    return Err(Error::from(ServiceError::Unauthorized(format!(
+                        "This application token does not have the {} scope",
+                        scope
+                    ))));
+                }
+            }
+
             Ok(Auth {
                 user,
+                application,
+                session_id,
                 _markers: (
                     std::marker::PhantomData,
                     std::marker::PhantomData,
@@ -144,6 +194,70 @@ impl<R: Role, VOpt: VerifiedOpt, AOpt: ApplicationOpt, ROpt: RegisteredOpt> From
     }
 }
 
+/// Proxies which are trusted to set the client IP with `X-Forwarded-For`.
+pub struct TrustedProxies(pub Vec<IpNet>);
+
+impl TrustedProxies {
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(ip))
+    }
+}
+
+/// Information about the client making a request.
+#[derive(Clone, Default)]
+pub struct ClientInfo {
+    /// IP address of the client.
+    /// Proxy headers are only used when the request was forwarded by a trusted proxy.
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        Self {
+            ip: client_ip(req).map(|v| v.to_string()),
+            user_agent: req
+                .headers()
+                .get("User-Agent")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_owned()),
+        }
+    }
+}
+
+/// Get the IP address of the client.
+/// `X-Forwarded-For` is read from the right since proxies append to it,
+/// the first address which isn't a trusted proxy is the client.
+fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let mut ip = req.peer_addr()?.ip();
+
+    let proxies = match req.app_data::<Data<TrustedProxies>>() {
+        Some(proxies) if proxies.contains(&ip) => proxies,
+        _ => return Some(ip),
+    };
+
+    let forwarded: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .collect();
+
+    for address in forwarded.into_iter().rev() {
+        match address.trim().parse::<IpAddr>() {
+            Ok(address) => {
+                ip = address;
+                if !proxies.contains(&ip) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+
+    Some(ip)
+}
+
 pub fn get_token(req: &HttpRequest) -> Option<String> {
     match req.headers().get("Authorization") {
         Some(header) => match header.to_str() {
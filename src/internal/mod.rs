@@ -2,7 +2,8 @@ use git_version::git_version;
 use rand::Rng;
 
 pub mod auth;
-pub mod file;
+pub mod signature;
+pub mod totp;
 
 pub const GIT_VERSION: &str = git_version!();
 
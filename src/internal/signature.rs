@@ -0,0 +1,120 @@
+//! HMAC signatures for links which expire.
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs and verifies expiring links.
+///
+/// A signature covers a subject (such as an object key) and the unix timestamp it expires at.
+#[derive(Clone)]
+pub struct Signer {
+    key: Vec<u8>,
+}
+
+impl Signer {
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.as_bytes().to_vec(),
+        }
+    }
+
+    /// Sign a subject until the expiry timestamp.
+    ///
+    /// Returns the hex encoded signature.
+    pub fn sign(&self, subject: &str, expires: i64) -> String {
+        format!("{:x}", self.mac(subject, expires).finalize().into_bytes())
+    }
+
+    /// Verify a signature for a subject.
+    /// Expired signatures are always invalid.
+    pub fn verify(&self, subject: &str, expires: i64, signature: &str) -> bool {
+        if expires < Utc::now().timestamp() {
+            return false;
+        }
+
+        let signature = match decode_hex(signature) {
+            Some(v) => v,
+            None => return false,
+        };
+
+        // Compared in constant time.
+        self.mac(subject, expires).verify_slice(&signature).is_ok()
+    }
+
+    fn mac(&self, subject: &str, expires: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC can take key of any size");
+        mac.update(format!("{}:{}", subject, expires).as_bytes());
+        mac
+    }
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> Signer {
+        Signer::new("secret")
+    }
+
+    fn in_an_hour() -> i64 {
+        Utc::now().timestamp() + 3600
+    }
+
+    #[test]
+    fn accepts_valid_signature() {
+        let expires = in_an_hour();
+        let signature = signer().sign("object", expires);
+
+        assert!(signer().verify("object", expires, &signature));
+    }
+
+    #[test]
+    fn rejects_expired_link() {
+        let expires = Utc::now().timestamp() - 1;
+        let signature = signer().sign("object", expires);
+
+        assert!(!signer().verify("object", expires, &signature));
+    }
+
+    #[test]
+    fn rejects_tampered_subject() {
+        let expires = in_an_hour();
+        let signature = signer().sign("object", expires);
+
+        assert!(!signer().verify("other", expires, &signature));
+    }
+
+    #[test]
+    fn rejects_tampered_expiry() {
+        let expires = in_an_hour();
+        let signature = signer().sign("object", expires);
+
+        assert!(!signer().verify("object", expires + 1, &signature));
+    }
+
+    #[test]
+    fn rejects_tampered_signature() {
+        let expires = in_an_hour();
+        let mut signature = signer().sign("object", expires);
+        let last = if signature.ends_with('0') { "1" } else { "0" };
+        signature.replace_range(signature.len() - 1.., last);
+
+        assert!(!signer().verify("object", expires, &signature));
+        assert!(!signer().verify("object", expires, "not hex"));
+        assert!(!Signer::new("other").verify("object", expires, &signer().sign("object", expires)));
+    }
+}
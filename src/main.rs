@@ -1,37 +1,49 @@
 use crate::{
-    database::entity::files,
+    database::entity::blobs,
     docs::ApiDoc,
-    internal::GIT_VERSION,
+    internal::{auth::TrustedProxies, GIT_VERSION},
     services::{
         application::ApplicationService,
-        auth::{auth_method::AuthMethodService, AuthService},
-        file::FileService,
+        audit_log::AuditLogService,
+        auth::{
+            auth_method::AuthMethodService, session::SessionService, two_factor::TwoFactorService,
+            AuthService,
+        },
+        file::{
+            new_storage, object_path, shard_objects, variant_keys, FileService, StorageProvider,
+            UploadDigest,
+        },
+        rate_limit::{MemoryStore, RateLimitService},
         registration_key::RegistrationKeyService,
+        settings::SettingsService,
+        upload::UploadService,
         user::UserService,
+        webhook::WebhookService,
     },
 };
-use actix_multipart_extract::MultipartConfig;
 use clap::Parser;
 use colored::*;
 use config::StorageConfig;
 use figlet_rs::FIGfont;
+use futures::{StreamExt, TryStreamExt};
 use indicatif::{ProgressBar, ProgressStyle};
-use models::MessageResponse;
+use models::{ContentQuery, MessageResponse};
 use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait, Statement};
-use std::sync::{Arc, RwLock};
-use tokio::{
-    runtime::Builder,
-    sync::mpsc::{unbounded_channel, UnboundedSender},
+use std::{
+    collections::HashSet,
+    sync::{Arc, RwLock},
 };
-
-use internal::file::IMAGE_EXTS;
+use tokio::io::AsyncWriteExt;
 use utoipa::OpenApi;
 
 use migration::{Migrator, MigratorTrait};
-use std::{convert::TryInto, ffi::OsStr, path::Path};
+use std::{convert::TryInto, path::Path};
 
 use actix_web::{
-    http::StatusCode,
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
     middleware::Logger,
     web::{self, Data},
     App, HttpRequest, HttpResponse, HttpServer,
@@ -58,9 +70,33 @@ mod services;
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    /// Regenerate image thumbnails
+    /// Regenerate thumbnails and image variants, this also creates thumbnails for files supported by newly installed tools
     #[clap(short, long, takes_value = false)]
     generate_thumbnails: bool,
+
+    /// Move local objects from the flat layout into the sharded layout
+    #[clap(long, takes_value = false)]
+    shard_local_storage: bool,
+
+    /// Copy all objects from the MIGRATE_FROM_ storage provider to the configured storage provider
+    #[clap(long, takes_value = false)]
+    migrate_storage: bool,
+
+    /// File which records copied objects so an interrupted storage migration can be resumed
+    #[clap(long, default_value = "storage-migration.progress")]
+    migration_progress: String,
+
+    /// Check that stored objects match the database
+    #[clap(long, takes_value = false)]
+    check_storage: bool,
+
+    /// Repair problems found by --check-storage, the server should be stopped first
+    #[clap(long, takes_value = false, requires = "check-storage")]
+    repair: bool,
+
+    /// Verify the hash of every object with --check-storage
+    #[clap(long, takes_value = false, requires = "check-storage")]
+    verify_hashes: bool,
 }
 
 #[tokio::main]
@@ -99,9 +135,38 @@ async fn main() -> std::io::Result<()> {
     // Get setting as single boolean before client gets moved
     let invite_only = config.invite_only;
 
+    // Proxies which can set the client IP.
+    let trusted_proxies = Data::new(TrustedProxies(config.trusted_proxies.clone()));
+
+    // Settings service.
+    let settings_service = Data::new(SettingsService::new(database.clone().into_inner()));
+
+    // Limits used to be set in the config, they are only used as initial values now.
+    settings_service
+        .seed_limits(
+            config.file_size_limit * 1000 * 1000,
+            match config.storage_quota {
+                0 => None,
+                quota => Some(quota * 1000 * 1000),
+            },
+        )
+        .await
+        .expect("Unable to seed server settings");
+
+    // Audit log service.
+    let audit_log_service = Data::new(AuditLogService::new(database.clone().into_inner()));
+
     // Registration key service.
-    let registration_key_service =
-        Data::new(RegistrationKeyService::new(database.clone().into_inner()));
+    let registration_key_service = Data::new(RegistrationKeyService::new(
+        database.clone().into_inner(),
+        audit_log_service.clone().into_inner(),
+    ));
+
+    // Webhook service.
+    let webhook_service = Data::new(WebhookService::new(
+        database.clone().into_inner(),
+        config.webhook_allow_local,
+    ));
 
     // File service.
     let file_service = Data::new(
@@ -109,12 +174,37 @@ async fn main() -> std::io::Result<()> {
             database.clone().into_inner(),
             config.storage_provider.clone(),
             &config.storage_url,
-            config.file_size_limit,
+            settings_service.clone().into_inner(),
+            webhook_service.clone().into_inner(),
+            &config.file_signing_key,
+            config.image_processing.clone(),
         )
         .await,
     );
 
-    let auth_method_service = Data::new(AuthMethodService::new(database.clone().into_inner()));
+    // Upload service.
+    let upload_service = Data::new(UploadService::new(
+        database.clone().into_inner(),
+        file_service.clone().into_inner(),
+        settings_service.clone().into_inner(),
+        config.upload_temp_path.clone(),
+    ));
+
+    // Rate limits are kept in memory, so they are only enforced per instance.
+    let rate_limit_service = Data::new(RateLimitService::new(Box::new(MemoryStore::new())));
+
+    let auth_method_service = Data::new(AuthMethodService::new(
+        database.clone().into_inner(),
+        rate_limit_service.clone().into_inner(),
+        audit_log_service.clone().into_inner(),
+    ));
+    let session_service = Data::new(SessionService::new(database.clone().into_inner()));
+    let two_factor_service = Data::new(TwoFactorService::new(
+        database.clone().into_inner(),
+        auth_method_service.clone().into_inner(),
+        settings_service.clone().into_inner(),
+        rate_limit_service.clone().into_inner(),
+    ));
 
     // User service.
     let user_service = Data::new(UserService::new(
@@ -122,6 +212,9 @@ async fn main() -> std::io::Result<()> {
         registration_key_service.clone().into_inner(),
         file_service.clone().into_inner(),
         auth_method_service.clone().into_inner(),
+        session_service.clone().into_inner(),
+        rate_limit_service.clone().into_inner(),
+        webhook_service.clone().into_inner(),
         config.smtp_config,
         &config.client_url,
         config.invite_only,
@@ -132,7 +225,11 @@ async fn main() -> std::io::Result<()> {
     // Auth service.
     let auth_service = Data::new(AuthService::new(
         auth_method_service.clone().into_inner(),
+        session_service.clone().into_inner(),
+        two_factor_service.clone().into_inner(),
         user_service.clone().into_inner(),
+        rate_limit_service.clone().into_inner(),
+        audit_log_service.clone().into_inner(),
         application_service_container.clone(),
         &config.api_url,
         &config.jwt_key,
@@ -140,12 +237,17 @@ async fn main() -> std::io::Result<()> {
         config.google_oauth,
         config.github_oauth,
         config.discord_oauth,
+        config.oidc_providers,
+        config.ldap,
     ));
 
     // Application service.
     let application_service = Data::new(ApplicationService::new(
         database.clone().into_inner(),
         auth_service.clone().into_inner(),
+        audit_log_service.clone().into_inner(),
+        webhook_service.clone().into_inner(),
+        &config.api_url,
     ));
 
     application_service_container
@@ -155,14 +257,125 @@ async fn main() -> std::io::Result<()> {
 
     // If the generate thumbnails flag is enabled
     if args.generate_thumbnails {
-        generate_thumbnails(&database, &file_service).await.unwrap();
+        generate_thumbnails(&database, &file_service)
+            .await
+            .map_err(|e| cli_error("Unable to generate thumbnails", e))?;
+        return Ok(());
+    }
+
+    // If the shard local storage flag is enabled
+    if args.shard_local_storage {
+        match &config.storage_provider {
+            StorageConfig::Local(v) => {
+                log::info!("Moving local objects into the sharded layout");
+                let moved = shard_objects(&v.path)
+                    .await
+                    .map_err(|e| cli_error("Unable to shard local storage", e))?;
+                log::info!("Moved {} objects", moved.to_string().yellow());
+            }
+            _ => log::error!("Only local storage can be sharded"),
+        }
         return Ok(());
     }
 
+    // If the migrate storage flag is enabled
+    if args.migrate_storage {
+        match config.migration_source.clone() {
+            Some(source) => {
+                let source = new_storage(source).await;
+                migrate_storage(
+                    &database,
+                    source.as_ref(),
+                    file_service.storage.as_ref(),
+                    Path::new(&args.migration_progress),
+                )
+                .await
+                .map_err(|e| cli_error("Unable to migrate storage", e))?;
+            }
+            None => log::error!("MIGRATE_FROM_STORAGE_PROVIDER must be set to migrate storage"),
+        }
+        return Ok(());
+    }
+
+    // If the check storage flag is enabled
+    if args.check_storage {
+        log::info!("Checking storage");
+        let report = file_service
+            .check_storage(args.repair, args.verify_hashes)
+            .await
+            .map_err(|e| cli_error("Unable to check storage", e.into()))?;
+
+        log::info!(
+            "Checked {} objects, {} blobs and {} files",
+            report.objects.to_string().yellow(),
+            report.blobs.to_string().yellow(),
+            report.files.to_string().yellow()
+        );
+
+        for (problem, items) in [
+            ("Blobs without objects", &report.missing_objects),
+            ("Files without objects", &report.missing_files),
+            ("Objects without blobs", &report.orphaned_objects),
+            ("Hash mismatches", &report.hash_mismatches),
+            ("Missing thumbnails", &report.missing_thumbnails),
+        ] {
+            if !items.is_empty() {
+                log::warn!("{} ({}):\n{}", problem, items.len(), items.join("\n"));
+            }
+        }
+
+        if report.repaired {
+            log::info!("Repaired storage, hash mismatches can't be repaired");
+        }
+
+        return Ok(());
+    }
+
+    // Delete expired files, sessions and uploads in the background.
+    let sweeper_file_service = file_service.clone();
+    let sweeper_session_service = session_service.clone();
+    let sweeper_upload_service = upload_service.clone();
+    let sweep_interval = std::time::Duration::from_secs(config.expiry_sweep_interval.max(1));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(sweep_interval);
+
+        loop {
+            interval.tick().await;
+
+            match sweeper_file_service.delete_expired().await {
+                Ok(0) => {}
+                Ok(count) => log::info!("Deleted {} expired files", count),
+                Err(err) => log::error!("Failed to delete expired files: {}", err),
+            }
+
+            if let Err(err) = sweeper_session_service.delete_expired().await {
+                log::error!("Failed to delete expired sessions: {}", err);
+            }
+
+            if let Err(err) = sweeper_upload_service.delete_expired().await {
+                log::error!("Failed to delete expired uploads: {}", err);
+            }
+        }
+    });
+
+    // Retry failed webhook deliveries in the background.
+    let retry_webhook_service = webhook_service.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(10));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = retry_webhook_service.retry_pending().await {
+                log::error!("Failed to retry webhook deliveries: {}", err);
+            }
+        }
+    });
+
     let storage_path = match &config.storage_provider {
         StorageConfig::Local(v) => {
             if v.serve {
-                Some(v.path.clone())
+                Some((v.path.clone(), v.sharded))
             } else {
                 None
             }
@@ -179,6 +392,7 @@ async fn main() -> std::io::Result<()> {
 
     HttpServer::new(move || {
         let base_storage_path = storage_path.clone();
+        let base_file_service = file_service.clone();
         App::new()
             .wrap(Logger::default())
             .wrap(
@@ -187,15 +401,33 @@ async fn main() -> std::io::Result<()> {
                     .allow_any_header()
                     .allow_any_method()
                     .supports_credentials()
+                    // Headers used by resumable uploads.
+                    .expose_headers(vec![
+                        "Location",
+                        "Tus-Resumable",
+                        "Tus-Version",
+                        "Tus-Extension",
+                        "Tus-Max-Size",
+                        "Upload-Offset",
+                        "Upload-Length",
+                        "X-Backpack-File-Id",
+                    ])
                     .max_age(None),
             )
             .app_data(database.clone())
+            .app_data(settings_service.clone())
             .app_data(registration_key_service.clone())
             .app_data(user_service.clone())
             .app_data(file_service.clone())
             .app_data(auth_service.clone())
             .app_data(application_service.clone())
             .app_data(auth_method_service.clone())
+            .app_data(session_service.clone())
+            .app_data(two_factor_service.clone())
+            .app_data(audit_log_service.clone())
+            .app_data(webhook_service.clone())
+            .app_data(upload_service.clone())
+            .app_data(trusted_proxies.clone())
             .route(
                 "/api/docs/openapi.json",
                 web::get().to(|| async { ApiDoc::openapi().to_pretty_json() }),
@@ -210,7 +442,9 @@ async fn main() -> std::io::Result<()> {
                     .service(routes::user::get_routes())
                     .service(routes::auth::get_routes())
                     .service(routes::application::get_routes())
+                    .service(routes::webhook::get_routes())
                     .service(routes::file::get_routes())
+                    .service(routes::upload::get_routes())
                     .service(routes::admin::get_routes(invite_only))
                     .service(routes::get_routes()),
             )
@@ -218,16 +452,11 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::JsonConfig::default().error_handler(|_, _| {
                 actix_web::Error::from(models::MessageResponse::bad_request())
             }))
-            .app_data(
-                MultipartConfig::default()
-                    .set_error_handler(|_| models::MessageResponse::bad_request().http_response()),
-            )
             .default_service(web::to(move |req: HttpRequest| {
                 let storage_path = base_storage_path.clone();
+                let file_service = base_file_service.clone();
                 async move {
-                    if let Some(v) = &storage_path {
-                        let mut file_path = v.clone();
-
+                    if let Some((root, sharded)) = &storage_path {
                         // Request path after the root
                         let path_end = req.path().trim_start_matches('/');
 
@@ -235,9 +464,42 @@ async fn main() -> std::io::Result<()> {
                         // This would attempt to send the directory (and fail) otherwise
                         if !path_end.eq("") {
                             // Sanitize the path to prevent walking to another directory
-                            file_path.push(path_end.replace("..", ""));
-                            if let Ok(v) = NamedFile::open(&file_path) {
-                                return v.into_response(&req);
+                            let key = path_end.replace("..", "");
+
+                            // Private objects require a signed link
+                            let query =
+                                web::Query::<ContentQuery>::from_query(req.query_string()).ok();
+                            let allowed = file_service
+                                .can_read_object(
+                                    &key,
+                                    query.as_ref().and_then(|q| q.expires),
+                                    query.as_ref().and_then(|q| q.signature.as_deref()),
+                                )
+                                .await
+                                .unwrap_or(false);
+
+                            if allowed {
+                                let file_path = object_path(root, &key, *sharded).await;
+                                if let Ok(mut v) = NamedFile::open(&file_path) {
+                                    // Thumbnails are PNG images, whatever the extension of the file is.
+                                    if key.starts_with("thumb/") {
+                                        v = v.set_content_type(mime::IMAGE_PNG);
+                                    }
+
+                                    // Browsers must not guess another type or run anything in uploaded content.
+                                    let mut response = v.into_response(&req);
+                                    let headers = response.headers_mut();
+                                    headers.insert(
+                                        header::X_CONTENT_TYPE_OPTIONS,
+                                        HeaderValue::from_static("nosniff"),
+                                    );
+                                    headers.insert(
+                                        header::CONTENT_SECURITY_POLICY,
+                                        HeaderValue::from_static("sandbox"),
+                                    );
+
+                                    return response;
+                                }
                             }
                         }
                     }
@@ -252,6 +514,14 @@ async fn main() -> std::io::Result<()> {
     .await
 }
 
+/// Error returned from `main` when a CLI command fails.
+fn cli_error(context: &str, error: anyhow::Error) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Other,
+        format!("{}: {:#}", context, error),
+    )
+}
+
 /// Get database version.
 async fn get_db_version(database: &DatabaseConnection) -> Result<String, anyhow::Error> {
     let version: String = database
@@ -278,47 +548,28 @@ async fn get_db_version(database: &DatabaseConnection) -> Result<String, anyhow:
 }
 
 /// TODO: Move this to admin panel with a websocket.
-/// Regenerate all thumbnails.
-/// This is a multithreaded blocking operation used in the CLI.
+/// Regenerate all thumbnails and image variants.
+/// This is a blocking operation used in the CLI.
 async fn generate_thumbnails(
     database: &Arc<DatabaseConnection>,
     file_service: &Arc<FileService>,
 ) -> anyhow::Result<()> {
-    log::info!("Regenerating image thumbnails");
-
-    let files = files::Entity::find().all(database.as_ref()).await?;
-
-    // Get every file which is an image or has an image extension.
-    let image_files: Vec<files::Model> = files
-        .iter()
-        .filter(|file| {
-            let extension = Path::new(&file.name)
-                .extension()
-                .and_then(OsStr::to_str)
-                .unwrap_or("");
-
-            IMAGE_EXTS
-                .into_iter()
-                .any(|ext| ext.eq(&extension.to_uppercase()))
-        })
-        .map(|v| v.clone())
-        .collect();
+    log::info!("Regenerating thumbnails and image variants");
+
+    // Files with identical content share a blob, so only blobs need thumbnails.
+    // Every blob is checked since thumbnailers select files by their contents.
+    let blobs = blobs::Entity::find().all(database.as_ref()).await?;
 
-    // Make a seperate runtime for spawning blocking operations so that hundreds of threads aren't created.
-    let runtime = Builder::new_multi_thread()
-        .worker_threads(1) // We only need blocking threads
-        .max_blocking_threads(num_cpus::get() / 2)
-        .thread_name("backpack-thumbnail-generator")
-        .build()
-        .unwrap();
+    // Thumbnails are created on blocking threads or by external tools, so only a few are created at once.
+    let concurrency = (num_cpus::get() / 2).max(1);
 
     log::info!(
         "{} files to generate with {} threads",
-        image_files.len().to_string().yellow(),
-        (num_cpus::get() / 2).to_string().yellow()
+        blobs.len().to_string().yellow(),
+        concurrency.to_string().yellow()
     );
 
-    let progress = ProgressBar::new(image_files.len().try_into().unwrap());
+    let progress = ProgressBar::new(blobs.len().try_into().unwrap());
     progress.set_style(
         ProgressStyle::default_bar()
             .template(
@@ -328,103 +579,188 @@ async fn generate_thumbnails(
             .progress_chars("##-"),
     );
 
-    let (tx, mut rx) = unbounded_channel();
-
-    for file in image_files {
-        let extension = Path::new(&file.name)
-            .extension()
-            .and_then(OsStr::to_str)
-            .unwrap_or("");
-
-        if IMAGE_EXTS
-            .into_iter()
-            .any(|ext| ext.eq(&extension.to_uppercase()))
-        {
+    let mut tasks = futures::stream::iter(blobs)
+        .map(|blob| {
             let file_service = file_service.clone();
-            let task_tx: UnboundedSender<Result<String, (String, String)>> = tx.clone();
-            let spawner = runtime.handle().clone();
-            tokio::spawn(async move {
-                match file_service.storage.get_object(&file.name).await {
-                    Ok(buf) => {
-                        // Open a new task on the custom tokio runtime.
-                        let resized = spawner
-                            .spawn_blocking(move || internal::file::get_thumbnail_image(&buf))
-                            .await
-                            .unwrap();
-
-                        match resized {
-                            Ok(v) => {
-                                // Write thumbnail object to storage.
-                                if let Err(err) = file_service
-                                    .storage
-                                    .put_object(&format!("thumb/{}", file.name), &v)
-                                    .await
-                                {
-                                    task_tx
-                                        .send(Err((
-                                            file.name.to_owned(),
-                                            format!("Error putting {}: {}", file.name, err),
-                                        )))
-                                        .unwrap();
-                                }
-                            }
-                            Err(e) => {
-                                task_tx.send(Err((file.name, e.to_string()))).unwrap();
-                                return;
-                            }
-                        };
-                    }
-                    Err(err) => {
-                        task_tx
-                            .send(Err((
-                                file.name.to_owned(),
-                                format!("Error getting {}: {}", file.name, err),
-                            )))
-                            .unwrap();
-                    }
-                }
+            async move {
+                let result = file_service.regenerate_previews(&blob).await;
+                (blob.name, result)
+            }
+        })
+        .buffer_unordered(concurrency);
 
-                // Send completion status of image.
-                task_tx.send(Ok(file.name)).unwrap();
-            });
+    // All errors produced while generating thumbnails.
+    let mut errors = vec![];
+
+    while let Some((name, result)) = tasks.next().await {
+        if let Err(err) = result {
+            errors.push(format!("{}: {}", name, err));
         }
+
+        progress.set_message(name);
+        progress.inc(1);
     }
 
-    // All errors produced while generating images.
-    let mut errors = vec![];
+    progress.finish_with_message("Finished generating thumbnails");
+
+    // If there were any errors then we should log them.
+    if errors.len() > 0 {
+        log::warn!(
+            "Completed with {} errors:\n{}",
+            errors.len(),
+            errors.join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+/// Copy every stored object from one storage provider to another.
+/// This is a blocking operation used in the CLI.
+///
+/// Each copied object is read back from the destination and verified against the blob hash.
+/// Copied objects are appended to the progress file and skipped if the migration is run again.
+async fn migrate_storage(
+    database: &Arc<DatabaseConnection>,
+    source: &dyn StorageProvider,
+    destination: &dyn StorageProvider,
+    progress_path: &Path,
+) -> anyhow::Result<()> {
+    log::info!("Migrating storage");
 
-    // Wait for all tasks to finish and handle completions.
-    while let Some(message) = rx.recv().await {
-        match message {
-            Ok(name) => progress.set_message(name),
-            Err((name, error)) => {
-                progress.set_message(name.to_owned());
-                errors.push(format!("{}: {}", name, error))
+    // Objects which were copied by an earlier run.
+    let completed: HashSet<String> = match tokio::fs::read_to_string(progress_path).await {
+        Ok(v) => v.lines().map(|line| line.to_owned()).collect(),
+        Err(_) => HashSet::new(),
+    };
+
+    // Every blob with its thumbnail and variants, which don't have a stored hash.
+    let objects: Vec<(String, Option<String>, bool)> = blobs::Entity::find()
+        .all(database.as_ref())
+        .await?
+        .into_iter()
+        .flat_map(|blob| {
+            let mut objects = vec![(blob.name.to_owned(), Some(blob.hash), blob.public)];
+            if blob.has_thumbnail {
+                objects.push((format!("thumb/{}", blob.name), None, blob.public));
             }
-        };
+            for key in variant_keys(&blob.name, &blob.variants) {
+                objects.push((key, None, blob.public));
+            }
+            objects
+        })
+        .filter(|(key, _, _)| !completed.contains(key))
+        .collect();
 
-        progress.inc(1);
+    log::info!(
+        "{} objects to copy, {} already copied",
+        objects.len().to_string().yellow(),
+        completed.len().to_string().yellow()
+    );
+
+    let progress = ProgressBar::new(objects.len().try_into()?);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>2}/{len:2} {msg}",
+            )?
+            .progress_chars("##-"),
+    );
+
+    let mut progress_file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(progress_path)
+        .await?;
 
-        // Drop the channel receiver and exit once everything has been recieved.
-        if progress.position() == progress.length().unwrap() {
-            drop(tx);
-            break;
+    let mut copies =
+        futures::stream::iter(objects.into_iter().map(|(key, hash, public)| async move {
+            let result = copy_object(source, destination, &key, hash.as_deref(), public).await;
+            (key, result)
+        }))
+        .buffer_unordered(8);
+
+    // All errors produced while copying objects.
+    let mut errors = vec![];
+
+    while let Some((key, result)) = copies.next().await {
+        match result {
+            Ok(_) => {
+                progress_file
+                    .write_all(format!("{}\n", key).as_bytes())
+                    .await?;
+            }
+            Err(err) => errors.push(format!("{}: {}", key, err)),
         }
+
+        progress.set_message(key);
+        progress.inc(1);
     }
 
-    progress.finish_with_message("Finished generating thumbnails");
+    progress_file.flush().await?;
+    progress.finish_with_message("Finished migrating storage");
 
     // If there were any errors then we should log them.
     if errors.len() > 0 {
         log::warn!(
-            "Completed with {} errors:\n{}",
+            "Completed with {} errors, run the migration again to retry them:\n{}",
             errors.len(),
             errors.join("\n")
         );
     }
 
-    // Shutdown the temporary runtime for this operation.
-    runtime.shutdown_background();
-
     Ok(())
 }
+
+/// Copy a single object and verify the copy.
+///
+/// # Arguments
+///
+/// * `key` - Object key.
+/// * `hash` - Expected SHA-256 hash of the object, if known.
+/// * `public` - Can the object be read directly from the destination.
+async fn copy_object(
+    source: &dyn StorageProvider,
+    destination: &dyn StorageProvider,
+    key: &str,
+    hash: Option<&str>,
+    public: bool,
+) -> anyhow::Result<()> {
+    let (stream, digest) =
+        UploadDigest::wrap(source.get_object_stream(key, None).await?, usize::MAX);
+    destination.put_object_stream(key, stream).await?;
+
+    let source_hash = digest
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Source digest lock was poisoned"))?
+        .hash();
+    if let Some(hash) = hash {
+        if source_hash != hash {
+            // The copy is removed so it isn't mistaken for a verified object.
+            destination.delete_objects(vec![key.to_owned()]).await?;
+            return Err(anyhow::anyhow!(
+                "Source object does not match the stored hash"
+            ));
+        }
+    }
+
+    // Read the object back to make sure it was stored correctly.
+    let (stream, digest) =
+        UploadDigest::wrap(destination.get_object_stream(key, None).await?, usize::MAX);
+    stream.try_for_each(|_| async { Ok(()) }).await?;
+
+    let copied_hash = digest
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Destination digest lock was poisoned"))?
+        .hash();
+    if copied_hash != source_hash {
+        destination.delete_objects(vec![key.to_owned()]).await?;
+        return Err(anyhow::anyhow!(
+            "Copied object does not match the source object"
+        ));
+    }
+
+    destination
+        .set_object_access(vec![key.to_owned()], public)
+        .await
+}
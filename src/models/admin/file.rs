@@ -1,5 +1,5 @@
-use serde::Deserialize;
-use utoipa::IntoParams;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Deserialize, IntoParams)]
 pub struct FileQuery {
@@ -8,3 +8,35 @@ pub struct FileQuery {
     /// File uploader ID
     pub user: Option<String>,
 }
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageCheckQuery {
+    /// Read every object to verify its hash, this can take a long time
+    pub verify_hashes: Option<bool>,
+}
+
+/// Problems found while comparing storage to the database.
+#[derive(Serialize, ToSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageReport {
+    /// Amount of objects in storage, including thumbnails.
+    pub objects: usize,
+    /// Amount of blobs in the database.
+    pub blobs: usize,
+    /// Amount of files in the database.
+    pub files: usize,
+    /// Names of blobs which don't have an object.
+    pub missing_objects: Vec<String>,
+    /// IDs of files which don't have a blob or object.
+    pub missing_files: Vec<String>,
+    /// Keys of objects which are not referenced by any blob.
+    pub orphaned_objects: Vec<String>,
+    /// Names of blobs whose object doesn't match the stored hash.
+    /// These can't be repaired.
+    pub hash_mismatches: Vec<String>,
+    /// Names of blobs which should have a thumbnail but don't.
+    pub missing_thumbnails: Vec<String>,
+    /// Were the problems repaired?
+    pub repaired: bool,
+}
@@ -1,2 +1,4 @@
 pub mod file;
 pub mod registration_key;
+pub mod settings;
+pub mod user;
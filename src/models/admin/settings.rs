@@ -0,0 +1,50 @@
+use sea_orm::ActiveEnum;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::entity::settings;
+
+/// Server settings.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsData {
+    pub app_name: String,
+    pub app_description: String,
+    /// Theme color of the Backpack instance
+    pub color: String,
+    /// Can new users register?
+    pub registration_open: bool,
+    /// Storage quota in bytes for users without their own quota, `null` if unlimited.
+    pub default_quota: Option<i64>,
+    /// Maximum size of an uploaded file in bytes.
+    pub file_size_limit: i64,
+}
+
+impl From<settings::Model> for SettingsData {
+    fn from(settings: settings::Model) -> Self {
+        Self {
+            app_name: settings.app_name,
+            app_description: settings.app_description,
+            color: settings.color.to_value(),
+            registration_open: settings.registration_open,
+            default_quota: settings.default_quota,
+            file_size_limit: settings.file_size_limit,
+        }
+    }
+}
+
+/// Update server settings.
+/// Fields which are not provided are left unchanged.
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateSettings {
+    pub app_name: Option<String>,
+    pub app_description: Option<String>,
+    /// One of `gray`, `red`, `orange`, `yellow`, `green`, `teal`, `blue`, `cyan`, `purple` or `pink`.
+    pub color: Option<String>,
+    pub registration_open: Option<bool>,
+    /// Storage quota in bytes for users without their own quota, `0` removes the quota.
+    pub default_quota: Option<i64>,
+    /// Maximum size of an uploaded file in bytes.
+    pub file_size_limit: Option<i64>,
+}
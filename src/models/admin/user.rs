@@ -0,0 +1,29 @@
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::UserRole;
+
+#[derive(Deserialize, IntoParams)]
+pub struct UserQuery {
+    /// Username or email search
+    pub search: Option<String>,
+}
+
+/// Change the role of a user.
+#[derive(Deserialize, ToSchema)]
+pub struct UserRoleForm {
+    pub role: UserRole,
+}
+
+/// Suspend or unsuspend a user.
+#[derive(Deserialize, ToSchema)]
+pub struct UserSuspensionForm {
+    pub suspended: bool,
+}
+
+/// Change the storage quota of a user.
+#[derive(Deserialize, ToSchema)]
+pub struct StorageQuotaForm {
+    /// Storage quota in bytes, `null` resets the user to the server default.
+    pub quota: Option<i64>,
+}
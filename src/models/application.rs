@@ -1,5 +1,6 @@
 use sea_orm::prelude::DateTimeUtc;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use utoipa::ToSchema;
 
 use crate::database::entity::applications;
@@ -21,6 +22,22 @@ pub struct ApplicationData {
     /// User ID who owns the application
     pub user_id: String,
 
+    /// Seconds until files uploaded by the application are deleted by default
+    pub default_expiration: Option<i64>,
+
+    /// What the application token can be used for
+    pub scopes: Vec<ApplicationScope>,
+
+    /// When tokens of the application stop working
+    #[schema(value_type = Option<String>)]
+    pub expires_at: Option<DateTimeUtc>,
+
+    /// IP address of the client which last used the application
+    pub last_ip: Option<String>,
+
+    /// User agent of the client which last used the application
+    pub last_user_agent: Option<String>,
+
     /// Only sent when the token is originally created
     pub token: Option<String>,
 }
@@ -31,6 +48,11 @@ impl From<applications::Model> for ApplicationData {
             id: application.id,
             name: application.name,
             user_id: application.user_id,
+            default_expiration: application.default_expiration,
+            scopes: ApplicationScope::parse_list(&application.scopes),
+            expires_at: application.expires_at,
+            last_ip: application.last_ip,
+            last_user_agent: application.last_user_agent,
             last_accessed: application.last_accessed,
             created: application.created,
             // Token is generated by JWT with parameters, not stored in DB
@@ -41,12 +63,132 @@ impl From<applications::Model> for ApplicationData {
 
 /// Token data response
 #[derive(Serialize, ToSchema, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct TokenResponse {
     pub token: String,
+    /// Used to get a new token when it expires, only present for logins
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
 }
 
 /// Application create request
 #[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct ApplicationCreate {
     pub name: String,
+    /// Every scope is granted if this is not provided
+    pub scopes: Option<Vec<ApplicationScope>>,
+    /// When tokens of the application stop working, they never expire if this is not provided
+    #[schema(value_type = Option<String>)]
+    pub expires_at: Option<DateTimeUtc>,
+}
+
+/// Change the scopes of an application
+#[derive(Deserialize, ToSchema)]
+pub struct ApplicationScopesForm {
+    pub scopes: Vec<ApplicationScope>,
+}
+
+/// Action an application token is allowed to perform.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, ToSchema)]
+pub enum ApplicationScope {
+    /// Upload files
+    #[serde(rename = "file:upload")]
+    FileUpload,
+    /// List files and read their contents
+    #[serde(rename = "file:read")]
+    FileRead,
+    /// Change files
+    #[serde(rename = "file:write")]
+    FileWrite,
+    /// Delete files
+    #[serde(rename = "file:delete")]
+    FileDelete,
+    /// Read the user's account information
+    #[serde(rename = "user:read")]
+    UserRead,
+}
+
+impl ApplicationScope {
+    pub const ALL: [ApplicationScope; 5] = [
+        ApplicationScope::FileUpload,
+        ApplicationScope::FileRead,
+        ApplicationScope::FileWrite,
+        ApplicationScope::FileDelete,
+        ApplicationScope::UserRead,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApplicationScope::FileUpload => "file:upload",
+            ApplicationScope::FileRead => "file:read",
+            ApplicationScope::FileWrite => "file:write",
+            ApplicationScope::FileDelete => "file:delete",
+            ApplicationScope::UserRead => "user:read",
+        }
+    }
+
+    /// Parse a space separated list of scopes as stored in the database.
+    /// Unknown scopes are ignored.
+    pub fn parse_list(value: &str) -> Vec<ApplicationScope> {
+        ApplicationScope::ALL
+            .iter()
+            .copied()
+            .filter(|scope| value.split_whitespace().any(|v| v == scope.as_str()))
+            .collect()
+    }
+
+    /// Format scopes as a space separated list to be stored in the database.
+    pub fn format_list(scopes: &[ApplicationScope]) -> String {
+        ApplicationScope::ALL
+            .iter()
+            .copied()
+            .filter(|scope| scopes.contains(scope))
+            .map(|scope| scope.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
+
+impl std::fmt::Display for ApplicationScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Tool an uploader config can be exported for.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UploaderTool {
+    /// ShareX custom uploader (`.sxcu`)
+    Sharex,
+    /// Shell script using curl, for Flameshot and other tools which can run a command
+    Curl,
+}
+
+/// Exported uploader config, this is sent as a file.
+pub struct UploaderConfig {
+    pub filename: String,
+    pub content_type: &'static str,
+    pub content: String,
+}
+
+/// ShareX custom uploader.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SharexUploader {
+    pub version: String,
+    pub name: String,
+    pub destination_type: String,
+    pub request_method: String,
+    #[serde(rename = "RequestURL")]
+    pub request_url: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    pub file_form_name: String,
+    #[serde(rename = "URL")]
+    pub url: String,
+    #[serde(rename = "ThumbnailURL")]
+    pub thumbnail_url: String,
+    pub error_message: String,
 }
@@ -0,0 +1,99 @@
+use sea_orm::prelude::DateTimeUtc;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::database::entity::audit_log;
+
+/// Security-relevant actions recorded in the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    AuthMethodLink,
+    AuthMethodChange,
+    AuthMethodUnlink,
+    ApplicationCreate,
+    RegistrationKeyCreate,
+    RegistrationKeyUse,
+    RegistrationKeyDelete,
+    UserRoleChange,
+    UserSuspensionChange,
+    UserDelete,
+    FileDelete,
+    SettingsUpdate,
+}
+
+impl AuditAction {
+    /// Name stored in the database, this matches the serialized name.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::AuthMethodLink => "auth_method_link",
+            Self::AuthMethodChange => "auth_method_change",
+            Self::AuthMethodUnlink => "auth_method_unlink",
+            Self::ApplicationCreate => "application_create",
+            Self::RegistrationKeyCreate => "registration_key_create",
+            Self::RegistrationKeyUse => "registration_key_use",
+            Self::RegistrationKeyDelete => "registration_key_delete",
+            Self::UserRoleChange => "user_role_change",
+            Self::UserSuspensionChange => "user_suspension_change",
+            Self::UserDelete => "user_delete",
+            Self::FileDelete => "file_delete",
+            Self::SettingsUpdate => "settings_update",
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogData {
+    pub id: String,
+
+    /// Action which was taken.
+    pub action: String,
+
+    /// User who took the action, `null` if it was taken by the server.
+    pub actor_id: Option<String>,
+
+    /// Type of resource the action was taken on (user, application, registration_key, file, settings).
+    pub target_type: Option<String>,
+
+    /// ID of the resource the action was taken on.
+    pub target_id: Option<String>,
+
+    /// Extra information, such as the auth method or the new role.
+    pub details: Option<String>,
+
+    /// IP address of the client.
+    pub ip: Option<String>,
+
+    /// Date of the action.
+    #[schema(value_type = String)]
+    pub created: DateTimeUtc,
+}
+
+impl From<audit_log::Model> for AuditLogData {
+    fn from(model: audit_log::Model) -> Self {
+        Self {
+            id: model.id,
+            action: model.action,
+            actor_id: model.actor_id,
+            target_type: model.target_type,
+            target_id: model.target_id,
+            details: model.details,
+            ip: model.ip,
+            created: model.created,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct AuditLogQuery {
+    /// Only get actions taken by this user.
+    pub actor_id: Option<String>,
+    /// Only get actions taken on this resource.
+    pub target_id: Option<String>,
+    /// Only get this action.
+    #[param(value_type = Option<String>)]
+    pub action: Option<AuditAction>,
+}
@@ -1,7 +1,10 @@
+use std::collections::HashMap;
+
+use sea_orm::prelude::DateTimeUtc;
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 
-use crate::services::auth::oauth::OAuthProvider;
+use crate::{database::entity::sessions, services::auth::oauth::OAuthProvider};
 
 #[derive(Deserialize, ToSchema)]
 pub struct BasicAuthForm {
@@ -27,22 +30,36 @@ pub struct AuthMethods {
     pub github: Option<String>,
     /// Cached discord tag.
     pub discord: Option<String>,
+    /// Cached LDAP username.
+    pub ldap: Option<String>,
+    /// Is TOTP two-factor authentication enabled.
+    pub totp: bool,
+    /// Linked OpenID Connect providers by name, with the cached username.
+    #[schema(value_type = Object)]
+    pub oidc: HashMap<String, Option<String>>,
 }
 
 impl AuthMethods {
     /// Get the amount of enabled auth methods.
+    /// Two-factor methods are not counted since they can't be used to log in alone.
     pub fn enabled_methods(&self) -> u8 {
         return (self.password as u8)
             + (self.google.is_some() as u8)
             + (self.github.is_some() as u8)
-            + (self.discord.is_some() as u8);
+            + (self.discord.is_some() as u8)
+            + (self.ldap.is_some() as u8)
+            + (self.oidc.len() as u8);
     }
 }
 
 #[derive(Deserialize, IntoParams)]
 pub struct OAuthLoginQuery {
+    /// Builtin provider or the name of an OpenID Connect provider.
+    #[param(value_type = String)]
     pub provider: OAuthProvider,
+    /// URL to redirect to after logging in, it must be on the client URL.
     pub redirect: Option<String>,
+    /// Add the token and refresh token to the redirect URL fragment.
     pub include_token: bool,
 }
 
@@ -54,7 +71,99 @@ pub struct LoginRedirectUrl {
 /// Unlink an OAuth method.
 #[derive(Deserialize, ToSchema)]
 pub struct UnlinkAuthMethod {
+    /// Builtin provider or the name of an OpenID Connect provider.
+    #[schema(value_type = String)]
     pub method: OAuthProvider,
     /// Password required if present.
     pub password: Option<String>,
 }
+
+/// Refresh token request
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshTokenForm {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionData {
+    pub id: String,
+
+    /// IP address of the client which last used the session
+    pub ip: Option<String>,
+
+    /// User agent of the client which last used the session
+    pub user_agent: Option<String>,
+
+    /// Date of login
+    #[schema(value_type = String)]
+    pub created: DateTimeUtc,
+
+    /// Last time the session was refreshed
+    #[schema(value_type = String)]
+    pub last_accessed: DateTimeUtc,
+
+    /// When the session expires if it is not used
+    #[schema(value_type = String)]
+    pub expires_at: DateTimeUtc,
+
+    /// Whether this is the session making the request
+    pub current: bool,
+}
+
+impl SessionData {
+    pub fn new(session: sessions::Model, current_session: Option<&str>) -> Self {
+        Self {
+            current: current_session == Some(session.id.as_str()),
+            id: session.id,
+            ip: session.ip,
+            user_agent: session.user_agent,
+            created: session.created,
+            last_accessed: session.last_accessed,
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+/// Returned instead of a token when the user has two-factor authentication enabled
+#[derive(Serialize, ToSchema)]
+pub struct TwoFactorChallenge {
+    /// Sent with a code to complete the login
+    pub challenge: String,
+}
+
+/// Complete a login with a two-factor code
+#[derive(Deserialize, ToSchema)]
+pub struct TwoFactorLoginForm {
+    pub challenge: String,
+    /// TOTP code or recovery code
+    pub code: String,
+}
+
+/// TOTP secret which has to be confirmed with a code before it is used
+#[derive(Serialize, ToSchema)]
+pub struct TotpEnrollment {
+    /// Base32 encoded secret
+    pub secret: String,
+    /// URI for authenticator apps
+    pub uri: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TotpCodeForm {
+    pub code: String,
+}
+
+/// Codes which can be used once each instead of a TOTP code
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+impl From<Vec<String>> for RecoveryCodes {
+    fn from(recovery_codes: Vec<String>) -> Self {
+        Self { recovery_codes }
+    }
+}
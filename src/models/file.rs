@@ -1,14 +1,11 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
-use actix_multipart_extract::{File, MultipartForm};
 use serde::{Deserialize, Serialize};
 
 use chrono::{DateTime, Utc};
 use utoipa::{IntoParams, ToSchema};
 
-use crate::internal::file::can_have_thumbnail;
-
-use crate::database::entity::files;
+use crate::database::entity::{files, sea_orm_active_enums::Visibility};
 
 #[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -19,10 +16,17 @@ pub struct FileData {
     pub original_name: String,
     pub url: Option<String>,
     pub thumbnail_url: Option<String>,
+    /// URLs of resized copies of images by variant name.
+    #[schema(value_type = Object)]
+    pub variants: HashMap<String, String>,
     pub hash: String,
     pub size: i64,
     #[schema(value_type = f64)]
     pub uploaded: DateTime<Utc>,
+    pub visibility: FileVisibility,
+    /// Time the file will be deleted.
+    #[schema(value_type = f64)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 impl From<files::Model> for FileData {
@@ -35,10 +39,13 @@ impl From<files::Model> for FileData {
             hash: file.hash,
             uploaded: file.uploaded.into(),
             size: file.size,
+            visibility: FileVisibility::from(file.visibility),
+            expires_at: file.expires_at.map(|v| v.into()),
             // These fields are not stored in database
             // They are filled in by the route returning it
             url: None,
             thumbnail_url: None,
+            variants: HashMap::new(),
         }
     }
 }
@@ -50,21 +57,86 @@ impl FileData {
         self.url = Some(root_path.as_path().display().to_string().replace("\\", "/"))
     }
 
-    /// Computes and sets the URL based on root storage path
-    /// This will only set if a valid image or extension was sent
+    /// Computes and sets the thumbnail URL based on root storage path
+    /// This should only be used if the file has a thumbnail
     pub fn set_thumbnail_url(&mut self, mut root_path: PathBuf) {
-        if can_have_thumbnail(&self.name) {
-            root_path.push(format!("thumb/{}", &self.name));
-            self.thumbnail_url = Some(root_path.as_path().display().to_string().replace("\\", "/"));
+        root_path.push(format!("thumb/{}", &self.name));
+        self.thumbnail_url = Some(root_path.as_path().display().to_string().replace("\\", "/"));
+    }
+
+    /// Computes and sets the URL of a variant based on root storage path
+    pub fn set_variant_url(&mut self, mut root_path: PathBuf, variant: &str, key: &str) {
+        root_path.push(key);
+        self.variants.insert(
+            variant.to_owned(),
+            root_path.as_path().display().to_string().replace("\\", "/"),
+        );
+    }
+}
+
+/// Who can access a file.
+/// - `public`: Anyone with the URL.
+/// - `unlisted`: Anyone with the URL, but the file is never listed publicly.
+/// - `private`: Only the owner or someone with a signed link.
+#[derive(Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum FileVisibility {
+    Public,
+    Private,
+    Unlisted,
+}
+
+impl From<Visibility> for FileVisibility {
+    fn from(visibility: Visibility) -> Self {
+        match visibility {
+            Visibility::Public => FileVisibility::Public,
+            Visibility::Private => FileVisibility::Private,
+            Visibility::Unlisted => FileVisibility::Unlisted,
         }
     }
 }
 
+impl From<FileVisibility> for Visibility {
+    fn from(visibility: FileVisibility) -> Self {
+        match visibility {
+            FileVisibility::Public => Visibility::Public,
+            FileVisibility::Private => Visibility::Private,
+            FileVisibility::Unlisted => Visibility::Unlisted,
+        }
+    }
+}
+
+/// Update who can access a file.
+#[derive(Deserialize, ToSchema)]
+pub struct FileVisibilityForm {
+    pub visibility: FileVisibility,
+}
+
+/// Signed link to a file.
+#[derive(Serialize, ToSchema)]
+pub struct FileLink {
+    pub url: String,
+    /// Time the link stops working.
+    #[schema(value_type = f64)]
+    pub expires: DateTime<Utc>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct FileLinkQuery {
+    /// Seconds until the link expires, defaults to an hour.
+    pub expires_in: Option<u64>,
+}
+
 /// File stats for user.
 #[derive(Serialize, ToSchema)]
 pub struct FileStats {
     /// Total usage in bytes
     pub usage: i64,
+    /// Storage quota in bytes, `null` if storage is unlimited
+    pub limit: Option<i64>,
+    /// Remaining storage in bytes, `null` if storage is unlimited
+    pub remaining: Option<i64>,
 }
 
 /// Delete multiple files.
@@ -102,14 +174,48 @@ pub struct UploadConflict {
 }
 
 /// Upload a file.
-#[derive(Deserialize, MultipartForm, ToSchema, Debug)]
+///
+/// Only used for the OpenAPI docs, the upload route reads the multipart stream itself.
+#[allow(dead_code)]
+#[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UploadFile {
     #[schema(value_type = String, format = Binary)]
-    pub upload_file: File,
+    pub upload_file: Vec<u8>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadQuery {
+    /// Who can access the file, defaults to `public`.
+    pub visibility: Option<FileVisibility>,
+    /// Seconds until the file is deleted.
+    /// Defaults to the application's or user's default expiration.
+    pub expiration: Option<u64>,
+    /// Respond with the existing file instead of a conflict if the file was already uploaded.
+    /// This is for uploaders which can't read the file from an error response.
+    pub return_existing: Option<bool>,
+}
+
+/// Default retention of uploaded files.
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionPolicy {
+    /// Seconds until uploaded files are deleted, `null` keeps files forever.
+    pub default_expiration: Option<u64>,
 }
 
 #[derive(Deserialize, IntoParams)]
 pub struct FileQuery {
     pub query: Option<String>,
 }
+
+#[derive(Deserialize, IntoParams)]
+pub struct ContentQuery {
+    /// Force the file to be downloaded instead of displayed inline.
+    pub download: Option<bool>,
+    /// Expiry timestamp of a signed link, required for private files.
+    pub expires: Option<i64>,
+    /// Signature of a signed link, required for private files.
+    pub signature: Option<String>,
+}
@@ -1,8 +1,10 @@
 pub mod admin;
 pub mod application;
+pub mod audit_log;
 pub mod auth;
 pub mod file;
 pub mod user;
+pub mod webhook;
 
 use crate::{database::entity::settings, internal::GIT_VERSION};
 use actix_http::body::BoxBody;
@@ -15,7 +17,7 @@ use std::fmt::Display;
 use utoipa::ToSchema;
 
 use self::registration_key::RegistrationKeyData;
-pub use self::{admin::*, application::*, auth::*, file::*, user::*};
+pub use self::{admin::*, application::*, audit_log::*, auth::*, file::*, user::*, webhook::*};
 
 /// Standard message response.
 ///
@@ -143,7 +145,11 @@ impl Responder for MessageResponse {
 #[aliases(
     FilePage = Page<FileData>,
     RegistrationKeyPage = Page<RegistrationKeyData>,
-    ApplicationPage = Page<ApplicationData>
+    ApplicationPage = Page<ApplicationData>,
+    UserPage = Page<UserData>,
+    AuditLogPage = Page<AuditLogData>,
+    WebhookPage = Page<WebhookData>,
+    WebhookDeliveryPage = Page<WebhookDeliveryData>
 )]
 pub struct Page<T> {
     pub page: usize,
@@ -157,6 +163,17 @@ pub struct OAuthProviders {
     pub google: bool,
     pub github: bool,
     pub discord: bool,
+    /// OpenID Connect providers configured on the server.
+    pub oidc: Vec<OidcProviderInfo>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcProviderInfo {
+    /// Name used in login and callback URLs.
+    pub name: String,
+    /// Name shown to users.
+    pub display_name: String,
 }
 
 /// Public server configuration
@@ -178,6 +195,12 @@ pub struct AppInfo {
     /// Is SMTP (email verification) enabled on the server?
     pub smtp: bool,
 
+    /// Can new users register?
+    pub registration_open: bool,
+
+    /// Maximum size of an uploaded file in bytes.
+    pub file_size_limit: i64,
+
     /// Git tag (version) or commit hash
     pub git_version: String,
 
@@ -202,6 +225,8 @@ impl AppInfo {
             color: settings_model.color.to_owned().to_value(),
             invite_only,
             smtp,
+            registration_open: settings_model.registration_open,
+            file_size_limit: settings_model.file_size_limit,
             git_version: GIT_VERSION.to_string(),
             uploaded_files,
             oauth_providers,
@@ -1,3 +1,4 @@
+use sea_orm::prelude::DateTimeUtc;
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 
@@ -13,6 +14,15 @@ pub struct UserData {
     /// This will be true always if service is in `invite_only` mode.
     pub registered: bool,
     pub role: UserRole,
+    /// Seconds until uploaded files are deleted by default.
+    pub default_expiration: Option<i64>,
+    /// Storage quota in bytes, the server default is used if this is `null`.
+    pub storage_quota: Option<i64>,
+    /// Suspended users can't use the API.
+    pub suspended: bool,
+    /// Date of account creation.
+    #[schema(value_type = String)]
+    pub created: DateTimeUtc,
 }
 
 impl From<users::Model> for UserData {
@@ -24,6 +34,10 @@ impl From<users::Model> for UserData {
             verified: user.verified,
             registered: user.registered,
             role: UserRole::from(user.role),
+            default_expiration: user.default_expiration,
+            storage_quota: user.storage_quota,
+            suspended: user.suspended,
+            created: user.created,
         }
     }
 }
@@ -45,6 +59,15 @@ impl From<Role> for UserRole {
     }
 }
 
+impl From<UserRole> for Role {
+    fn from(role: UserRole) -> Self {
+        match role {
+            UserRole::Admin => Role::Admin,
+            UserRole::User => Role::User,
+        }
+    }
+}
+
 #[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UserCreateForm {
@@ -83,3 +106,15 @@ pub struct UpdateUserSettings {
     /// This is required if a password has been set prior.
     pub current_password: Option<String>,
 }
+
+#[derive(Deserialize, ToSchema)]
+pub struct ForgotPasswordForm {
+    pub email: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasswordResetForm {
+    /// Code sent in the password reset email
+    pub code: String,
+    pub password: String,
+}
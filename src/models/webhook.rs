@@ -0,0 +1,161 @@
+use sea_orm::prelude::DateTimeUtc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::database::entity::{webhook_deliveries, webhooks};
+
+/// Event which is sent to webhooks.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, ToSchema)]
+pub enum WebhookEvent {
+    /// A file was uploaded
+    #[serde(rename = "file.uploaded")]
+    FileUploaded,
+    /// A file was deleted, this includes files which expired
+    #[serde(rename = "file.deleted")]
+    FileDeleted,
+    /// The user's email was verified
+    #[serde(rename = "user.verified")]
+    UserVerified,
+    /// An application was created
+    #[serde(rename = "application.created")]
+    ApplicationCreated,
+    /// Sent when a webhook is tested, this can't be subscribed to
+    #[serde(rename = "ping")]
+    Ping,
+}
+
+impl WebhookEvent {
+    /// Events which webhooks can subscribe to.
+    pub const ALL: [WebhookEvent; 4] = [
+        WebhookEvent::FileUploaded,
+        WebhookEvent::FileDeleted,
+        WebhookEvent::UserVerified,
+        WebhookEvent::ApplicationCreated,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::FileUploaded => "file.uploaded",
+            WebhookEvent::FileDeleted => "file.deleted",
+            WebhookEvent::UserVerified => "user.verified",
+            WebhookEvent::ApplicationCreated => "application.created",
+            WebhookEvent::Ping => "ping",
+        }
+    }
+
+    /// Parse a space separated list of events as stored in the database.
+    /// Unknown events are ignored.
+    pub fn parse_list(value: &str) -> Vec<WebhookEvent> {
+        WebhookEvent::ALL
+            .iter()
+            .copied()
+            .filter(|event| value.split_whitespace().any(|v| v == event.as_str()))
+            .collect()
+    }
+
+    /// Format events as a space separated list to be stored in the database.
+    pub fn format_list(events: &[WebhookEvent]) -> String {
+        WebhookEvent::ALL
+            .iter()
+            .copied()
+            .filter(|event| events.contains(event))
+            .map(|event| event.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookData {
+    pub id: String,
+
+    /// Only events caused by this application are sent, every event of the user is sent if this is `null`
+    pub application_id: Option<String>,
+
+    /// URL events are posted to
+    pub url: String,
+
+    /// Events sent to the webhook
+    pub events: Vec<WebhookEvent>,
+
+    /// Date of webhook creation
+    #[schema(value_type = String)]
+    pub created: DateTimeUtc,
+
+    /// Key used to sign deliveries, only sent when the webhook is originally created
+    pub secret: Option<String>,
+}
+
+impl From<webhooks::Model> for WebhookData {
+    fn from(webhook: webhooks::Model) -> Self {
+        Self {
+            id: webhook.id,
+            application_id: webhook.application_id,
+            url: webhook.url,
+            events: WebhookEvent::parse_list(&webhook.events),
+            created: webhook.created,
+            secret: None,
+        }
+    }
+}
+
+/// Webhook create request
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookCreate {
+    /// `http` or `https` URL events are posted to
+    pub url: String,
+    /// Every event is sent if this is not provided
+    pub events: Option<Vec<WebhookEvent>>,
+    /// Only send events caused by this application
+    pub application_id: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDeliveryData {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+
+    /// JSON body which was sent
+    pub payload: String,
+
+    /// Amount of times delivery was attempted
+    pub attempts: i32,
+
+    /// Did the receiver respond with a successful status
+    pub delivered: bool,
+
+    /// Response status of the last attempt
+    pub status_code: Option<i32>,
+
+    /// Error of the last attempt
+    pub error: Option<String>,
+
+    /// Date the event was sent
+    #[schema(value_type = String)]
+    pub created: DateTimeUtc,
+
+    /// When delivery is attempted again, `null` once it was delivered or ran out of attempts
+    #[schema(value_type = Option<String>)]
+    pub next_attempt_at: Option<DateTimeUtc>,
+}
+
+impl From<webhook_deliveries::Model> for WebhookDeliveryData {
+    fn from(delivery: webhook_deliveries::Model) -> Self {
+        Self {
+            id: delivery.id,
+            webhook_id: delivery.webhook_id,
+            event: delivery.event,
+            payload: delivery.payload,
+            attempts: delivery.attempts,
+            delivered: delivery.delivered,
+            status_code: delivery.status_code,
+            error: delivery.error,
+            created: delivery.created,
+            next_attempt_at: delivery.next_attempt_at,
+        }
+    }
+}
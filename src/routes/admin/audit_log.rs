@@ -0,0 +1,42 @@
+use actix_http::StatusCode;
+use actix_web::{get, web, Responder, Scope};
+
+use crate::{
+    internal::auth::{auth_role, Auth},
+    models::{AuditLogData, AuditLogQuery},
+    services::{audit_log::AuditLogService, ToPageResponse},
+};
+
+pub fn get_routes() -> Scope {
+    web::scope("/audit").service(list)
+}
+
+/// Get a paginated list of audit log entries
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/admin/audit",
+    tag = "admin",
+    responses(
+        (status = 200, body = AuditLogPage),
+        (status = 400, body = MessageResponse, description = "Invalid page number")
+    ),
+    params(
+        ("page_number" = usize, Path, description = "Page to get"),
+        AuditLogQuery
+    ),
+    security(("apiKey" = [])),
+)]
+#[get("/list/{page_number}")]
+async fn list(
+    service: web::Data<AuditLogService>,
+    page_number: web::Path<usize>,
+    query: web::Query<AuditLogQuery>,
+    _user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    service
+        .get_filtered_page(*page_number, 25, &query)
+        .await
+        .to_page_response::<AuditLogData>(StatusCode::OK)
+}
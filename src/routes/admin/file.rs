@@ -1,15 +1,23 @@
 use actix_http::StatusCode;
-use actix_web::{delete, get, web, Responder, Scope};
+use actix_web::{delete, get, post, web, HttpRequest, Responder, Scope};
 
 use crate::{
-    internal::auth::{auth_role, Auth},
-    models::{admin::file::FileQuery, BatchDeleteRequest, BatchDeleteResponse, FileData},
-    services::{file::FileService, ToMessageResponse, ToPageResponse, ToResponse},
+    internal::auth::{auth_role, Auth, ClientInfo},
+    models::{
+        admin::file::{FileQuery, StorageCheckQuery, StorageReport},
+        AuditAction, BatchDeleteRequest, BatchDeleteResponse, FileData,
+    },
+    services::{
+        audit_log::{AuditLogService, AuditTarget},
+        file::FileService,
+        ToMessageResponse, ToPageResponse, ToResponse,
+    },
 };
 
 pub fn get_routes() -> Scope {
     web::scope("/file")
         .service(list)
+        .service(check)
         .service(info)
         .service(delete_file)
         .service(delete_files)
@@ -92,14 +100,27 @@ async fn info(
 )]
 #[delete("/{file_id}")]
 async fn delete_file(
+    req: HttpRequest,
     service: web::Data<FileService>,
+    audit_log_service: web::Data<AuditLogService>,
     file_id: web::Path<String>,
-    _user: Auth<auth_role::Admin>,
+    user: Auth<auth_role::Admin>,
 ) -> impl Responder {
-    service
-        .delete_file(&file_id, None)
-        .await
-        .to_message_response(StatusCode::OK)
+    let result = service.delete_file(&file_id, None, None).await;
+
+    if result.is_ok() {
+        audit_log_service
+            .record(
+                AuditAction::FileDelete,
+                Some(&user.id),
+                Some(AuditTarget::File(&file_id)),
+                None,
+                &ClientInfo::from_request(&req),
+            )
+            .await;
+    }
+
+    result.to_message_response(StatusCode::OK)
 }
 
 /// Delete multiple files by ID.
@@ -118,12 +139,57 @@ async fn delete_file(
 )]
 #[delete("/batch")]
 async fn delete_files(
+    req: HttpRequest,
     service: web::Data<FileService>,
+    audit_log_service: web::Data<AuditLogService>,
     body: web::Json<BatchDeleteRequest>,
+    user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    let result = service.delete_batch(&body.ids, None, None).await;
+
+    if let Ok(response) = &result {
+        let client = ClientInfo::from_request(&req);
+
+        for file_id in &response.deleted {
+            audit_log_service
+                .record(
+                    AuditAction::FileDelete,
+                    Some(&user.id),
+                    Some(AuditTarget::File(file_id)),
+                    None,
+                    &client,
+                )
+                .await;
+        }
+    }
+
+    result.to_response::<BatchDeleteResponse>(StatusCode::OK)
+}
+
+/// Check that stored objects match the database
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+///
+/// Problems are only reported, repairs delete objects which are still being uploaded
+/// so they are only available with `--check-storage --repair` while the server is stopped.
+#[utoipa::path(
+    context_path = "/api/admin/file",
+    tag = "admin",
+    responses(
+        (status = 200, body = StorageReport),
+    ),
+    params(StorageCheckQuery),
+    security(("apiKey" = [])),
+)]
+#[post("/check")]
+async fn check(
+    service: web::Data<FileService>,
+    query: web::Query<StorageCheckQuery>,
     _user: Auth<auth_role::Admin>,
 ) -> impl Responder {
     service
-        .delete_batch(&body.ids, None)
+        .check_storage(false, query.verify_hashes.unwrap_or(false))
         .await
-        .to_response::<BatchDeleteResponse>(StatusCode::OK)
+        .to_response::<StorageReport>(StatusCode::OK)
 }
@@ -1,10 +1,17 @@
 use actix_web::{web, Scope};
 
+pub mod audit_log;
 pub mod file;
 pub mod registration_key;
+pub mod settings;
+pub mod user;
 
 pub fn get_routes(invite_only: bool) -> Scope {
-    let scope = web::scope("/admin").service(file::get_routes());
+    let scope = web::scope("/admin")
+        .service(audit_log::get_routes())
+        .service(file::get_routes())
+        .service(settings::get_routes())
+        .service(user::get_routes());
 
     if invite_only {
         scope.service(registration_key::get_routes())
@@ -1,8 +1,8 @@
 use actix_http::StatusCode;
-use actix_web::{delete, get, post, web, Responder, Scope};
+use actix_web::{delete, get, post, web, HttpRequest, Responder, Scope};
 
 use crate::{
-    internal::auth::{auth_role, Auth},
+    internal::auth::{auth_role, Auth, ClientInfo},
     models::admin::registration_key::{RegistrationKeyData, RegistrationKeyParams},
     services::{prelude::*, registration_key::RegistrationKeyService},
 };
@@ -28,12 +28,18 @@ pub fn get_routes() -> Scope {
 )]
 #[post("")]
 async fn create(
+    req: HttpRequest,
     service: web::Data<RegistrationKeyService>,
     user: Auth<auth_role::Admin>,
     query: web::Query<RegistrationKeyParams>,
 ) -> impl Responder {
     service
-        .create_registration_key(&user.id, query.uses, query.expiration)
+        .create_registration_key(
+            &user.id,
+            query.uses,
+            query.expiration,
+            &ClientInfo::from_request(&req),
+        )
         .await
         .to_response::<RegistrationKeyData>(StatusCode::OK)
 }
@@ -109,12 +115,13 @@ async fn get_one(
 )]
 #[delete("/{registration_id}")]
 async fn delete(
+    req: HttpRequest,
     service: web::Data<RegistrationKeyService>,
     registration_id: web::Path<String>,
-    _user: Auth<auth_role::Admin>,
+    user: Auth<auth_role::Admin>,
 ) -> impl Responder {
     service
-        .delete(registration_id.to_string(), true, None)
+        .delete_registration_key(&registration_id, &user.id, &ClientInfo::from_request(&req))
         .await
         .to_message_response(StatusCode::OK)
 }
@@ -0,0 +1,83 @@
+use actix_http::StatusCode;
+use actix_web::{get, put, web, HttpRequest, Responder, Scope};
+
+use crate::{
+    internal::auth::{auth_role, Auth, ClientInfo},
+    models::{
+        admin::settings::{SettingsData, UpdateSettings},
+        AuditAction,
+    },
+    services::{
+        audit_log::{AuditLogService, AuditTarget},
+        settings::SettingsService,
+        ToResponse,
+    },
+};
+
+pub fn get_routes() -> Scope {
+    web::scope("/settings").service(info).service(update)
+}
+
+/// Get server settings
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/admin/settings",
+    tag = "admin",
+    responses((status = 200, body = SettingsData)),
+    security(("apiKey" = [])),
+)]
+#[get("")]
+async fn info(
+    service: web::Data<SettingsService>,
+    _user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    service
+        .get_settings()
+        .await
+        .to_response::<SettingsData>(StatusCode::OK)
+}
+
+/// Update server settings
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+///
+/// Only provided fields are changed.
+#[utoipa::path(
+    context_path = "/api/admin/settings",
+    tag = "admin",
+    responses(
+        (status = 200, body = SettingsData),
+        (status = 400, body = MessageResponse, description = "Invalid settings")
+    ),
+    request_body = UpdateSettings,
+    security(("apiKey" = [])),
+)]
+#[put("")]
+async fn update(
+    req: HttpRequest,
+    service: web::Data<SettingsService>,
+    audit_log_service: web::Data<AuditLogService>,
+    form: web::Json<UpdateSettings>,
+    user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    // Changed fields are recorded, unchanged fields are null.
+    let details = serde_json::to_string(&form.0).ok();
+    let result = service.update_settings(form.0).await;
+
+    if result.is_ok() {
+        audit_log_service
+            .record(
+                AuditAction::SettingsUpdate,
+                Some(&user.id),
+                Some(AuditTarget::Settings),
+                details,
+                &ClientInfo::from_request(&req),
+            )
+            .await;
+    }
+
+    result.to_response::<SettingsData>(StatusCode::OK)
+}
@@ -0,0 +1,352 @@
+use actix_http::StatusCode;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder, Scope};
+use sea_orm::ActiveEnum;
+
+use crate::{
+    database::entity::sea_orm_active_enums::Role,
+    internal::auth::{auth_role, Auth, ClientInfo},
+    models::{
+        admin::user::{StorageQuotaForm, UserQuery, UserRoleForm, UserSuspensionForm},
+        AuditAction, FileStats, MessageResponse, UserData,
+    },
+    services::{
+        audit_log::{AuditLogService, AuditTarget},
+        file::FileService,
+        prelude::DataService,
+        user::UserService,
+        ToPageResponse, ToResponse,
+    },
+};
+
+pub fn get_routes() -> Scope {
+    web::scope("/user")
+        .service(list)
+        .service(info)
+        .service(stats)
+        .service(role)
+        .service(verify)
+        .service(suspend)
+        .service(quota)
+        .service(delete)
+}
+
+/// Get a paginated list of users
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/admin/user",
+    tag = "admin",
+    responses(
+        (status = 200, body = UserPage),
+        (status = 400, body = MessageResponse, description = "Invalid page number")
+    ),
+    params(
+        ("page_number" = usize, Path, description = "Page to get"),
+        UserQuery
+    ),
+    security(("apiKey" = [])),
+)]
+#[get("/list/{page_number}")]
+async fn list(
+    service: web::Data<UserService>,
+    page_number: web::Path<usize>,
+    query: web::Query<UserQuery>,
+    _user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    service
+        .get_user_page(*page_number, 25, query.0.search)
+        .await
+        .to_page_response::<UserData>(StatusCode::OK)
+}
+
+/// Get a user by ID
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/admin/user",
+    tag = "admin",
+    responses(
+        (status = 200, body = UserData),
+        (status = 404, body = MessageResponse, description = "User not found")
+    ),
+    params(
+        ("user_id" = str, Path, description = "User ID"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[get("/{user_id}")]
+async fn info(
+    service: web::Data<UserService>,
+    user_id: web::Path<String>,
+    _user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    service
+        .by_id(user_id.to_string())
+        .await
+        .to_response::<UserData>(StatusCode::OK)
+}
+
+/// Get file stats for a user
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/admin/user",
+    tag = "admin",
+    responses(
+        (status = 200, body = FileStats),
+        (status = 404, body = MessageResponse, description = "User not found")
+    ),
+    params(
+        ("user_id" = str, Path, description = "User ID"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[get("/{user_id}/stats")]
+async fn stats(
+    service: web::Data<UserService>,
+    file_service: web::Data<FileService>,
+    user_id: web::Path<String>,
+    _user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    let user = match service.by_id(user_id.to_string()).await {
+        Ok(v) => v,
+        Err(e) => return e.to_response(),
+    };
+
+    file_service
+        .user_stats(&user)
+        .await
+        .to_response::<FileStats>(StatusCode::OK)
+}
+
+/// Change the role of a user
+/// Admins can't change their own role.
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/admin/user",
+    tag = "admin",
+    responses(
+        (status = 200, body = UserData),
+        (status = 400, body = MessageResponse, description = "Can't change your own role"),
+        (status = 404, body = MessageResponse, description = "User not found")
+    ),
+    params(
+        ("user_id" = str, Path, description = "User ID"),
+    ),
+    request_body = UserRoleForm,
+    security(("apiKey" = [])),
+)]
+#[put("/{user_id}/role")]
+async fn role(
+    req: HttpRequest,
+    service: web::Data<UserService>,
+    audit_log_service: web::Data<AuditLogService>,
+    user_id: web::Path<String>,
+    form: web::Json<UserRoleForm>,
+    user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    if *user_id == user.id {
+        return self_action_response("change your own role");
+    }
+
+    let role = Role::from(form.into_inner().role);
+    let result = service.set_role(&user_id, role.clone()).await;
+
+    if result.is_ok() {
+        audit_log_service
+            .record(
+                AuditAction::UserRoleChange,
+                Some(&user.id),
+                Some(AuditTarget::User(&user_id)),
+                Some(role.to_value()),
+                &ClientInfo::from_request(&req),
+            )
+            .await;
+    }
+
+    result.to_response::<UserData>(StatusCode::OK)
+}
+
+/// Verify a user without a verification email
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/admin/user",
+    tag = "admin",
+    responses(
+        (status = 200, body = UserData),
+        (status = 404, body = MessageResponse, description = "User not found")
+    ),
+    params(
+        ("user_id" = str, Path, description = "User ID"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[post("/{user_id}/verify")]
+async fn verify(
+    service: web::Data<UserService>,
+    user_id: web::Path<String>,
+    _user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    let user = match service.by_id(user_id.to_string()).await {
+        Ok(v) => v,
+        Err(e) => return e.to_response(),
+    };
+
+    if let Err(e) = service.verify_user(&user).await {
+        return e.to_response();
+    }
+
+    service
+        .by_id(user.id)
+        .await
+        .to_response::<UserData>(StatusCode::OK)
+}
+
+/// Suspend or unsuspend a user
+/// Suspended users can't use the API until they are unsuspended.
+/// Admins can't suspend themselves.
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/admin/user",
+    tag = "admin",
+    responses(
+        (status = 200, body = UserData),
+        (status = 400, body = MessageResponse, description = "Can't suspend yourself"),
+        (status = 404, body = MessageResponse, description = "User not found")
+    ),
+    params(
+        ("user_id" = str, Path, description = "User ID"),
+    ),
+    request_body = UserSuspensionForm,
+    security(("apiKey" = [])),
+)]
+#[put("/{user_id}/suspension")]
+async fn suspend(
+    req: HttpRequest,
+    service: web::Data<UserService>,
+    audit_log_service: web::Data<AuditLogService>,
+    user_id: web::Path<String>,
+    form: web::Json<UserSuspensionForm>,
+    user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    if *user_id == user.id {
+        return self_action_response("suspend yourself");
+    }
+
+    let result = service.set_suspended(&user_id, form.suspended).await;
+
+    if result.is_ok() {
+        audit_log_service
+            .record(
+                AuditAction::UserSuspensionChange,
+                Some(&user.id),
+                Some(AuditTarget::User(&user_id)),
+                Some(form.suspended.to_string()),
+                &ClientInfo::from_request(&req),
+            )
+            .await;
+    }
+
+    result.to_response::<UserData>(StatusCode::OK)
+}
+
+/// Change the storage quota of a user
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/admin/user",
+    tag = "admin",
+    responses(
+        (status = 200, body = UserData),
+        (status = 400, body = MessageResponse, description = "Invalid quota"),
+        (status = 404, body = MessageResponse, description = "User not found")
+    ),
+    params(
+        ("user_id" = str, Path, description = "User ID"),
+    ),
+    request_body = StorageQuotaForm,
+    security(("apiKey" = [])),
+)]
+#[put("/{user_id}/quota")]
+async fn quota(
+    service: web::Data<UserService>,
+    user_id: web::Path<String>,
+    form: web::Json<StorageQuotaForm>,
+    _user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    service
+        .set_storage_quota(&user_id, form.quota)
+        .await
+        .to_response::<UserData>(StatusCode::OK)
+}
+
+/// Delete a user and all files owned by the user
+/// Admins can't delete themselves through this route.
+/// - Minimum required role: `admin`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/admin/user",
+    tag = "admin",
+    responses(
+        (status = 200, body = MessageResponse, description = "User was deleted"),
+        (status = 400, body = MessageResponse, description = "Can't delete yourself"),
+        (status = 404, body = MessageResponse, description = "User not found")
+    ),
+    params(
+        ("user_id" = str, Path, description = "User ID"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[delete("/{user_id}")]
+async fn delete(
+    req: HttpRequest,
+    service: web::Data<UserService>,
+    audit_log_service: web::Data<AuditLogService>,
+    user_id: web::Path<String>,
+    user: Auth<auth_role::Admin>,
+) -> impl Responder {
+    if *user_id == user.id {
+        return self_action_response("delete yourself");
+    }
+
+    let target = match service.by_id(user_id.to_string()).await {
+        Ok(v) => v,
+        Err(e) => return e.to_response(),
+    };
+
+    if let Err(e) = service.force_delete(&target).await {
+        return e.to_response();
+    }
+
+    audit_log_service
+        .record(
+            AuditAction::UserDelete,
+            Some(&user.id),
+            Some(AuditTarget::User(&target.id)),
+            Some(target.username),
+            &ClientInfo::from_request(&req),
+        )
+        .await;
+
+    MessageResponse::new(StatusCode::OK, "User has been deleted").http_response()
+}
+
+/// Response for actions admins can't take on their own account.
+fn self_action_response(action: &str) -> HttpResponse {
+    MessageResponse::new(
+        StatusCode::BAD_REQUEST,
+        &format!("You can not {} as an admin", action),
+    )
+    .http_response()
+}
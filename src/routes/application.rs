@@ -1,10 +1,17 @@
-use actix_web::{delete, get, http::StatusCode, post, web, Responder, Scope};
+use actix_web::{
+    delete, get,
+    http::{
+        header::{ContentDisposition, DispositionParam, DispositionType},
+        StatusCode,
+    },
+    post, put, web, HttpRequest, HttpResponse, Responder, Scope,
+};
 use sea_orm::{prelude::*, Condition};
 
 use crate::{
     database::entity::applications,
-    internal::auth::{auth_role, Auth},
-    models::application::*,
+    internal::auth::{auth_role, Auth, ClientInfo},
+    models::{application::*, RetentionPolicy},
     services::{
         application::ApplicationService, prelude::DataService, ToMessageResponse, ToPageResponse,
         ToResponse,
@@ -18,6 +25,10 @@ pub fn get_routes() -> Scope {
         .service(create)
         .service(delete)
         .service(token)
+        .service(rotate)
+        .service(retention)
+        .service(scopes)
+        .service(uploader)
 }
 
 /// Get token by application ID
@@ -48,6 +59,140 @@ async fn token(
         .to_response::<TokenResponse>(StatusCode::OK)
 }
 
+/// Rotate the token of an application
+/// Every token of the application created before this is revoked.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/application",
+    tag = "application",
+    responses(
+        (status = 200, body = TokenResponse),
+        (status = 404, body = MessageResponse, description = "Application not found")
+    ),
+    params(
+        ("application_id" = str, Path, description = "Application ID to rotate the token of"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[post("/{application_id}/token/rotate")]
+async fn rotate(
+    service: web::Data<ApplicationService>,
+    application_id: web::Path<String>,
+    user: Auth<auth_role::User>,
+) -> impl Responder {
+    service
+        .rotate_token(&application_id, Some(&user.id))
+        .await
+        .to_response::<TokenResponse>(StatusCode::OK)
+}
+
+/// Change the default retention of files uploaded by an application
+/// The user's default retention is used if this is not set.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/application",
+    tag = "application",
+    responses(
+        (status = 200, body = ApplicationData),
+        (status = 400, body = MessageResponse, description = "Invalid expiration"),
+        (status = 404, body = MessageResponse, description = "Application not found")
+    ),
+    params(
+        ("application_id" = str, Path, description = "Application ID to update"),
+    ),
+    request_body = RetentionPolicy,
+    security(("apiKey" = [])),
+)]
+#[put("/{application_id}/retention")]
+async fn retention(
+    service: web::Data<ApplicationService>,
+    application_id: web::Path<String>,
+    form: web::Json<RetentionPolicy>,
+    user: Auth<auth_role::User>,
+) -> impl Responder {
+    service
+        .set_default_expiration(&application_id, Some(&user.id), form.default_expiration)
+        .await
+        .to_response::<ApplicationData>(StatusCode::OK)
+}
+
+/// Change what tokens of an application can be used for
+/// Removed scopes also apply to existing tokens, added scopes only apply to new tokens.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/application",
+    tag = "application",
+    responses(
+        (status = 200, body = ApplicationData),
+        (status = 404, body = MessageResponse, description = "Application not found")
+    ),
+    params(
+        ("application_id" = str, Path, description = "Application ID to update"),
+    ),
+    request_body = ApplicationScopesForm,
+    security(("apiKey" = [])),
+)]
+#[put("/{application_id}/scopes")]
+async fn scopes(
+    service: web::Data<ApplicationService>,
+    application_id: web::Path<String>,
+    form: web::Json<ApplicationScopesForm>,
+    user: Auth<auth_role::User>,
+) -> impl Responder {
+    service
+        .set_scopes(&application_id, Some(&user.id), &form.scopes)
+        .await
+        .to_response::<ApplicationData>(StatusCode::OK)
+}
+
+/// Export an uploader config for an application
+/// The config contains a new token of the application, which needs the `file:upload` scope.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/application",
+    tag = "application",
+    responses(
+        (status = 200, description = "Config file, `.sxcu` for ShareX or a shell script for curl"),
+        (status = 400, body = MessageResponse, description = "Application can't upload files"),
+        (status = 404, body = MessageResponse, description = "Application not found")
+    ),
+    params(
+        ("application_id" = str, Path, description = "Application ID to export a config for"),
+        ("tool" = UploaderTool, Path, description = "Tool to export a config for"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[get("/{application_id}/uploader/{tool}")]
+async fn uploader(
+    service: web::Data<ApplicationService>,
+    path: web::Path<(String, UploaderTool)>,
+    user: Auth<auth_role::User>,
+) -> impl Responder {
+    let (application_id, tool) = path.into_inner();
+
+    match service
+        .uploader_config(&application_id, Some(&user.id), tool)
+        .await
+    {
+        Ok(config) => HttpResponse::Ok()
+            .content_type(config.content_type)
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(config.filename)],
+            })
+            .body(config.content),
+        Err(e) => e.to_response(),
+    }
+}
+
 /// Get all applications
 /// - Minimum required role: `user`
 /// - Allow unverified users: `false`
@@ -122,12 +267,19 @@ async fn info(
 )]
 #[post("")]
 async fn create(
+    req: HttpRequest,
     service: web::Data<ApplicationService>,
     user: Auth<auth_role::User>,
     form: web::Json<ApplicationCreate>,
 ) -> impl Responder {
     service
-        .create_application(&user.id, &form.name)
+        .create_application(
+            &user.id,
+            &form.name,
+            form.scopes.as_deref().unwrap_or(&ApplicationScope::ALL),
+            form.expires_at,
+            &ClientInfo::from_request(&req),
+        )
         .await
         .to_response::<ApplicationData>(StatusCode::OK)
 }
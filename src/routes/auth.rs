@@ -1,23 +1,38 @@
 use crate::{
     internal::auth::{
-        auth_role, get_token, AllowUnregistered, AllowUnverified, Auth, DenyApplication,
+        auth_role, get_token, AllowUnregistered, AllowUnverified, Auth, ClientInfo, DenyApplication,
     },
     models::{
         auth::BasicAuthForm, AuthMethods, LoginRedirectUrl, OAuthLoginQuery, OAuthRequest,
-        TokenResponse, UnlinkAuthMethod,
+        RecoveryCodes, RefreshTokenForm, SessionData, TokenResponse, TotpCodeForm, TotpEnrollment,
+        TwoFactorLoginForm, UnlinkAuthMethod,
     },
     services::{
-        auth::{auth_method::AuthMethodService, oauth::OAuthProvider, AuthService},
-        ToResponse,
+        auth::{
+            auth_method::AuthMethodService, oauth::OAuthProvider, session::SessionService,
+            two_factor::TwoFactorService, AuthService, LoginResult,
+        },
+        ToMessageResponse, ToResponse,
     },
 };
 
 use actix_http::header;
-use actix_web::{get, http::StatusCode, post, web, HttpRequest, HttpResponse, Responder, Scope};
+use actix_web::{
+    delete, get, http::StatusCode, post, web, HttpRequest, HttpResponse, Responder, Scope,
+};
 
 pub fn get_routes() -> Scope {
     web::scope("/auth")
         .service(basic)
+        .service(two_factor)
+        .service(totp_enroll)
+        .service(totp_confirm)
+        .service(totp_disable)
+        .service(recovery_codes)
+        .service(refresh)
+        .service(sessions)
+        .service(revoke_session)
+        .service(revoke_sessions)
         .service(enabled_methods)
         .service(unlink_method)
         .service(oauth_login)
@@ -25,23 +40,263 @@ pub fn get_routes() -> Scope {
 }
 
 /// Login with email and password.
+/// This starts a new session, the returned token is short-lived and the refresh token is used to get a new one.
 #[utoipa::path(
     context_path = "/api/auth",
     tag = "authentication",
     responses(
         (status = 200, body = TokenResponse),
+        (status = 202, body = TwoFactorChallenge, description = "Two-factor code required"),
         (status = 400, body = MessageResponse, description = "Invalid credentials"),
+        (status = 429, body = MessageResponse, description = "Too many login attempts or the account is locked"),
     ),
     request_body(content = BasicAuthForm)
 )]
 #[post("/basic")]
-async fn basic(service: web::Data<AuthService>, form: web::Json<BasicAuthForm>) -> impl Responder {
+async fn basic(
+    req: HttpRequest,
+    service: web::Data<AuthService>,
+    form: web::Json<BasicAuthForm>,
+) -> impl Responder {
+    match service
+        .password_auth(&form.auth, &form.password, &ClientInfo::from_request(&req))
+        .await
+    {
+        Ok(LoginResult::Token(token)) => HttpResponse::Ok().json(token),
+        Ok(LoginResult::Challenge(challenge)) => HttpResponse::Accepted().json(challenge),
+        Err(e) => e.to_response(),
+    }
+}
+
+/// Complete a login with a TOTP code or recovery code.
+#[utoipa::path(
+    context_path = "/api/auth",
+    tag = "authentication",
+    responses(
+        (status = 200, body = TokenResponse),
+        (status = 400, body = MessageResponse, description = "Invalid code"),
+        (status = 401, body = MessageResponse, description = "Challenge expired"),
+        (status = 429, body = MessageResponse, description = "Too many codes were entered"),
+    ),
+    request_body(content = TwoFactorLoginForm)
+)]
+#[post("/two-factor")]
+async fn two_factor(
+    req: HttpRequest,
+    service: web::Data<AuthService>,
+    form: web::Json<TwoFactorLoginForm>,
+) -> impl Responder {
+    service
+        .complete_challenge(&form.challenge, &form.code, &ClientInfo::from_request(&req))
+        .await
+        .to_response::<TokenResponse>(StatusCode::OK)
+}
+
+/// Start TOTP enrollment.
+/// The returned secret is only used after it is confirmed.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/auth",
+    tag = "authentication",
+    responses(
+        (status = 200, body = TotpEnrollment),
+        (status = 409, body = MessageResponse, description = "Two-factor authentication already enabled"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[post("/totp")]
+async fn totp_enroll(
+    service: web::Data<TwoFactorService>,
+    user: Auth<auth_role::User>,
+) -> impl Responder {
+    service
+        .enroll(&user)
+        .await
+        .to_response::<TotpEnrollment>(StatusCode::OK)
+}
+
+/// Confirm TOTP enrollment with a code, this enables two-factor authentication.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/auth",
+    tag = "authentication",
+    responses(
+        (status = 200, body = RecoveryCodes),
+        (status = 400, body = MessageResponse, description = "Invalid code"),
+    ),
+    request_body(content = TotpCodeForm),
+    security(("apiKey" = [])),
+)]
+#[post("/totp/confirm")]
+async fn totp_confirm(
+    req: HttpRequest,
+    service: web::Data<TwoFactorService>,
+    user: Auth<auth_role::User>,
+    form: web::Json<TotpCodeForm>,
+) -> impl Responder {
+    service
+        .confirm(&user.id, &form.code, &ClientInfo::from_request(&req))
+        .await
+        .to_response::<RecoveryCodes>(StatusCode::OK)
+}
+
+/// Disable two-factor authentication.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/auth",
+    tag = "authentication",
+    responses(
+        (status = 200, body = MessageResponse, description = "Two-factor authentication was disabled"),
+        (status = 400, body = MessageResponse, description = "Invalid code"),
+        (status = 429, body = MessageResponse, description = "Too many codes were entered"),
+    ),
+    request_body(content = TotpCodeForm),
+    security(("apiKey" = [])),
+)]
+#[post("/totp/disable")]
+async fn totp_disable(
+    req: HttpRequest,
+    service: web::Data<TwoFactorService>,
+    user: Auth<auth_role::User>,
+    form: web::Json<TotpCodeForm>,
+) -> impl Responder {
+    service
+        .disable(&user.id, &form.code, &ClientInfo::from_request(&req))
+        .await
+        .to_message_response(StatusCode::OK)
+}
+
+/// Replace all recovery codes, the old codes stop working.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/auth",
+    tag = "authentication",
+    responses(
+        (status = 200, body = RecoveryCodes),
+        (status = 400, body = MessageResponse, description = "Invalid code"),
+        (status = 429, body = MessageResponse, description = "Too many codes were entered"),
+    ),
+    request_body(content = TotpCodeForm),
+    security(("apiKey" = [])),
+)]
+#[post("/totp/recovery")]
+async fn recovery_codes(
+    service: web::Data<TwoFactorService>,
+    user: Auth<auth_role::User>,
+    form: web::Json<TotpCodeForm>,
+) -> impl Responder {
+    service
+        .regenerate_recovery_codes(&user.id, &form.code)
+        .await
+        .to_response::<RecoveryCodes>(StatusCode::OK)
+}
+
+/// Get a new token using a refresh token.
+/// The refresh token is replaced, the old one can't be used again.
+#[utoipa::path(
+    context_path = "/api/auth",
+    tag = "authentication",
+    responses(
+        (status = 200, body = TokenResponse),
+        (status = 401, body = MessageResponse, description = "Invalid refresh token or expired session"),
+    ),
+    request_body(content = RefreshTokenForm)
+)]
+#[post("/refresh")]
+async fn refresh(
+    req: HttpRequest,
+    service: web::Data<AuthService>,
+    form: web::Json<RefreshTokenForm>,
+) -> impl Responder {
     service
-        .password_auth(&form.auth, &form.password)
+        .refresh_session(&form.refresh_token, &ClientInfo::from_request(&req))
         .await
         .to_response::<TokenResponse>(StatusCode::OK)
 }
 
+/// Get all active login sessions of the user.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `true`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/auth",
+    tag = "authentication",
+    responses((status = 200, body = [SessionData])),
+    security(("apiKey" = [])),
+)]
+#[get("/sessions")]
+async fn sessions(
+    service: web::Data<SessionService>,
+    user: Auth<auth_role::User, AllowUnverified, DenyApplication, AllowUnregistered>,
+) -> impl Responder {
+    match service.get_sessions(&user.id).await {
+        Ok(sessions) => HttpResponse::Ok().json(
+            sessions
+                .into_iter()
+                .map(|session| SessionData::new(session, user.session_id.as_deref()))
+                .collect::<Vec<_>>(),
+        ),
+        Err(e) => e.to_response(),
+    }
+}
+
+/// Revoke a login session, this logs out the device using it.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `true`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/auth",
+    tag = "authentication",
+    responses(
+        (status = 200, body = MessageResponse, description = "Session was revoked"),
+        (status = 404, body = MessageResponse, description = "Session not found"),
+    ),
+    params(
+        ("session_id" = str, Path, description = "ID of the session to revoke")
+    ),
+    security(("apiKey" = [])),
+)]
+#[delete("/sessions/{session_id}")]
+async fn revoke_session(
+    service: web::Data<SessionService>,
+    user: Auth<auth_role::User, AllowUnverified, DenyApplication, AllowUnregistered>,
+    session_id: web::Path<String>,
+) -> impl Responder {
+    service
+        .revoke_session(&session_id, &user.id)
+        .await
+        .to_message_response(StatusCode::OK)
+}
+
+/// Revoke every login session except the one making the request.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `true`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/auth",
+    tag = "authentication",
+    responses((status = 200, body = MessageResponse, description = "Sessions were revoked")),
+    security(("apiKey" = [])),
+)]
+#[delete("/sessions")]
+async fn revoke_sessions(
+    service: web::Data<SessionService>,
+    user: Auth<auth_role::User, AllowUnverified, DenyApplication, AllowUnregistered>,
+) -> impl Responder {
+    service
+        .revoke_all(&user.id, user.session_id.as_deref())
+        .await
+        .to_message_response(StatusCode::OK)
+}
+
 /// Get all enabled auth methods for this user.
 #[utoipa::path(
     context_path = "/api/auth",
@@ -71,12 +326,18 @@ async fn enabled_methods(
 )]
 #[post("/unlink")]
 async fn unlink_method(
+    req: HttpRequest,
     service: web::Data<AuthMethodService>,
     user: Auth<auth_role::User, AllowUnverified, DenyApplication, AllowUnregistered>,
     body: web::Json<UnlinkAuthMethod>,
 ) -> impl Responder {
     service
-        .unlink_method(&user.id, body.method.into(), body.password.clone())
+        .unlink_method(
+            &user.id,
+            &body.method,
+            body.password.clone(),
+            &ClientInfo::from_request(&req),
+        )
         .await
         .to_response::<AuthMethods>(StatusCode::OK)
 }
@@ -97,7 +358,10 @@ pub async fn oauth_login(
     query: web::Query<OAuthLoginQuery>,
 ) -> impl Responder {
     let token = match get_token(&req) {
-        Some(v) => match service.validate_jwt(true, &v).await {
+        Some(v) => match service
+            .validate_jwt(true, &v, &ClientInfo::from_request(&req))
+            .await
+        {
             Ok(v) => Some(v.0.id),
             Err(_) => None,
         },
@@ -106,7 +370,7 @@ pub async fn oauth_login(
 
     match service
         .oauth_login(
-            query.provider,
+            query.provider.clone(),
             token,
             query.redirect.clone(),
             query.include_token,
@@ -123,6 +387,11 @@ pub async fn oauth_login(
 #[utoipa::path(
     context_path = "/api/auth",
     tag = "authentication",
+    responses(
+        (status = 200, body = TokenResponse),
+        (status = 202, body = TwoFactorChallenge, description = "Two-factor code required"),
+        (status = 302, description = "Redirect with the token or challenge in the URL fragment"),
+    ),
     request_body(content = OAuthRequest),
     params(
         ("provider" = str, Path, description = "Provider to callback to.")
@@ -130,16 +399,25 @@ pub async fn oauth_login(
 )]
 #[get("/{provider}/callback")]
 pub async fn oauth_callback(
+    req: HttpRequest,
     service: web::Data<AuthService>,
     params: web::Query<OAuthRequest>,
     provider: web::Path<OAuthProvider>,
 ) -> impl Responder {
-    match service.oauth_authenticate(*provider, &params).await {
-        Ok((token, redirect)) => match redirect {
-            Some(redirect) => HttpResponse::Found()
+    match service
+        .oauth_authenticate(
+            provider.into_inner(),
+            &params,
+            &ClientInfo::from_request(&req),
+        )
+        .await
+    {
+        Ok((result, redirect)) => match (redirect, result) {
+            (Some(redirect), _) => HttpResponse::Found()
                 .append_header((header::LOCATION, redirect))
                 .finish(),
-            None => HttpResponse::Ok().json(token),
+            (None, LoginResult::Token(token)) => HttpResponse::Ok().json(token),
+            (None, LoginResult::Challenge(challenge)) => HttpResponse::Accepted().json(challenge),
         },
         Err(e) => e.to_response(),
     }
@@ -1,15 +1,36 @@
-use actix_multipart_extract::Multipart;
-use actix_web::{delete, get, http::StatusCode, post, web, HttpResponse, Responder, Scope};
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_files::HttpRange;
+use actix_multipart::Multipart;
+use actix_web::{
+    body::SizedStream,
+    delete, get,
+    http::{
+        header::{
+            self, Charset, ContentDisposition, ContentRange, ContentRangeSpec, ContentType,
+            DispositionParam, DispositionType, ETag, EntityTag, ExtendedValue, HttpDate,
+            IfModifiedSince, IfNoneMatch, IfRange, LastModified,
+        },
+        StatusCode,
+    },
+    post, put, web, HttpMessage, HttpRequest, HttpResponse, Responder, Scope,
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
 
 use crate::services::ToPageResponse;
 use crate::{
-    internal::auth::{auth_role, AllowApplication, Auth, DenyUnverified},
+    database::entity::sea_orm_active_enums::{Role, Visibility},
+    internal::auth::{app_scope, auth_role, AllowUnverified, Auth, DenyUnverified},
     models::{
-        BatchDeleteRequest, BatchDeleteResponse, FileData, FileQuery, FileStats, UploadConflict,
-        UploadFile,
+        BatchDeleteRequest, BatchDeleteResponse, ContentQuery, FileData, FileLink, FileLinkQuery,
+        FileQuery, FileStats, FileVisibilityForm, MessageResponse, UploadConflict, UploadQuery,
     },
     services::{
-        file::{FileService, UploadResult},
+        file::{FileService, ObjectRange, ObjectStream, UploadResult},
         ToMessageResponse, ToResponse,
     },
 };
@@ -18,6 +39,9 @@ pub fn get_routes() -> Scope {
     web::scope("/file")
         .service(stats)
         .service(list)
+        .service(content)
+        .service(link)
+        .service(set_visibility)
         .service(info)
         .service(upload)
         .service(delete_files)
@@ -27,43 +51,118 @@ pub fn get_routes() -> Scope {
 /// Upload a file
 /// - Minimum required role: `user`
 /// - Allow unverified users: `false`
-/// - Application token allowed: `true`
+/// - Application token allowed: `true` (`file:upload` scope)
 #[utoipa::path(
     context_path = "/api/file",
     tag = "file",
     responses(
         (status = 200, body = FileData),
-        (status = 409, body = MessageResponse, description = "File already uploaded"),
+        (status = 400, body = MessageResponse, description = "Invalid expiration"),
+        (status = 409, body = UploadConflict, description = "File already uploaded, unless `returnExisting` is set"),
         (status = 413, body = MessageResponse, description = "File too large")
     ),
+    params(UploadQuery),
     security(("apiKey" = [])),
     request_body(content = UploadFile, content_type = "multipart/form-data")
 )]
 #[post("")]
 async fn upload(
     service: web::Data<FileService>,
-    user: Auth<auth_role::User, DenyUnverified, AllowApplication>,
-    file: Multipart<UploadFile>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileUpload>,
+    query: web::Query<UploadQuery>,
+    mut payload: Multipart,
 ) -> impl Responder {
-    match service
-        .upload_file(&user.id, &file.upload_file.name, &file.upload_file.bytes)
-        .await
-    {
-        Ok(v) => match v {
-            UploadResult::Success(file) => HttpResponse::Ok().json(file),
-            UploadResult::Conflict(file) => HttpResponse::Conflict().json(UploadConflict {
-                message: "File was already uploaded".into(),
-                file,
-            }),
-        },
-        Err(e) => e.to_response(),
+    let visibility = match &query.visibility {
+        Some(v) => Visibility::from(v.clone()),
+        None => Visibility::Public,
+    };
+
+    // Application defaults take priority over user defaults.
+    let expiration = query.expiration.or(user
+        .application
+        .as_ref()
+        .and_then(|application| application.default_expiration)
+        .or(user.default_expiration)
+        .map(|v| v as u64));
+
+    while let Some(field) = payload.next().await {
+        let field = match field {
+            Ok(v) => v,
+            Err(_) => return MessageResponse::bad_request().http_response(),
+        };
+
+        // Other fields are skipped.
+        if field.content_disposition().get_name() != Some("uploadFile") {
+            continue;
+        }
+
+        let name = match field.content_disposition().get_filename() {
+            Some(v) => v.to_owned(),
+            None => return MessageResponse::bad_request().http_response(),
+        };
+
+        let application_id = user.application.as_ref().map(|v| v.id.as_str());
+
+        return match service
+            .upload_file(
+                &user,
+                application_id,
+                &name,
+                visibility,
+                expiration,
+                body_stream(field),
+            )
+            .await
+        {
+            Ok(v) => match v {
+                UploadResult::Success(file) => HttpResponse::Ok().json(file),
+                UploadResult::Conflict(file) if query.return_existing.unwrap_or(false) => {
+                    HttpResponse::Ok().json(file)
+                }
+                UploadResult::Conflict(file) => HttpResponse::Conflict().json(UploadConflict {
+                    message: "File was already uploaded".into(),
+                    file,
+                }),
+            },
+            Err(e) => e.to_response(),
+        };
     }
+
+    MessageResponse::bad_request().http_response()
+}
+
+/// Convert a request body or multipart field to an [`ObjectStream`].
+///
+/// Request bodies can't be sent between threads, so the body is read on the current worker
+/// and passed through a channel. The channel is bounded so the upload is only read as fast as storage accepts it.
+pub(crate) fn body_stream<S, E>(mut body: S) -> ObjectStream
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin + 'static,
+    E: Display,
+{
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+
+    actix_web::rt::spawn(async move {
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| anyhow::anyhow!(e.to_string()));
+            let failed = chunk.is_err();
+
+            // Stop reading if the receiver is gone or the body failed.
+            if tx.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    Box::pin(futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }))
 }
 
 /// Get file stats for user
 /// - Minimum required role: `user`
 /// - Allow unverified users: `false`
-/// - Application token allowed: `true`
+/// - Application token allowed: `true` (`file:read` scope)
 #[utoipa::path(
     context_path = "/api/file",
     tag = "file",
@@ -73,10 +172,10 @@ async fn upload(
 #[get("/stats")]
 async fn stats(
     service: web::Data<FileService>,
-    user: Auth<auth_role::User, DenyUnverified, AllowApplication>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileRead>,
 ) -> impl Responder {
     service
-        .user_stats(&user.id)
+        .user_stats(&user)
         .await
         .to_response::<FileStats>(StatusCode::OK)
 }
@@ -84,7 +183,7 @@ async fn stats(
 /// Get a paginated list of files
 /// - Minimum required role: `user`
 /// - Allow unverified users: `false`
-/// - Application token allowed: `true`
+/// - Application token allowed: `true` (`file:read` scope)
 #[utoipa::path(
     context_path = "/api/file",
     tag = "file",
@@ -103,7 +202,7 @@ async fn stats(
 async fn list(
     service: web::Data<FileService>,
     page_number: web::Path<usize>,
-    user: Auth<auth_role::User, DenyUnverified, AllowApplication>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileRead>,
     query: web::Query<FileQuery>,
 ) -> impl Responder {
     service
@@ -120,7 +219,7 @@ async fn list(
 /// Get file data by ID
 /// - Minimum required role: `user`
 /// - Allow unverified users: `false`
-/// - Application token allowed: `true`
+/// - Application token allowed: `true` (`file:read` scope)
 #[utoipa::path(
     context_path = "/api/file", 
     tag = "file",
@@ -138,7 +237,7 @@ async fn list(
 async fn info(
     service: web::Data<FileService>,
     file_id: web::Path<String>,
-    user: Auth<auth_role::User, DenyUnverified, AllowApplication>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileRead>,
 ) -> impl Responder {
     service
         .get_file(&file_id, Some(&user.id))
@@ -146,10 +245,281 @@ async fn info(
         .to_response::<FileData>(StatusCode::OK)
 }
 
+/// Get file contents
+/// Supports range requests and conditional requests using `ETag` and `Last-Modified`.
+/// The `ETag` of a file is its SHA-256 hash.
+///
+/// Private files require the owner's token or a signed link.
+#[utoipa::path(
+    context_path = "/api/file",
+    tag = "file",
+    responses(
+        (status = 200, description = "File contents"),
+        (status = 206, description = "Requested range of the file contents"),
+        (status = 304, description = "File was not modified"),
+        (status = 403, body = MessageResponse, description = "Access denied"),
+        (status = 404, body = MessageResponse, description = "File not found"),
+        (status = 416, description = "Requested range is not satisfiable")
+    ),
+    params(
+        ("file_id" = u64, Path, description = "File ID"),
+        ContentQuery
+    ),
+)]
+#[get("/{file_id}/content")]
+async fn content(
+    req: HttpRequest,
+    service: web::Data<FileService>,
+    file_id: web::Path<String>,
+    query: web::Query<ContentQuery>,
+    user: Option<Auth<auth_role::User, AllowUnverified, app_scope::FileRead>>,
+) -> impl Responder {
+    let file = match service.file_by_id(&file_id).await {
+        Ok(v) => v,
+        Err(e) => return e.to_response(),
+    };
+
+    if file.visibility == Visibility::Private {
+        let owner = match &user {
+            Some(user) => user.id == file.uploader || user.role == Role::Admin,
+            None => false,
+        };
+
+        let signed = match (query.expires, &query.signature) {
+            (Some(expires), Some(signature)) => service.verify_link(&file.name, expires, signature),
+            _ => false,
+        };
+
+        if !owner && !signed {
+            return MessageResponse::new(StatusCode::FORBIDDEN, "You can not access this file")
+                .http_response();
+        }
+    }
+
+    let etag = EntityTag::new_strong(file.hash.to_owned());
+    let last_modified = HttpDate::from(SystemTime::from(file.uploaded));
+
+    if is_not_modified(&req, &etag, last_modified) {
+        return HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .insert_header(LastModified(last_modified))
+            .finish();
+    }
+
+    let size = file.size as u64;
+
+    let range = match requested_range(&req, &etag, last_modified) {
+        Some(header) if size > 0 => match HttpRange::parse(header, size) {
+            // Multiple ranges would require a multipart response, only the first one is served.
+            Ok(ranges) => ranges.first().map(|range| ObjectRange {
+                start: range.start,
+                length: range.length,
+            }),
+            Err(_) => {
+                return HttpResponse::RangeNotSatisfiable()
+                    .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                        range: None,
+                        instance_length: Some(size),
+                    }))
+                    .finish()
+            }
+        },
+        _ => None,
+    };
+
+    let mime = match std::path::Path::new(&file.original_name).extension() {
+        Some(ext) => actix_files::file_extension_to_mime(&ext.to_string_lossy()),
+        None => mime::APPLICATION_OCTET_STREAM,
+    };
+
+    let mut response = match range {
+        Some(range) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header(ContentRange(ContentRangeSpec::Bytes {
+                range: Some((range.start, range.start + range.length - 1)),
+                instance_length: Some(size),
+            }));
+            response
+        }
+        None => HttpResponse::Ok(),
+    };
+
+    response
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified))
+        .insert_header(content_disposition(
+            &file.original_name,
+            &mime,
+            query.download.unwrap_or(false),
+        ))
+        .insert_header(ContentType(mime))
+        // Browsers must not guess another type or run anything in uploaded content.
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((header::CONTENT_SECURITY_POLICY, "sandbox"));
+
+    match service.get_content(&file, range).await {
+        Ok(stream) => response.body(SizedStream::new(
+            range.map(|range| range.length).unwrap_or(size),
+            stream,
+        )),
+        Err(e) => e.to_response(),
+    }
+}
+
+/// Create a signed link to a file which expires
+/// Links can be used to share private files.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `true` (`file:read` scope)
+#[utoipa::path(
+    context_path = "/api/file",
+    tag = "file",
+    responses(
+        (status = 200, body = FileLink),
+        (status = 400, body = MessageResponse, description = "Invalid expiry"),
+        (status = 403, body = MessageResponse, description = "Access denied"),
+        (status = 404, body = MessageResponse, description = "File not found")
+    ),
+    params(
+        ("file_id" = u64, Path, description = "File ID"),
+        FileLinkQuery
+    ),
+    security(("apiKey" = [])),
+)]
+#[get("/{file_id}/link")]
+async fn link(
+    service: web::Data<FileService>,
+    file_id: web::Path<String>,
+    query: web::Query<FileLinkQuery>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileRead>,
+) -> impl Responder {
+    service
+        .file_link(
+            &file_id,
+            Some(&user.id),
+            Duration::from_secs(query.expires_in.unwrap_or(60 * 60)),
+        )
+        .await
+        .to_response::<FileLink>(StatusCode::OK)
+}
+
+/// Update who can access a file
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `true` (`file:write` scope)
+#[utoipa::path(
+    context_path = "/api/file",
+    tag = "file",
+    responses(
+        (status = 200, body = FileData),
+        (status = 403, body = MessageResponse, description = "Access denied"),
+        (status = 404, body = MessageResponse, description = "File not found")
+    ),
+    params(
+        ("file_id" = u64, Path, description = "File ID"),
+    ),
+    request_body(content = FileVisibilityForm),
+    security(("apiKey" = [])),
+)]
+#[put("/{file_id}/visibility")]
+async fn set_visibility(
+    service: web::Data<FileService>,
+    file_id: web::Path<String>,
+    form: web::Json<FileVisibilityForm>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileWrite>,
+) -> impl Responder {
+    service
+        .set_visibility(
+            &file_id,
+            Some(&user.id),
+            Visibility::from(form.visibility.clone()),
+        )
+        .await
+        .to_response::<FileData>(StatusCode::OK)
+}
+
+/// Check `If-None-Match` and `If-Modified-Since` headers.
+/// `If-Modified-Since` is ignored if `If-None-Match` is present.
+fn is_not_modified(req: &HttpRequest, etag: &EntityTag, last_modified: HttpDate) -> bool {
+    match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(items)) => items.iter().any(|item| item.weak_eq(etag)),
+        None => match req.get_header::<IfModifiedSince>() {
+            Some(IfModifiedSince(since)) => not_after(last_modified, since),
+            None => false,
+        },
+    }
+}
+
+/// Get the `Range` header if the range applies to the current file.
+/// A range with a stale `If-Range` condition is ignored and the full file is sent instead.
+fn requested_range<'a>(
+    req: &'a HttpRequest,
+    etag: &EntityTag,
+    last_modified: HttpDate,
+) -> Option<&'a str> {
+    let header = req.headers().get(header::RANGE)?.to_str().ok()?;
+
+    let fresh = match req.get_header::<IfRange>() {
+        Some(IfRange::EntityTag(tag)) => tag.strong_eq(etag),
+        Some(IfRange::Date(date)) => not_after(last_modified, date),
+        None => true,
+    };
+
+    fresh.then(|| header)
+}
+
+/// Compare HTTP dates with second precision.
+fn not_after(date: HttpDate, other: HttpDate) -> bool {
+    let date = SystemTime::from(date).duration_since(UNIX_EPOCH);
+    let other = SystemTime::from(other).duration_since(UNIX_EPOCH);
+
+    match (date, other) {
+        (Ok(date), Ok(other)) => date.as_secs() <= other.as_secs(),
+        _ => false,
+    }
+}
+
+/// Build the `Content-Disposition` header for a file.
+/// Raster images, audio and video are sent inline unless a download is requested.
+/// Everything else is downloaded, HTML or SVG sent inline could run scripts on this origin.
+fn content_disposition(name: &str, mime: &mime::Mime, download: bool) -> ContentDisposition {
+    let inline = match mime.type_() {
+        mime::AUDIO | mime::VIDEO => true,
+        mime::IMAGE => matches!(
+            mime.subtype().as_str(),
+            "png" | "jpeg" | "gif" | "webp" | "bmp" | "avif"
+        ),
+        _ => false,
+    };
+
+    let disposition = if inline && !download {
+        DispositionType::Inline
+    } else {
+        DispositionType::Attachment
+    };
+
+    let mut parameters = vec![DispositionParam::Filename(name.to_owned())];
+
+    if !name.is_ascii() {
+        parameters.push(DispositionParam::FilenameExt(ExtendedValue {
+            charset: Charset::Ext("UTF-8".into()),
+            language_tag: None,
+            value: name.as_bytes().to_vec(),
+        }));
+    }
+
+    ContentDisposition {
+        disposition,
+        parameters,
+    }
+}
+
 /// Delete file data by ID.
 /// - Minimum required role: `user`
 /// - Allow unverified users: `false`
-/// - Application token allowed: `true`
+/// - Application token allowed: `true` (`file:delete` scope)
 #[utoipa::path(
     context_path = "/api/file",
     tag = "file",
@@ -167,10 +537,14 @@ async fn info(
 async fn delete_file(
     service: web::Data<FileService>,
     file_id: web::Path<String>,
-    user: Auth<auth_role::User, DenyUnverified, AllowApplication>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileDelete>,
 ) -> impl Responder {
     service
-        .delete_file(&file_id, Some(&user.id))
+        .delete_file(
+            &file_id,
+            Some(&user.id),
+            user.application.as_ref().map(|v| v.id.as_str()),
+        )
         .await
         .to_message_response(StatusCode::OK)
 }
@@ -179,7 +553,7 @@ async fn delete_file(
 /// This will ignore any invalid IDs.
 /// - Minimum required role: `user`
 /// - Allow unverified users: `false`
-/// - Application token allowed: `true`
+/// - Application token allowed: `true` (`file:delete` scope)
 #[utoipa::path(
     context_path = "/api/file",
     tag = "file",
@@ -193,10 +567,14 @@ async fn delete_file(
 async fn delete_files(
     service: web::Data<FileService>,
     body: web::Json<BatchDeleteRequest>,
-    user: Auth<auth_role::User, DenyUnverified, AllowApplication>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileDelete>,
 ) -> impl Responder {
     service
-        .delete_batch(&body.ids, Some(&user.id))
+        .delete_batch(
+            &body.ids,
+            Some(&user.id),
+            user.application.as_ref().map(|v| v.id.as_str()),
+        )
         .await
         .to_response::<BatchDeleteResponse>(StatusCode::OK)
 }
@@ -1,8 +1,9 @@
 use crate::{
-    database::entity::{files, settings},
-    models::{AppInfo, OAuthProviders},
+    database::entity::files,
+    models::{AppInfo, OAuthProviders, OidcProviderInfo},
     services::{
         auth::{oauth::OAuthProvider, AuthService},
+        settings::SettingsService,
         user::UserService,
         ServiceError,
     },
@@ -14,7 +15,9 @@ pub mod admin;
 pub mod application;
 pub mod auth;
 pub mod file;
+pub mod upload;
 pub mod user;
+pub mod webhook;
 
 pub fn get_routes() -> Scope {
     web::scope("").service(info)
@@ -32,39 +35,33 @@ pub fn get_routes() -> Scope {
 async fn info(
     user_service: web::Data<UserService>,
     auth_service: web::Data<AuthService>,
+    settings_service: web::Data<SettingsService>,
     database: web::Data<DatabaseConnection>,
 ) -> impl Responder {
-    match settings::Entity::find_by_id(true)
-        .one(database.as_ref())
-        .await
-        .map_err(|e| ServiceError::DbErr(e))
-    {
-        Ok(settings) => {
-            let settings = settings.unwrap();
-            HttpResponse::Ok().json(AppInfo::new(
-                settings::Model {
-                    one_row_enforce: true,
-                    app_name: settings.app_name,
-                    app_description: settings.app_description,
-                    color: settings.color,
-                },
-                user_service.invite_only(),
-                user_service.smtp_enabled(),
-                match files::Entity::find()
-                    .count(database.as_ref())
-                    .await
-                    .map_err(|e| ServiceError::DbErr(e))
-                {
-                    Ok(v) => v,
-                    Err(e) => return e.to_response(),
-                },
-                OAuthProviders {
-                    google: auth_service.oauth_enabled(OAuthProvider::Google),
-                    github: auth_service.oauth_enabled(OAuthProvider::Github),
-                    discord: auth_service.oauth_enabled(OAuthProvider::Discord),
-                },
-            ))
-        }
+    match settings_service.get_settings().await {
+        Ok(settings) => HttpResponse::Ok().json(AppInfo::new(
+            settings,
+            user_service.invite_only(),
+            user_service.smtp_enabled(),
+            match files::Entity::find()
+                .count(database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))
+            {
+                Ok(v) => v,
+                Err(e) => return e.to_response(),
+            },
+            OAuthProviders {
+                google: auth_service.oauth_enabled(OAuthProvider::Google),
+                github: auth_service.oauth_enabled(OAuthProvider::Github),
+                discord: auth_service.oauth_enabled(OAuthProvider::Discord),
+                oidc: auth_service
+                    .oidc_providers()
+                    .into_iter()
+                    .map(|(name, display_name)| OidcProviderInfo { name, display_name })
+                    .collect(),
+            },
+        )),
         Err(e) => e.to_response(),
     }
 }
@@ -0,0 +1,362 @@
+//! Resumable uploads using the [tus](https://tus.io/protocols/resumable-upload.html) protocol.
+//!
+//! The core protocol and the `creation` extension are supported.
+//! Completed uploads are stored like files uploaded with `POST /api/file`.
+
+use actix_web::{
+    head,
+    http::header::{HeaderName, HeaderValue, CONTENT_TYPE},
+    options, patch, post, web, HttpRequest, HttpResponse, HttpResponseBuilder, Responder, Scope,
+};
+
+use super::file::body_stream;
+use crate::{
+    database::entity::sea_orm_active_enums::Visibility,
+    internal::auth::{app_scope, auth_role, Auth, DenyUnverified},
+    services::{
+        file::UploadResult,
+        upload::{UploadProgress, UploadService},
+        ServiceError,
+    },
+};
+
+/// Protocol version which is supported.
+const TUS_VERSION: &str = "1.0.0";
+
+/// Content type of `PATCH` requests.
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// Header with the ID of the file once an upload is complete.
+const FILE_ID_HEADER: &str = "X-Backpack-File-Id";
+
+pub fn get_routes() -> Scope {
+    web::scope("/upload")
+        .service(discover)
+        .service(create)
+        .service(upload_offset)
+        .service(append)
+}
+
+/// Get supported tus versions and extensions
+#[utoipa::path(
+    context_path = "/api/upload",
+    tag = "upload",
+    responses((status = 204, description = "Supported versions and extensions are in the `Tus-Version`, `Tus-Extension` and `Tus-Max-Size` headers")),
+)]
+#[options("")]
+async fn discover(service: web::Data<UploadService>) -> impl Responder {
+    let max_size = match service.max_size().await {
+        Ok(v) => v,
+        Err(e) => return tus_error(e),
+    };
+
+    tus_response(HttpResponse::NoContent())
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", "creation"))
+        .insert_header(("Tus-Max-Size", max_size))
+        .finish()
+}
+
+/// Create an upload
+/// `Upload-Metadata` can contain the base64 encoded `filename`, `visibility` and `expiration` of the file.
+/// The filename may also be sent as `name`.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `true` (`file:upload` scope)
+#[utoipa::path(
+    context_path = "/api/upload",
+    tag = "upload",
+    responses(
+        (status = 201, description = "Upload was created, its URL is in the `Location` header"),
+        (status = 400, body = MessageResponse, description = "Invalid metadata or expiration"),
+        (status = 412, description = "Unsupported tus version"),
+        (status = 413, body = MessageResponse, description = "File too large"),
+        (status = 429, body = MessageResponse, description = "Too many unfinished uploads")
+    ),
+    params(
+        ("Tus-Resumable" = String, Header, description = "Must be `1.0.0`"),
+        ("Upload-Length" = u64, Header, description = "Size of the file"),
+        ("Upload-Metadata" = String, Header, description = "Comma separated pairs of keys and base64 encoded values"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[post("")]
+async fn create(
+    service: web::Data<UploadService>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileUpload>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Some(response) = check_version(&req) {
+        return response;
+    }
+
+    let length = match header_u64(&req, "Upload-Length") {
+        Some(v) => v,
+        None => return tus_error(ServiceError::InvalidData("Invalid Upload-Length".into())),
+    };
+
+    let metadata = match req.headers().get("Upload-Metadata") {
+        Some(v) => match v.to_str().ok().and_then(parse_metadata) {
+            Some(v) => v,
+            None => return tus_error(ServiceError::InvalidData("Invalid Upload-Metadata".into())),
+        },
+        None => Vec::new(),
+    };
+
+    let value = |key: &str| {
+        metadata
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    };
+
+    let filename = match value("filename").or(value("name")) {
+        Some(v) if !v.is_empty() => v,
+        _ => return tus_error(ServiceError::InvalidData("Missing filename".into())),
+    };
+
+    let visibility = match value("visibility") {
+        None | Some("public") => Visibility::Public,
+        Some("private") => Visibility::Private,
+        Some("unlisted") => Visibility::Unlisted,
+        Some(_) => return tus_error(ServiceError::InvalidData("Invalid visibility".into())),
+    };
+
+    // Application defaults take priority over user defaults.
+    let expiration = match value("expiration") {
+        Some(v) => match v.parse::<u64>() {
+            Ok(v) => Some(v),
+            Err(_) => return tus_error(ServiceError::InvalidData("Invalid expiration".into())),
+        },
+        None => user
+            .application
+            .as_ref()
+            .and_then(|application| application.default_expiration)
+            .or(user.default_expiration)
+            .map(|v| v as u64),
+    };
+
+    let application_id = user.application.as_ref().map(|v| v.id.as_str());
+
+    match service
+        .create_upload(
+            &user,
+            application_id,
+            filename,
+            visibility,
+            expiration,
+            length,
+        )
+        .await
+    {
+        Ok(upload) => tus_response(HttpResponse::Created())
+            .insert_header(("Location", format!("/api/upload/{}", upload.id)))
+            .finish(),
+        Err(e) => tus_error(e),
+    }
+}
+
+/// Get the offset of an upload
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `true` (`file:upload` scope)
+#[utoipa::path(
+    context_path = "/api/upload",
+    tag = "upload",
+    responses(
+        (status = 200, description = "Offset and length are in the `Upload-Offset` and `Upload-Length` headers"),
+        (status = 404, description = "Upload not found"),
+        (status = 412, description = "Unsupported tus version")
+    ),
+    params(
+        ("upload_id" = str, Path, description = "Upload ID"),
+        ("Tus-Resumable" = String, Header, description = "Must be `1.0.0`"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[head("/{upload_id}")]
+async fn upload_offset(
+    service: web::Data<UploadService>,
+    upload_id: web::Path<String>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileUpload>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Some(response) = check_version(&req) {
+        return response;
+    }
+
+    match service.get_upload(&upload_id, &user.id).await {
+        Ok(upload) => tus_response(HttpResponse::Ok())
+            .insert_header(("Upload-Offset", upload.offset))
+            .insert_header(("Upload-Length", upload.length))
+            .insert_header(("Cache-Control", "no-store"))
+            .finish(),
+        Err(e) => tus_error(e),
+    }
+}
+
+/// Send data to an upload
+/// The file is stored once the upload is complete, its ID is returned in the `X-Backpack-File-Id` header.
+/// If the same file was already uploaded, the ID of the existing file is returned instead.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `true` (`file:upload` scope)
+#[utoipa::path(
+    context_path = "/api/upload",
+    tag = "upload",
+    responses(
+        (status = 204, description = "Data was received, the new offset is in the `Upload-Offset` header"),
+        (status = 400, body = MessageResponse, description = "Data was larger than the upload length"),
+        (status = 404, description = "Upload not found"),
+        (status = 409, body = MessageResponse, description = "Offset does not match or the upload is already receiving data"),
+        (status = 412, description = "Unsupported tus version"),
+        (status = 413, body = MessageResponse, description = "File too large"),
+        (status = 415, description = "Content type is not `application/offset+octet-stream`")
+    ),
+    params(
+        ("upload_id" = str, Path, description = "Upload ID"),
+        ("Tus-Resumable" = String, Header, description = "Must be `1.0.0`"),
+        ("Upload-Offset" = u64, Header, description = "Offset the data starts at"),
+    ),
+    request_body(content = String, content_type = "application/offset+octet-stream"),
+    security(("apiKey" = [])),
+)]
+#[patch("/{upload_id}")]
+async fn append(
+    service: web::Data<UploadService>,
+    upload_id: web::Path<String>,
+    user: Auth<auth_role::User, DenyUnverified, app_scope::FileUpload>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> impl Responder {
+    if let Some(response) = check_version(&req) {
+        return response;
+    }
+
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    if content_type != Some(OFFSET_CONTENT_TYPE) {
+        return tus_response(HttpResponse::UnsupportedMediaType()).finish();
+    }
+
+    let offset = match header_u64(&req, "Upload-Offset") {
+        Some(v) => v,
+        None => return tus_error(ServiceError::InvalidData("Invalid Upload-Offset".into())),
+    };
+
+    match service
+        .append(&upload_id, &user, offset, body_stream(payload))
+        .await
+    {
+        Ok(UploadProgress { offset, file }) => {
+            let mut response = tus_response(HttpResponse::NoContent());
+            response.insert_header(("Upload-Offset", offset));
+
+            if let Some(result) = file {
+                let file = match result {
+                    UploadResult::Success(file) | UploadResult::Conflict(file) => file,
+                };
+                response.insert_header((FILE_ID_HEADER, file.id));
+            }
+
+            response.finish()
+        }
+        Err(e) => tus_error(e),
+    }
+}
+
+/// Respond with `412 Precondition Failed` if the client uses an unsupported tus version.
+fn check_version(req: &HttpRequest) -> Option<HttpResponse> {
+    match req.headers().get("Tus-Resumable") {
+        Some(v) if v == TUS_VERSION => None,
+        _ => Some(
+            HttpResponse::PreconditionFailed()
+                .insert_header(("Tus-Version", TUS_VERSION))
+                .finish(),
+        ),
+    }
+}
+
+/// Start a response with the `Tus-Resumable` header, which is required on every response.
+fn tus_response(mut builder: HttpResponseBuilder) -> HttpResponseBuilder {
+    builder.insert_header(("Tus-Resumable", TUS_VERSION));
+    builder
+}
+
+/// Convert an error to a response with the `Tus-Resumable` header.
+fn tus_error(error: ServiceError) -> HttpResponse {
+    let mut response = error.to_response();
+    response.headers_mut().insert(
+        HeaderName::from_static("tus-resumable"),
+        HeaderValue::from_static(TUS_VERSION),
+    );
+    response
+}
+
+/// Read a header containing a non-negative integer.
+fn header_u64(req: &HttpRequest, name: &str) -> Option<u64> {
+    req.headers().get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// Parse `Upload-Metadata`, a comma separated list of keys and base64 encoded values.
+/// Values may be omitted, which is treated as an empty value.
+fn parse_metadata(header: &str) -> Option<Vec<(String, String)>> {
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, ' ');
+            let key = parts.next()?.to_owned();
+            let value = match parts.next() {
+                Some(v) => String::from_utf8(base64::decode(v.trim()).ok()?).ok()?,
+                None => String::new(),
+            };
+
+            Some((key, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_metadata_pairs() {
+        // "photo.png" and "image/png"
+        let metadata = parse_metadata("filename cGhvdG8ucG5n, filetype aW1hZ2UvcG5n").unwrap();
+
+        assert_eq!(
+            metadata,
+            vec![
+                ("filename".to_owned(), "photo.png".to_owned()),
+                ("filetype".to_owned(), "image/png".to_owned()),
+            ]
+        );
+        assert_eq!(parse_metadata("").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn parses_key_without_value() {
+        let metadata = parse_metadata("is_confidential,filename cGhvdG8ucG5n").unwrap();
+
+        assert_eq!(
+            metadata,
+            vec![
+                ("is_confidential".to_owned(), String::new()),
+                ("filename".to_owned(), "photo.png".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_base64() {
+        assert!(parse_metadata("filename not-base64!").is_none());
+        assert!(parse_metadata("filename cGhvdG8ucG5n,filetype ???").is_none());
+        // Valid base64, but not UTF-8.
+        assert!(parse_metadata("filename //4=").is_none());
+    }
+}
@@ -1,17 +1,21 @@
 use actix_web::{
-    delete, get, http::StatusCode, patch, post, put, web, HttpResponse, Responder, Scope,
+    delete, get, http::StatusCode, patch, post, put, web, HttpRequest, HttpResponse, Responder,
+    Scope,
 };
 
 use crate::{
     database::entity::sea_orm_active_enums::AuthMethod,
     internal::auth::{
-        auth_role, AllowApplication, AllowUnregistered, AllowUnverified, Auth, DenyApplication,
+        app_scope, auth_role, AllowUnregistered, AllowUnverified, Auth, ClientInfo, DenyApplication,
     },
     models::{
-        MessageResponse, RegistrationParams, UpdateUserSettings, UserCreateForm, UserData,
-        UserDeleteForm,
+        AuditLogData, ForgotPasswordForm, MessageResponse, PasswordResetForm, RegistrationParams,
+        RetentionPolicy, UpdateUserSettings, UserCreateForm, UserData, UserDeleteForm,
+    },
+    services::{
+        audit_log::AuditLogService, settings::SettingsService, user::UserService, ToPageResponse,
+        ToResponse,
     },
-    services::{user::UserService, ToResponse},
 };
 
 pub fn get_routes() -> Scope {
@@ -19,16 +23,20 @@ pub fn get_routes() -> Scope {
         .service(create)
         .service(delete)
         .service(settings)
+        .service(retention)
         .service(info)
         .service(resend_verify)
         .service(verify)
+        .service(forgot_password)
+        .service(reset_password)
         .service(register_key)
+        .service(audit)
 }
 
 /// Get current user information
 /// - Minimum required role: `user`
 /// - Allow unverified users: `true`
-/// - Application token allowed: `true`
+/// - Application token allowed: `true` (`user:read` scope)
 #[utoipa::path(
     context_path = "/api/user",
     tag = "user",
@@ -39,11 +47,40 @@ pub fn get_routes() -> Scope {
 )]
 #[get("")]
 async fn info(
-    user: Auth<auth_role::User, AllowUnverified, AllowApplication, AllowUnregistered>,
+    user: Auth<auth_role::User, AllowUnverified, app_scope::UserRead, AllowUnregistered>,
 ) -> impl Responder {
     HttpResponse::Ok().json(UserData::from(user.user))
 }
 
+/// Get a paginated list of security events on your account
+/// These are actions you took, such as logins, and actions admins took on your account.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `true`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/user",
+    tag = "user",
+    responses(
+        (status = 200, body = AuditLogPage),
+        (status = 400, body = MessageResponse, description = "Invalid page number")
+    ),
+    params(
+        ("page_number" = usize, Path, description = "Page to get"),
+    ),
+    security(("apiKey" = []))
+)]
+#[get("/audit/{page_number}")]
+async fn audit(
+    service: web::Data<AuditLogService>,
+    page_number: web::Path<usize>,
+    user: Auth<auth_role::User, AllowUnverified, DenyApplication, AllowUnregistered>,
+) -> impl Responder {
+    service
+        .get_user_page(&user.id, *page_number, 25)
+        .await
+        .to_page_response::<AuditLogData>(StatusCode::OK)
+}
+
 /// Change user settings
 /// - Minimum required role: `user`
 /// - Allow unverified users: `true`
@@ -61,6 +98,7 @@ async fn info(
 )]
 #[put("/settings")]
 async fn settings(
+    req: HttpRequest,
     service: web::Data<UserService>,
     form: web::Json<UpdateUserSettings>,
     user: Auth<auth_role::User, AllowUnverified, DenyApplication, AllowUnregistered>,
@@ -72,11 +110,38 @@ async fn settings(
             form.0.username,
             form.0.new_password,
             form.0.current_password,
+            &ClientInfo::from_request(&req),
         )
         .await
         .to_response::<UserData>(StatusCode::OK)
 }
 
+/// Change the default retention of uploaded files
+/// - Minimum required role: `user`
+/// - Allow unverified users: `true`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/user",
+    tag = "user",
+    responses(
+        (status = 200, body = UserData),
+        (status = 400, body = MessageResponse, description = "Invalid expiration")
+    ),
+    security(("apiKey" = [])),
+    request_body = RetentionPolicy
+)]
+#[put("/retention")]
+async fn retention(
+    service: web::Data<UserService>,
+    form: web::Json<RetentionPolicy>,
+    user: Auth<auth_role::User, AllowUnverified, DenyApplication, AllowUnregistered>,
+) -> impl Responder {
+    service
+        .set_default_expiration(&user, form.default_expiration)
+        .await
+        .to_response::<UserData>(StatusCode::OK)
+}
+
 /// Register account using a registration key.
 /// This is only required on services with `invite_only` enabled.
 #[utoipa::path(
@@ -85,17 +150,19 @@ async fn settings(
     responses(
         (status = 200, body = UserData),
         (status = 400, body = MessageResponse),
+        (status = 429, body = MessageResponse, description = "Too many registration keys were entered"),
     ),
     params(RegistrationParams)
 )]
 #[get("/register")]
 async fn register_key(
+    req: HttpRequest,
     service: web::Data<UserService>,
     user: Auth<auth_role::User, AllowUnverified, DenyApplication, AllowUnregistered>,
     params: web::Query<RegistrationParams>,
 ) -> impl Responder {
     service
-        .register_user(&user, &params.key)
+        .register_user(&user, &params.key, &ClientInfo::from_request(&req))
         .await
         .to_response::<UserData>(StatusCode::OK)
 }
@@ -107,21 +174,40 @@ async fn register_key(
     responses(
         (status = 200, body = UserData),
         (status = 400, body = MessageResponse),
+        (status = 403, body = MessageResponse, description = "Registration is closed"),
         (status = 409, body = MessageResponse)
     ),
     request_body = UserCreateForm
 )]
 #[post("")]
 async fn create(
+    req: HttpRequest,
     service: web::Data<UserService>,
+    settings_service: web::Data<SettingsService>,
     form: web::Json<UserCreateForm>,
 ) -> impl Responder {
+    // Accounts can still be created with a registration key while registration is closed.
+    if form.registration_key.is_none() {
+        match settings_service.get_settings().await {
+            Ok(server_settings) if !server_settings.registration_open => {
+                return MessageResponse::new(
+                    StatusCode::FORBIDDEN,
+                    "Registration is currently closed",
+                )
+                .http_response()
+            }
+            Err(e) => return e.to_response(),
+            _ => {}
+        }
+    }
+
     match service
         .create_user(
             form.0.username,
             form.0.email,
-            (AuthMethod::Password, form.0.password, None),
+            (AuthMethod::Password, form.0.password, None, String::new()),
             form.0.registration_key,
+            &ClientInfo::from_request(&req),
         )
         .await
     {
@@ -171,20 +257,88 @@ async fn resend_verify(
     responses(
         (status = 200, body = MessageResponse),
         (status = 400, body = MessageResponse, description = "Invalid verification code"),
-        (status = 410, body = MessageResponse, description = "SMTP is disabled")
+        (status = 410, body = MessageResponse, description = "SMTP is disabled"),
+        (status = 429, body = MessageResponse, description = "Too many codes were entered")
     ),
     params(
         ("code" = str, Path, description = "Verification code to verify"),
     )
 )]
 #[patch("/verify/{code}")]
-async fn verify(service: web::Data<UserService>, code: web::Path<String>) -> impl Responder {
-    match service.verify_by_code(&code).await {
+async fn verify(
+    req: HttpRequest,
+    service: web::Data<UserService>,
+    code: web::Path<String>,
+) -> impl Responder {
+    match service
+        .verify_by_code(&code, &ClientInfo::from_request(&req))
+        .await
+    {
         Ok(_) => MessageResponse::new(StatusCode::OK, "User has been verified").http_response(),
         Err(e) => e.to_response(),
     }
 }
 
+/// Request a password reset email
+///
+/// The response is the same whether or not an account has the email.
+/// This will be disabled if `smtp` is disabled in server settings
+#[utoipa::path(
+    context_path = "/api/user",
+    tag = "user",
+    responses(
+        (status = 200, body = MessageResponse),
+        (status = 409, body = MessageResponse, description = "SMTP is disabled")
+    ),
+    request_body(content = ForgotPasswordForm),
+)]
+#[post("/password/forgot")]
+async fn forgot_password(
+    service: web::Data<UserService>,
+    form: web::Json<ForgotPasswordForm>,
+) -> impl Responder {
+    match service.request_password_reset(&form.email).await {
+        Ok(_) => MessageResponse::new(
+            StatusCode::OK,
+            "If an account exists with that email, a password reset link was sent to it",
+        )
+        .http_response(),
+        Err(e) => e.to_response(),
+    }
+}
+
+/// Set a new password using a password reset code
+///
+/// Every session of the user is logged out.
+/// This will be disabled if `smtp` is disabled in server settings
+#[utoipa::path(
+    context_path = "/api/user",
+    tag = "user",
+    responses(
+        (status = 200, body = MessageResponse),
+        (status = 400, body = MessageResponse, description = "Invalid reset code or password"),
+        (status = 409, body = MessageResponse, description = "SMTP is disabled"),
+        (status = 429, body = MessageResponse, description = "Too many codes were entered")
+    ),
+    request_body(content = PasswordResetForm),
+)]
+#[post("/password/reset")]
+async fn reset_password(
+    req: HttpRequest,
+    service: web::Data<UserService>,
+    form: web::Json<PasswordResetForm>,
+) -> impl Responder {
+    match service
+        .reset_password(&form.code, &form.password, &ClientInfo::from_request(&req))
+        .await
+    {
+        Ok(_) => {
+            MessageResponse::new(StatusCode::OK, "Your password has been reset").http_response()
+        }
+        Err(e) => e.to_response(),
+    }
+}
+
 /// Delete a user and all files owned by the user
 /// - Minimum required role: `user`
 /// - Allow unverified users: `true`
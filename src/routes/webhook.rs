@@ -0,0 +1,205 @@
+use actix_web::{delete, get, http::StatusCode, post, web, Responder, Scope};
+use sea_orm::{prelude::*, Condition};
+
+use crate::{
+    database::entity::webhooks,
+    internal::auth::{auth_role, Auth},
+    models::webhook::*,
+    services::{
+        prelude::DataService, webhook::WebhookService, ToMessageResponse, ToPageResponse,
+        ToResponse,
+    },
+};
+
+pub fn get_routes() -> Scope {
+    web::scope("/webhook")
+        .service(list)
+        .service(info)
+        .service(create)
+        .service(delete)
+        .service(test)
+        .service(deliveries)
+}
+
+/// Get all webhooks
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/webhook",
+    tag = "webhook",
+    responses((status = 200, body = WebhookPage)),
+    params(
+        ("page_number" = u64, Path, description = "Page to get webhooks by (starts at 1)"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[get("/list/{page_number}")]
+async fn list(
+    service: web::Data<WebhookService>,
+    page_number: web::Path<usize>,
+    user: Auth<auth_role::User>,
+) -> impl Responder {
+    service
+        .get_page(
+            *page_number,
+            10,
+            Some(Condition::all().add(webhooks::Column::UserId.eq(user.id.to_owned()))),
+        )
+        .await
+        .to_page_response::<WebhookData>(StatusCode::OK)
+}
+
+/// Get a webhook
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/webhook",
+    tag = "webhook",
+    responses(
+        (status = 200, body = WebhookData),
+        (status = 404, body = MessageResponse, description = "Webhook not found")
+    ),
+    params(
+        ("webhook_id" = str, Path, description = "Webhook ID"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[get("/{webhook_id}")]
+async fn info(
+    service: web::Data<WebhookService>,
+    webhook_id: web::Path<String>,
+    user: Auth<auth_role::User>,
+) -> impl Responder {
+    service
+        .get_webhook(&webhook_id, &user.id)
+        .await
+        .to_response::<WebhookData>(StatusCode::OK)
+}
+
+/// Create a webhook
+/// The secret used to sign deliveries is only returned here.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/webhook",
+    tag = "webhook",
+    responses(
+        (status = 200, body = WebhookData),
+        (status = 400, body = MessageResponse, description = "Webhook limit reached or invalid URL"),
+        (status = 404, body = MessageResponse, description = "Application not found")
+    ),
+    request_body = WebhookCreate,
+    security(("apiKey" = [])),
+)]
+#[post("")]
+async fn create(
+    service: web::Data<WebhookService>,
+    user: Auth<auth_role::User>,
+    form: web::Json<WebhookCreate>,
+) -> impl Responder {
+    service
+        .create_webhook(
+            &user.id,
+            &form.url,
+            form.events.as_deref().unwrap_or(&WebhookEvent::ALL),
+            form.application_id.as_deref(),
+        )
+        .await
+        .to_response::<WebhookData>(StatusCode::OK)
+}
+
+/// Delete a webhook
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/webhook",
+    tag = "webhook",
+    responses(
+        (status = 200, body = MessageResponse, description = "Webhook was deleted"),
+        (status = 404, body = MessageResponse, description = "Webhook not found")
+    ),
+    params(
+        ("webhook_id" = str, Path, description = "Webhook ID"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[delete("/{webhook_id}")]
+async fn delete(
+    service: web::Data<WebhookService>,
+    webhook_id: web::Path<String>,
+    user: Auth<auth_role::User>,
+) -> impl Responder {
+    service
+        .delete(
+            webhook_id.to_string(),
+            false,
+            Some(Condition::all().add(webhooks::Column::UserId.eq(user.id.to_owned()))),
+        )
+        .await
+        .to_message_response(StatusCode::OK)
+}
+
+/// Send a `ping` event to a webhook
+/// The request waits for the receiver to respond, pings are not retried.
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/webhook",
+    tag = "webhook",
+    responses(
+        (status = 200, body = WebhookDeliveryData, description = "Result of the delivery"),
+        (status = 404, body = MessageResponse, description = "Webhook not found")
+    ),
+    params(
+        ("webhook_id" = str, Path, description = "Webhook ID"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[post("/{webhook_id}/test")]
+async fn test(
+    service: web::Data<WebhookService>,
+    webhook_id: web::Path<String>,
+    user: Auth<auth_role::User>,
+) -> impl Responder {
+    service
+        .test_webhook(&webhook_id, &user.id)
+        .await
+        .to_response::<WebhookDeliveryData>(StatusCode::OK)
+}
+
+/// Get the delivery log of a webhook
+/// - Minimum required role: `user`
+/// - Allow unverified users: `false`
+/// - Application token allowed: `false`
+#[utoipa::path(
+    context_path = "/api/webhook",
+    tag = "webhook",
+    responses(
+        (status = 200, body = WebhookDeliveryPage),
+        (status = 400, body = MessageResponse, description = "Invalid page number"),
+        (status = 404, body = MessageResponse, description = "Webhook not found")
+    ),
+    params(
+        ("webhook_id" = str, Path, description = "Webhook ID"),
+        ("page_number" = u64, Path, description = "Page to get deliveries by (starts at 1)"),
+    ),
+    security(("apiKey" = [])),
+)]
+#[get("/{webhook_id}/deliveries/{page_number}")]
+async fn deliveries(
+    service: web::Data<WebhookService>,
+    path: web::Path<(String, usize)>,
+    user: Auth<auth_role::User>,
+) -> impl Responder {
+    let (webhook_id, page_number) = path.into_inner();
+
+    service
+        .get_delivery_page(&webhook_id, &user.id, page_number, 25)
+        .await
+        .to_page_response::<WebhookDeliveryData>(StatusCode::OK)
+}
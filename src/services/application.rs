@@ -1,31 +1,52 @@
 use chrono::Utc;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection,
+    EntityTrait, IntoActiveModel, QueryFilter, Set,
 };
 
 use super::{
+    audit_log::{AuditLogService, AuditTarget},
     auth::AuthService,
+    file::validate_expiration,
     prelude::{data_service, DataService},
+    webhook::WebhookService,
     ServiceError, ServiceResult,
 };
 use crate::{
     database::entity::applications,
-    models::{ApplicationData, TokenResponse},
+    internal::auth::ClientInfo,
+    models::{
+        ApplicationData, ApplicationScope, AuditAction, SharexUploader, TokenResponse,
+        UploaderConfig, UploaderTool, WebhookEvent,
+    },
 };
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 pub struct ApplicationService {
     database: Arc<DatabaseConnection>,
     auth_service: Arc<AuthService>,
+    audit_log_service: Arc<AuditLogService>,
+    webhook_service: Arc<WebhookService>,
+    /// Used in exported uploader configs.
+    api_url: String,
 }
 
 data_service!(ApplicationService, applications);
 
 impl ApplicationService {
-    pub fn new(database: Arc<DatabaseConnection>, auth_service: Arc<AuthService>) -> Self {
+    pub fn new(
+        database: Arc<DatabaseConnection>,
+        auth_service: Arc<AuthService>,
+        audit_log_service: Arc<AuditLogService>,
+        webhook_service: Arc<WebhookService>,
+        api_url: &str,
+    ) -> Self {
         Self {
             database,
             auth_service,
+            audit_log_service,
+            webhook_service,
+            api_url: api_url.to_owned(),
         }
     }
 
@@ -38,6 +59,172 @@ impl ApplicationService {
         &self,
         id: &str,
         user_id: Option<&str>,
+    ) -> ServiceResult<TokenResponse> {
+        let application = self.get_application(id, user_id).await?;
+        self.application_token(&application)
+    }
+
+    /// Get an application.
+    ///
+    /// # Arguments
+    /// * `id` - ID of the application.
+    /// * `user_id` - User who owns the application, if there is a mismatch this will return not found.
+    async fn get_application(
+        &self,
+        id: &str,
+        user_id: Option<&str>,
+    ) -> ServiceResult<applications::Model> {
+        let mut condition = Condition::all().add(applications::Column::Id.eq(id.to_owned()));
+
+        if let Some(user_id) = user_id {
+            condition = condition.add(applications::Column::UserId.eq(user_id));
+        }
+
+        self.by_condition(condition).await
+    }
+
+    /// Create a new token for an application.
+    fn application_token(&self, application: &applications::Model) -> ServiceResult<TokenResponse> {
+        self.auth_service
+            .new_jwt(&application.user_id, Some(application), None)
+    }
+
+    /// Export a config for an uploader tool.
+    /// The config contains a new token of the application, which must have the `file:upload` scope.
+    ///
+    /// # Arguments
+    /// * `id` - ID of the application.
+    /// * `user_id` - User who owns the application, if there is a mismatch this will return not found.
+    /// * `tool` - Tool to export the config for.
+    pub async fn uploader_config(
+        &self,
+        id: &str,
+        user_id: Option<&str>,
+        tool: UploaderTool,
+    ) -> ServiceResult<UploaderConfig> {
+        let application = self.get_application(id, user_id).await?;
+
+        if !ApplicationScope::parse_list(&application.scopes)
+            .contains(&ApplicationScope::FileUpload)
+        {
+            return Err(ServiceError::InvalidData(format!(
+                "Application needs the {} scope to upload files",
+                ApplicationScope::FileUpload
+            )));
+        }
+
+        let token = self.application_token(&application)?.token;
+        let upload_url = format!("{}/api/file", self.api_url);
+
+        Ok(match tool {
+            UploaderTool::Sharex => UploaderConfig {
+                filename: "backpack.sxcu".into(),
+                content_type: "application/json",
+                content: serde_json::to_string_pretty(&SharexUploader {
+                    version: "14.0.0".into(),
+                    name: format!("Backpack ({})", application.name),
+                    destination_type: "ImageUploader, TextUploader, FileUploader".into(),
+                    request_method: "POST".into(),
+                    // ShareX can't read the URL from an error response,
+                    // so files which were already uploaded are returned as a success.
+                    request_url: format!("{}?returnExisting=true", upload_url),
+                    headers: HashMap::from([(
+                        "Authorization".to_owned(),
+                        format!("Bearer {}", token),
+                    )]),
+                    body: "MultipartFormData".into(),
+                    file_form_name: "uploadFile".into(),
+                    url: "{json:url}".into(),
+                    thumbnail_url: "{json:thumbnailUrl}".into(),
+                    error_message: "{json:message}".into(),
+                })
+                .map_err(|e| ServiceError::ServerError(e.into()))?,
+            },
+            UploaderTool::Curl => UploaderConfig {
+                filename: "backpack-upload.sh".into(),
+                content_type: "text/x-shellscript",
+                content: curl_script(&application.name, &token, &upload_url),
+            },
+        })
+    }
+
+    /// Set the default time until files uploaded by an application expire.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the application.
+    /// * `user_id` - User who owns the application, if there is a mismatch this will return not found.
+    /// * `expiration` - Seconds until files expire, the user's default is used if this is [`None`].
+    pub async fn set_default_expiration(
+        &self,
+        id: &str,
+        user_id: Option<&str>,
+        expiration: Option<u64>,
+    ) -> ServiceResult<ApplicationData> {
+        let expiration = match expiration {
+            Some(v) => Some(validate_expiration(v)?),
+            None => None,
+        };
+
+        let mut condition = Condition::all().add(applications::Column::Id.eq(id.to_owned()));
+
+        if let Some(user_id) = user_id {
+            condition = condition.add(applications::Column::UserId.eq(user_id));
+        }
+
+        let mut application = self.by_condition(condition).await?.into_active_model();
+        application.default_expiration = Set(expiration);
+
+        Ok(ApplicationData::from(
+            application
+                .update(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?,
+        ))
+    }
+
+    /// Change what tokens of an application can be used for.
+    /// Existing tokens lose removed scopes immediately but only new tokens get added scopes.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the application.
+    /// * `user_id` - User who owns the application, if there is a mismatch this will return not found.
+    /// * `scopes` - New scopes of the application.
+    pub async fn set_scopes(
+        &self,
+        id: &str,
+        user_id: Option<&str>,
+        scopes: &[ApplicationScope],
+    ) -> ServiceResult<ApplicationData> {
+        let mut condition = Condition::all().add(applications::Column::Id.eq(id.to_owned()));
+
+        if let Some(user_id) = user_id {
+            condition = condition.add(applications::Column::UserId.eq(user_id));
+        }
+
+        let mut application = self.by_condition(condition).await?.into_active_model();
+        application.scopes = Set(ApplicationScope::format_list(scopes));
+
+        Ok(ApplicationData::from(
+            application
+                .update(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?,
+        ))
+    }
+
+    /// Rotate the token of an application.
+    /// Every token created before the rotation is revoked.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the application.
+    /// * `user_id` - User who owns the application, if there is a mismatch this will return not found.
+    pub async fn rotate_token(
+        &self,
+        id: &str,
+        user_id: Option<&str>,
     ) -> ServiceResult<TokenResponse> {
         let mut condition = Condition::all().add(applications::Column::Id.eq(id.to_owned()));
 
@@ -46,16 +233,32 @@ impl ApplicationService {
         }
 
         let application = self.by_condition(condition).await?;
+        let token_version = application.token_version + 1;
+
+        let mut application = application.into_active_model();
+        application.token_version = Set(token_version);
+
+        let application = application
+            .update(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
 
         self.auth_service
-            .new_jwt(&application.user_id, Some(application.id))
+            .new_jwt(&application.user_id, Some(&application), None)
     }
 
     /// Update the last accessed date on an application to the current time.
-    pub async fn update_accessed(&self, application_id: &str) -> ServiceResult<()> {
+    /// The client which accessed the application is recorded.
+    pub async fn update_accessed(
+        &self,
+        application_id: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<()> {
         applications::ActiveModel {
             id: Set(application_id.to_owned()),
             last_accessed: Set(Utc::now()),
+            last_ip: Set(client.ip.to_owned()),
+            last_user_agent: Set(client.user_agent.to_owned()),
             ..Default::default()
         }
         .update(self.database.as_ref())
@@ -71,12 +274,18 @@ impl ApplicationService {
     ///
     /// * `user_id` - User who owns the application.
     /// * `name` - Name of the application (must be unique).
+    /// * `scopes` - What tokens of the application can be used for.
+    /// * `expires_at` - When tokens of the application stop working, they never expire if this is [`None`].
+    /// * `client` - Client creating the application.
     ///
     /// Returns [`ApplicationData`] with a token.
     pub async fn create_application(
         &self,
         user_id: &str,
         name: &str,
+        scopes: &[ApplicationScope],
+        expires_at: Option<DateTimeUtc>,
+        client: &ClientInfo,
     ) -> ServiceResult<ApplicationData> {
         if name.len() > 16 {
             return Err(ServiceError::InvalidData(
@@ -88,6 +297,12 @@ impl ApplicationService {
             ));
         }
 
+        if expires_at.map_or(false, |v| v <= Utc::now()) {
+            return Err(ServiceError::InvalidData(
+                "Application expiry date must be in the future".into(),
+            ));
+        }
+
         // Application with the same name owned by the same user already exists.
         if let Some(_) = applications::Entity::find()
             .filter(applications::Column::Name.eq(name.to_owned()))
@@ -102,23 +317,83 @@ impl ApplicationService {
         }
 
         // Create an application token and send JWT to user
-        let mut token_data = ApplicationData::from(
-            applications::ActiveModel {
-                user_id: Set(user_id.to_owned()),
-                name: Set(name.to_owned()),
-                ..Default::default()
-            }
-            .insert(self.database.as_ref())
-            .await
-            .map_err(|e| ServiceError::DbErr(e))?,
-        );
+        let application = applications::ActiveModel {
+            user_id: Set(user_id.to_owned()),
+            name: Set(name.to_owned()),
+            scopes: Set(ApplicationScope::format_list(scopes)),
+            expires_at: Set(expires_at),
+            ..Default::default()
+        }
+        .insert(self.database.as_ref())
+        .await
+        .map_err(|e| ServiceError::DbErr(e))?;
+
+        let token = self
+            .auth_service
+            .new_jwt(user_id, Some(&application), None)?
+            .token;
 
-        token_data.token = Some(
-            self.auth_service
-                .new_jwt(user_id, Some(token_data.id.clone()))?
-                .token,
-        );
+        self.audit_log_service
+            .record(
+                AuditAction::ApplicationCreate,
+                Some(user_id),
+                Some(AuditTarget::Application(&application.id)),
+                Some(application.name.clone()),
+                client,
+            )
+            .await;
+
+        // The token is only returned to the user who created the application.
+        self.webhook_service
+            .emit(
+                WebhookEvent::ApplicationCreated,
+                user_id,
+                None,
+                &ApplicationData::from(application.clone()),
+            )
+            .await;
+
+        let mut token_data = ApplicationData::from(application);
+        token_data.token = Some(token);
 
         Ok(token_data)
     }
 }
+
+/// Shell script which uploads a file with curl and prints its URL.
+/// Files can be read from stdin with `-`, which is how screenshot tools like Flameshot output images.
+fn curl_script(name: &str, token: &str, upload_url: &str) -> String {
+    // The name is only used in a comment, it can't end the line.
+    let name = name.replace(|c: char| c.is_control(), " ");
+
+    format!(
+        r#"#!/bin/sh
+# Backpack uploader for the "{name}" application.
+# Requires curl and jq, prints the URL of the uploaded file.
+#
+# Usage: backpack-upload.sh <file>
+# Flameshot: flameshot gui --raw | backpack-upload.sh -
+
+set -e
+
+if [ -z "$1" ]; then
+    echo "Usage: $0 <file>" >&2
+    exit 1
+fi
+
+if [ "$1" = "-" ]; then
+    form="uploadFile=@-;filename=screenshot.png"
+else
+    form="uploadFile=@$1"
+fi
+
+curl -sS \
+    -H 'Authorization: Bearer {token}' \
+    -F "$form" \
+    '{upload_url}' | jq -r '.url // .file.url // .message'
+"#,
+        name = name,
+        token = token,
+        upload_url = upload_url,
+    )
+}
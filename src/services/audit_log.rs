@@ -0,0 +1,155 @@
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, Order, Set};
+use std::sync::Arc;
+
+use super::prelude::*;
+use crate::{
+    database::entity::audit_log,
+    internal::auth::ClientInfo,
+    models::{AuditAction, AuditLogQuery},
+};
+
+/// Resource an action was taken on.
+pub enum AuditTarget<'a> {
+    User(&'a str),
+    Application(&'a str),
+    RegistrationKey(&'a str),
+    File(&'a str),
+    Settings,
+}
+
+impl<'a> AuditTarget<'a> {
+    fn target_type(&self) -> &'static str {
+        match self {
+            Self::User(_) => "user",
+            Self::Application(_) => "application",
+            Self::RegistrationKey(_) => "registration_key",
+            Self::File(_) => "file",
+            Self::Settings => "settings",
+        }
+    }
+
+    fn id(&self) -> Option<&'a str> {
+        match self {
+            Self::User(id) | Self::Application(id) | Self::RegistrationKey(id) | Self::File(id) => {
+                Some(id)
+            }
+            Self::Settings => None,
+        }
+    }
+}
+
+/// Records security-relevant actions.
+pub struct AuditLogService {
+    database: Arc<DatabaseConnection>,
+}
+
+data_service!(AuditLogService, audit_log);
+
+impl AuditLogService {
+    pub fn new(database: Arc<DatabaseConnection>) -> Self {
+        Self { database }
+    }
+
+    /// Record an action.
+    /// Entries which can't be written are logged, this never fails the action which was taken.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - Action which was taken.
+    /// * `actor_id` - User who took the action, [`None`] if it was taken by the server.
+    /// * `target` - Resource the action was taken on.
+    /// * `details` - Extra information, such as the auth method or the new role.
+    /// * `client` - Client which made the request.
+    pub async fn record(
+        &self,
+        action: AuditAction,
+        actor_id: Option<&str>,
+        target: Option<AuditTarget<'_>>,
+        details: Option<String>,
+        client: &ClientInfo,
+    ) {
+        let result = audit_log::ActiveModel {
+            action: Set(action.as_str().to_owned()),
+            actor_id: Set(actor_id.map(|v| v.to_owned())),
+            target_type: Set(target.as_ref().map(|v| v.target_type().to_owned())),
+            target_id: Set(target.as_ref().and_then(|v| v.id()).map(|v| v.to_owned())),
+            details: Set(details),
+            ip: Set(client.ip.to_owned()),
+            ..Default::default()
+        }
+        .insert(self.database.as_ref())
+        .await;
+
+        if let Err(e) = result {
+            log::error!("Failed to write {} audit log entry: {}", action.as_str(), e);
+        }
+    }
+
+    /// Get a page of entries about a user.
+    /// These are actions taken by the user and actions taken on their account.
+    pub async fn get_user_page(
+        &self,
+        user_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> ServiceResult<ServicePage<audit_log::Model>> {
+        self.get_newest_page(
+            page,
+            page_size,
+            Some(
+                Condition::any()
+                    .add(audit_log::Column::ActorId.eq(user_id.to_owned()))
+                    .add(
+                        Condition::all()
+                            .add(audit_log::Column::TargetType.eq("user"))
+                            .add(audit_log::Column::TargetId.eq(user_id.to_owned())),
+                    ),
+            ),
+        )
+        .await
+    }
+
+    /// Get a page of entries matching a query.
+    pub async fn get_filtered_page(
+        &self,
+        page: usize,
+        page_size: usize,
+        query: &AuditLogQuery,
+    ) -> ServiceResult<ServicePage<audit_log::Model>> {
+        let mut condition = Condition::all();
+
+        if let Some(actor_id) = &query.actor_id {
+            condition = condition.add(audit_log::Column::ActorId.eq(actor_id.to_owned()));
+        }
+
+        if let Some(target_id) = &query.target_id {
+            condition = condition.add(audit_log::Column::TargetId.eq(target_id.to_owned()));
+        }
+
+        if let Some(action) = query.action {
+            condition = condition.add(audit_log::Column::Action.eq(action.as_str()));
+        }
+
+        self.get_newest_page(page, page_size, Some(condition)).await
+    }
+
+    /// Get a page of entries, newest first.
+    /// IDs are ordered by time, they break ties between entries created in the same instant.
+    async fn get_newest_page(
+        &self,
+        page: usize,
+        page_size: usize,
+        condition: Option<Condition>,
+    ) -> ServiceResult<ServicePage<audit_log::Model>> {
+        self.get_sorted_page(
+            page,
+            page_size,
+            condition,
+            vec![
+                (audit_log::Column::Created, Order::Desc),
+                (audit_log::Column::Id, Order::Desc),
+            ],
+        )
+        .await
+    }
+}
@@ -1,8 +1,12 @@
 use crate::{
     database::entity::{auth_methods, sea_orm_active_enums::AuthMethod, users},
-    models::AuthMethods,
+    internal::auth::ClientInfo,
+    models::{AuditAction, AuthMethods},
+    services::auth::oauth::OAuthProvider,
     services::{
+        audit_log::{AuditLogService, AuditTarget},
         prelude::{data_service, DataService},
+        rate_limit::{limits, RateLimitService},
         ServiceError, ServiceResult, ToOption,
     },
 };
@@ -15,13 +19,50 @@ use super::{new_password, validate_password};
 
 pub struct AuthMethodService {
     database: Arc<DatabaseConnection>,
+    rate_limit_service: Arc<RateLimitService>,
+    audit_log_service: Arc<AuditLogService>,
 }
 
 data_service!(AuthMethodService, auth_methods);
 
 impl AuthMethodService {
-    pub fn new(database: Arc<DatabaseConnection>) -> Self {
-        Self { database }
+    pub fn new(
+        database: Arc<DatabaseConnection>,
+        rate_limit_service: Arc<RateLimitService>,
+        audit_log_service: Arc<AuditLogService>,
+    ) -> Self {
+        Self {
+            database,
+            rate_limit_service,
+            audit_log_service,
+        }
+    }
+
+    /// Validate the password of a user.
+    /// The user is locked out for a while after too many incorrect passwords.
+    ///
+    /// * `method` - Password auth method of the user.
+    /// * `password` - Password to check.
+    pub async fn validate_user_password(
+        &self,
+        method: &auth_methods::Model,
+        password: &str,
+    ) -> ServiceResult<()> {
+        self.rate_limit_service
+            .check(limits::PASSWORD_FAILURES, &method.user_id)
+            .await?;
+
+        if let Err(e) = validate_password(&method.value, password) {
+            self.rate_limit_service
+                .hit(limits::PASSWORD_FAILURES, &method.user_id)
+                .await?;
+
+            return Err(e);
+        }
+
+        self.rate_limit_service
+            .reset(limits::PASSWORD_FAILURES, &method.user_id)
+            .await
     }
 
     /// Get authentication method for a user.
@@ -38,6 +79,23 @@ impl AuthMethodService {
         .await
     }
 
+    /// Get the authentication method of a user for an OAuth provider.
+    pub async fn get_provider_method(
+        &self,
+        user_id: &str,
+        provider: &OAuthProvider,
+    ) -> ServiceResult<auth_methods::Model> {
+        let method: AuthMethod = provider.clone().into();
+
+        self.by_condition(
+            Condition::all()
+                .add(auth_methods::Column::AuthMethod.eq(method))
+                .add(auth_methods::Column::Provider.eq(provider.method_provider()))
+                .add(auth_methods::Column::UserId.eq(user_id.to_owned())),
+        )
+        .await
+    }
+
     /// Get all methods enabled for a user.
     pub async fn get_enabled_methods(&self, user_id: &str) -> ServiceResult<AuthMethods> {
         let found_methods = auth_methods::Entity::find()
@@ -54,7 +112,12 @@ impl AuthMethodService {
                 AuthMethod::Discord => methods.discord = method.cached_username,
                 AuthMethod::Github => methods.github = method.cached_username,
                 AuthMethod::Google => methods.google = method.cached_username,
+                AuthMethod::Ldap => methods.ldap = method.cached_username,
                 AuthMethod::Password => methods.password = true,
+                AuthMethod::Totp => methods.totp = method.confirmed,
+                AuthMethod::Oidc => {
+                    methods.oidc.insert(method.provider, method.cached_username);
+                }
             };
         }
 
@@ -65,10 +128,11 @@ impl AuthMethodService {
     pub async fn unlink_method(
         &self,
         user_id: &str,
-        method: AuthMethod,
+        provider: &OAuthProvider,
         password: Option<String>,
+        client: &ClientInfo,
     ) -> ServiceResult<AuthMethods> {
-        let method = self.get_auth_method(user_id, method).await?;
+        let method = self.get_provider_method(user_id, provider).await?;
 
         // Validate password if exists
         if let Some(password_method) = self
@@ -77,7 +141,8 @@ impl AuthMethodService {
             .to_option()?
         {
             if let Some(password) = password {
-                validate_password(&password_method.value, &password)?;
+                self.validate_user_password(&password_method, &password)
+                    .await?;
             } else {
                 return Err(ServiceError::InvalidData(
                     "Password is required to remove an auth method.".into(),
@@ -93,10 +158,14 @@ impl AuthMethodService {
         }
 
         method
+            .clone()
             .delete(self.database.as_ref())
             .await
             .map_err(|e| ServiceError::DbErr(e))?;
 
+        self.record(AuditAction::AuthMethodUnlink, &method, client)
+            .await;
+
         self.get_enabled_methods(user_id).await
     }
 
@@ -105,6 +174,7 @@ impl AuthMethodService {
     pub async fn get_user_by_value(
         &self,
         method: AuthMethod,
+        provider: &str,
         value: &str,
         new_cached_username: Option<String>,
     ) -> ServiceResult<Option<users::Model>> {
@@ -112,6 +182,7 @@ impl AuthMethodService {
             .by_condition(
                 Condition::all()
                     .add(auth_methods::Column::AuthMethod.eq(method))
+                    .add(auth_methods::Column::Provider.eq(provider.to_owned()))
                     .add(auth_methods::Column::Value.eq(value.to_owned())),
             )
             .await
@@ -146,6 +217,7 @@ impl AuthMethodService {
         method: AuthMethod,
         cached_username: Option<String>,
         value: &str,
+        client: &ClientInfo,
     ) -> ServiceResult<auth_methods::Model> {
         match auth_methods::Entity::find()
             .filter(auth_methods::Column::AuthMethod.eq(method.clone()))
@@ -162,40 +234,79 @@ impl AuthMethodService {
                 });
 
                 active_method.last_accessed = Set(Utc::now());
-                active_method
+                let method = active_method
                     .update(self.database.as_ref())
                     .await
-                    .map_err(|e| ServiceError::DbErr(e))
+                    .map_err(|e| ServiceError::DbErr(e))?;
+
+                self.record(AuditAction::AuthMethodChange, &method, client)
+                    .await;
+
+                Ok(method)
             }
             None => {
-                self.create_auth_method(user_id, method, cached_username, value)
+                self.create_auth_method(user_id, method, "", cached_username, value, client)
                     .await
             }
         }
     }
 
     /// Create and validate auth methods.
+    ///
+    /// * `provider` - Name of the OpenID Connect provider, empty for other methods.
     pub async fn create_auth_method(
         &self,
         user_id: &str,
         method: AuthMethod,
+        provider: &str,
         cached_username: Option<String>,
         value: &str,
+        client: &ClientInfo,
     ) -> ServiceResult<auth_methods::Model> {
         let value = match method {
             AuthMethod::Password => new_password(&value)?,
             _ => value.to_owned(),
         };
 
-        auth_methods::ActiveModel {
+        let model = auth_methods::ActiveModel {
             user_id: Set(user_id.to_owned()),
             auth_method: Set(method),
+            provider: Set(provider.to_owned()),
             cached_username: Set(cached_username),
             value: Set(value),
             ..Default::default()
         }
         .insert(self.database.as_ref())
         .await
-        .map_err(|e| ServiceError::DbErr(e))
+        .map_err(|e| ServiceError::DbErr(e))?;
+
+        self.record(AuditAction::AuthMethodLink, &model, client)
+            .await;
+
+        Ok(model)
+    }
+
+    /// Record a change to an auth method of a user.
+    /// Values are secret, only the method and provider are recorded.
+    pub async fn record(
+        &self,
+        action: AuditAction,
+        method: &auth_methods::Model,
+        client: &ClientInfo,
+    ) {
+        let details = match method.provider.is_empty() {
+            true => method.auth_method.to_value(),
+            false => format!("{}:{}", method.auth_method.to_value(), method.provider),
+        };
+
+        self.audit_log_service
+            .record(
+                action,
+                Some(&method.user_id),
+                Some(AuditTarget::User(&method.user_id)),
+                Some(details),
+                client,
+            )
+            .await;
     }
 }
@@ -0,0 +1,161 @@
+//! LDAP directory authentication.
+
+use std::time::Duration;
+
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry};
+
+use crate::{
+    config::LdapConfig,
+    services::{ServiceError, ServiceResult},
+};
+
+/// Result code returned when a bind is rejected.
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// User found in the directory.
+pub struct LdapUser {
+    /// DN of the entry, this is stored as the auth method value.
+    pub dn: String,
+    pub username: String,
+    pub email: String,
+    /// Is the user a member of the admin group.
+    /// This is only set if an admin group is configured.
+    pub admin: Option<bool>,
+}
+
+pub struct LdapClient {
+    config: LdapConfig,
+}
+
+impl LdapClient {
+    pub fn new(config: LdapConfig) -> Self {
+        Self { config }
+    }
+
+    /// Authenticate a user against the directory.
+    ///
+    /// The user entry is searched for with the configured bind account, then the password is checked by binding as the user.
+    ///
+    /// * `identifier` - Username or email, this is substituted into the search filter.
+    /// * `password` - Password of the directory user.
+    pub async fn authenticate(&self, identifier: &str, password: &str) -> ServiceResult<LdapUser> {
+        // An empty password would make an unauthenticated bind, which always succeeds.
+        if password.is_empty() {
+            return Err(incorrect_credentials());
+        }
+
+        let mut ldap = self.connect().await?;
+        let result = self.find_and_bind(&mut ldap, identifier, password).await;
+        let _ = ldap.unbind().await;
+
+        result
+    }
+
+    async fn find_and_bind(
+        &self,
+        ldap: &mut Ldap,
+        identifier: &str,
+        password: &str,
+    ) -> ServiceResult<LdapUser> {
+        if let (Some(bind_dn), Some(bind_password)) =
+            (&self.config.bind_dn, &self.config.bind_password)
+        {
+            ldap.simple_bind(bind_dn, bind_password)
+                .await
+                .and_then(|result| result.success())
+                .map_err(server_error)?;
+        }
+
+        let filter = self
+            .config
+            .user_filter
+            .replace("{username}", &ldap_escape(identifier));
+
+        let (entries, _) = ldap
+            .search(
+                &self.config.base_dn,
+                Scope::Subtree,
+                &filter,
+                vec![
+                    self.config.username_attribute.as_str(),
+                    self.config.email_attribute.as_str(),
+                    self.config.group_attribute.as_str(),
+                ],
+            )
+            .await
+            .and_then(|result| result.success())
+            .map_err(server_error)?;
+
+        // The filter must match exactly one user.
+        let entry = match entries.len() {
+            1 => SearchEntry::construct(entries.into_iter().next().unwrap()),
+            _ => return Err(incorrect_credentials()),
+        };
+
+        match ldap
+            .simple_bind(&entry.dn, password)
+            .await
+            .and_then(|result| result.success())
+        {
+            Ok(_) => {}
+            Err(LdapError::LdapResult { result }) if result.rc == INVALID_CREDENTIALS => {
+                return Err(incorrect_credentials())
+            }
+            Err(e) => return Err(server_error(e)),
+        }
+
+        let attribute = |name: &str| {
+            entry
+                .attrs
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .and_then(|(_, values)| values.first())
+                .map(|value| value.to_owned())
+        };
+
+        let username = attribute(&self.config.username_attribute).ok_or_else(|| {
+            ServiceError::InvalidData("Your directory account has no username".into())
+        })?;
+
+        let email = attribute(&self.config.email_attribute).ok_or_else(|| {
+            ServiceError::InvalidData("Your directory account has no email".into())
+        })?;
+
+        let admin = self.config.admin_group.as_ref().map(|admin_group| {
+            entry
+                .attrs
+                .iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case(&self.config.group_attribute))
+                .flat_map(|(_, groups)| groups)
+                .any(|group| group.eq_ignore_ascii_case(admin_group))
+        });
+
+        Ok(LdapUser {
+            dn: entry.dn,
+            username,
+            email,
+            admin,
+        })
+    }
+
+    async fn connect(&self) -> ServiceResult<Ldap> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(Duration::from_secs(10))
+            .set_starttls(self.config.starttls);
+
+        let (conn, ldap) = LdapConnAsync::with_settings(settings, &self.config.url)
+            .await
+            .map_err(server_error)?;
+        ldap3::drive!(conn);
+
+        Ok(ldap)
+    }
+}
+
+fn incorrect_credentials() -> ServiceError {
+    ServiceError::InvalidData("Incorrect credentials provided".into())
+}
+
+fn server_error(e: LdapError) -> ServiceError {
+    ServiceError::ServerError(e.into())
+}
@@ -2,37 +2,72 @@ use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, Pa
 use chrono::Utc;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use rand::{rngs::OsRng, Rng};
-use sea_orm::{ColumnTrait, Condition};
+use sea_orm::{ActiveEnum, ColumnTrait, Condition};
 use serde::{Deserialize, Serialize};
-use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
-};
-use url::Url;
+use std::sync::{Arc, RwLock};
+use url::{form_urlencoded, Url};
 
 use crate::{
-    config::OAuthConfig,
-    database::entity::{applications, auth_methods, sea_orm_active_enums::AuthMethod, users},
-    models::{OAuthRequest, TokenResponse},
+    config::{LdapConfig, OAuthConfig, OidcConfig},
+    database::entity::{
+        applications, auth_methods,
+        sea_orm_active_enums::{AuthMethod, Role},
+        users,
+    },
+    internal::auth::ClientInfo,
+    models::{ApplicationScope, AuditAction, OAuthRequest, TokenResponse, TwoFactorChallenge},
 };
 
 use self::{
     auth_method::AuthMethodService,
+    ldap::LdapClient,
     oauth::{OAuthClient, OAuthProvider},
+    oidc::OidcClient,
+    session::SessionService,
+    two_factor::TwoFactorService,
 };
 
 use super::{
-    application::ApplicationService, prelude::DataService, user::UserService, ServiceError,
-    ServiceResult, ToOption,
+    application::ApplicationService,
+    audit_log::{AuditLogService, AuditTarget},
+    prelude::DataService,
+    rate_limit::{limits, RateLimitService},
+    user::UserService,
+    ServiceError, ServiceResult, ToOption,
 };
 
 pub mod auth_method;
+pub mod ldap;
 pub mod oauth;
+pub mod oidc;
+pub mod session;
+pub mod two_factor;
+
+/// Minutes a login access token is valid for, a refresh token is needed to get a new one.
+const ACCESS_TOKEN_EXPIRY_MINUTES: i64 = 15;
+
+/// Minutes a user has to complete a two-factor challenge.
+const CHALLENGE_EXPIRY_MINUTES: i64 = 5;
+
+/// Audience of two-factor challenge tokens, these can't be used as access tokens.
+const CHALLENGE_AUDIENCE: &str = "two-factor";
+
+/// Result of a password or OAuth login.
+pub enum LoginResult {
+    /// The user is logged in.
+    Token(TokenResponse),
+    /// The user has to complete a two-factor challenge with [`AuthService::complete_challenge`].
+    Challenge(TwoFactorChallenge),
+}
 
 /// Handles authentication and validation.
 pub struct AuthService {
     auth_method_service: Arc<AuthMethodService>,
+    session_service: Arc<SessionService>,
+    two_factor_service: Arc<TwoFactorService>,
     user_service: Arc<UserService>,
+    rate_limit_service: Arc<RateLimitService>,
+    audit_log_service: Arc<AuditLogService>,
     // TODO: Figure out how to avoid this circular dependency.
     application_service: Arc<RwLock<Option<Arc<ApplicationService>>>>,
     api_url: actix_http::Uri,
@@ -43,12 +78,18 @@ pub struct AuthService {
     google_oauth_client: Option<OAuthClient>,
     github_oauth_client: Option<OAuthClient>,
     discord_oauth_client: Option<OAuthClient>,
+    oidc_clients: Vec<OidcClient>,
+    ldap_client: Option<LdapClient>,
 }
 
 impl AuthService {
     pub fn new(
         auth_method_service: Arc<AuthMethodService>,
+        session_service: Arc<SessionService>,
+        two_factor_service: Arc<TwoFactorService>,
         user_service: Arc<UserService>,
+        rate_limit_service: Arc<RateLimitService>,
+        audit_log_service: Arc<AuditLogService>,
         application_service: Arc<RwLock<Option<Arc<ApplicationService>>>>,
         api_url: &str,
         jwt_key: &str,
@@ -56,10 +97,16 @@ impl AuthService {
         google_oauth: Option<OAuthConfig>,
         github_oauth: Option<OAuthConfig>,
         discord_oauth: Option<OAuthConfig>,
+        oidc_providers: Vec<OidcConfig>,
+        ldap: Option<LdapConfig>,
     ) -> Self {
         Self {
             auth_method_service,
+            session_service,
+            two_factor_service,
             user_service,
+            rate_limit_service,
+            audit_log_service,
             application_service,
             api_url: api_url.parse::<actix_http::Uri>().unwrap(),
             jwt_key: jwt_key.into(),
@@ -85,6 +132,14 @@ impl AuthService {
                 ),
                 None => None,
             },
+            oidc_clients: oidc_providers
+                .into_iter()
+                .map(|config| {
+                    let callback_url = format!("{}/api/auth/{}/callback", api_url, config.name);
+                    OidcClient::new(config, &callback_url)
+                })
+                .collect(),
+            ldap_client: ldap.map(|config| LdapClient::new(config)),
         }
     }
 
@@ -92,22 +147,230 @@ impl AuthService {
     ///
     /// * `username` - User identifier (email or username)
     /// * `password` - User password
+    /// * `client` - Client logging in, this is shown in the session list.
     ///
-    /// Returns JWT token response.
-    pub async fn password_auth(&self, auth: &str, password: &str) -> ServiceResult<TokenResponse> {
+    /// Returns JWT token response, or a challenge if the user has two-factor authentication enabled.
+    pub async fn password_auth(
+        &self,
+        auth: &str,
+        password: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<LoginResult> {
+        self.rate_limit_service
+            .hit_client(limits::LOGIN_IP, client)
+            .await?;
+        self.rate_limit_service
+            .hit(limits::LOGIN_IDENTIFIER, auth.trim())
+            .await?;
+
+        let (user, method) = match &self.ldap_client {
+            // Users with a local password keep using it, everyone else is checked against the directory.
+            Some(ldap_client) => {
+                match self.local_password_auth(auth, password).await.to_option()? {
+                    Some(user) => (user, AuthMethod::Password),
+                    None => (
+                        self.ldap_auth(ldap_client, auth, password, client).await?,
+                        AuthMethod::Ldap,
+                    ),
+                }
+            }
+            None => (
+                self.local_password_auth(auth, password).await?,
+                AuthMethod::Password,
+            ),
+        };
+
+        if self.user_service.smtp_enabled() {
+            self.user_service.verify_user(&user).await?;
+        }
+
+        self.login(&user.id, &method.to_value(), client).await
+    }
+
+    /// Check the local password of a user.
+    async fn local_password_auth(&self, auth: &str, password: &str) -> ServiceResult<users::Model> {
         let user = self.user_service.get_by_identifier(auth).await?;
         let method = self
             .auth_method_service
             .get_auth_method(&user.id, AuthMethod::Password)
             .await?;
 
-        validate_password(&method.value, password)?;
+        self.auth_method_service
+            .validate_user_password(&method, password)
+            .await?;
 
-        if self.user_service.smtp_enabled() {
-            self.user_service.verify_user(&user).await?;
+        Ok(user)
+    }
+
+    /// Authenticate a user with the LDAP directory.
+    /// Users are created the first time they log in, and their role follows the admin group if one is configured.
+    async fn ldap_auth(
+        &self,
+        ldap_client: &LdapClient,
+        auth: &str,
+        password: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<users::Model> {
+        let ldap_user = ldap_client.authenticate(auth, password).await?;
+
+        let user = match self
+            .auth_method_service
+            .get_user_by_value(
+                AuthMethod::Ldap,
+                "",
+                &ldap_user.dn,
+                Some(ldap_user.username.clone()),
+            )
+            .await?
+        {
+            Some(user) => user,
+            None => {
+                self.user_service
+                    .create_user(
+                        self.new_unique_username(&ldap_user.username).await?,
+                        ldap_user.email,
+                        (
+                            AuthMethod::Ldap,
+                            ldap_user.dn,
+                            Some(ldap_user.username),
+                            String::new(),
+                        ),
+                        None,
+                        client,
+                    )
+                    .await?
+            }
+        };
+
+        let role = match ldap_user.admin {
+            Some(true) => Role::Admin,
+            Some(false) => Role::User,
+            None => return Ok(user),
+        };
+
+        if user.role == role {
+            return Ok(user);
         }
 
-        self.new_jwt(&user.id, None)
+        let user = self.user_service.set_role(&user.id, role.clone()).await?;
+        self.audit_log_service
+            .record(
+                AuditAction::UserRoleChange,
+                None,
+                Some(AuditTarget::User(&user.id)),
+                Some(role.to_value()),
+                client,
+            )
+            .await;
+
+        Ok(user)
+    }
+
+    /// Complete a login which needed a two-factor code.
+    ///
+    /// * `challenge` - Challenge returned by [`AuthService::password_auth`].
+    /// * `code` - TOTP code or recovery code.
+    /// * `client` - Client logging in, this is shown in the session list.
+    pub async fn complete_challenge(
+        &self,
+        challenge: &str,
+        code: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<TokenResponse> {
+        let mut validation = Validation::default();
+        validation.set_audience(&[CHALLENGE_AUDIENCE]);
+
+        let claims = decode::<ChallengeClaims>(
+            challenge,
+            &DecodingKey::from_secret(self.jwt_key.as_ref()),
+            &validation,
+        )
+        .map_err(|_| ServiceError::Unauthorized("This login has expired, try again".into()))?
+        .claims;
+
+        self.two_factor_service.verify(&claims.sub, code).await?;
+        self.new_session(&claims.sub, "two_factor", client).await
+    }
+
+    /// Start a session for a user who was authenticated,
+    /// or a two-factor challenge if the user has two-factor authentication enabled.
+    async fn login(
+        &self,
+        user_id: &str,
+        method: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<LoginResult> {
+        if self.two_factor_service.enabled(user_id).await? {
+            return Ok(LoginResult::Challenge(self.new_challenge(user_id)?));
+        }
+
+        Ok(LoginResult::Token(
+            self.new_session(user_id, method, client).await?,
+        ))
+    }
+
+    /// Create a challenge which proves the first factor of a user was correct.
+    fn new_challenge(&self, user_id: &str) -> ServiceResult<TwoFactorChallenge> {
+        let claims = ChallengeClaims {
+            aud: CHALLENGE_AUDIENCE.into(),
+            exp: (Utc::now() + chrono::Duration::minutes(CHALLENGE_EXPIRY_MINUTES)).timestamp(),
+            sub: user_id.to_owned(),
+        };
+
+        let challenge = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(self.jwt_key.as_ref()),
+        )
+        .map_err(|e| ServiceError::ServerError(e.into()))?;
+
+        Ok(TwoFactorChallenge { challenge })
+    }
+
+    /// Start a new login session for a user.
+    ///
+    /// * `user_id` - User logging in.
+    /// * `method` - How the user logged in, this is recorded in the audit log.
+    /// * `client` - Client logging in, this is shown in the session list.
+    ///
+    /// Returns a short-lived access token and the refresh token of the session.
+    pub async fn new_session(
+        &self,
+        user_id: &str,
+        method: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<TokenResponse> {
+        let (session, refresh_token) = self.session_service.create_session(user_id, client).await?;
+
+        self.audit_log_service
+            .record(
+                AuditAction::Login,
+                Some(user_id),
+                Some(AuditTarget::User(user_id)),
+                Some(method.to_owned()),
+                client,
+            )
+            .await;
+
+        let mut token = self.new_jwt(user_id, None, Some(&session.id))?;
+        token.refresh_token = Some(refresh_token);
+        Ok(token)
+    }
+
+    /// Exchange a refresh token for a new access token and refresh token.
+    pub async fn refresh_session(
+        &self,
+        refresh_token: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<TokenResponse> {
+        let (session, refresh_token) = self
+            .session_service
+            .refresh_session(refresh_token, client)
+            .await?;
+
+        let mut token = self.new_jwt(&session.user_id, None, Some(&session.id))?;
+        token.refresh_token = Some(refresh_token);
+        Ok(token)
     }
 
     /// Validate JWT with specific parameters.
@@ -115,11 +378,23 @@ impl AuthService {
     /// # Arguments
     ///
     /// * `allow_unverified` - Allow unverified users.
+    /// * `client` - Client using the token, this is recorded for application tokens.
+    ///
+    /// Returns the user, the application if it is an application token, the scopes the token can use
+    /// and the session if it is a login token.
+    /// Application tokens can only use scopes which are in both the token and the application.
+    /// Login tokens stop working as soon as their session is revoked.
     pub async fn validate_jwt(
         &self,
         allow_unverified: bool,
         jwt_token: &str,
-    ) -> ServiceResult<(users::Model, Option<applications::Model>)> {
+        client: &ClientInfo,
+    ) -> ServiceResult<(
+        users::Model,
+        Option<applications::Model>,
+        Vec<ApplicationScope>,
+        Option<String>,
+    )> {
         let mut validation = Validation::default();
 
         // Application tokens might not have expiration date so it's not required.
@@ -134,8 +409,35 @@ impl AuthService {
         .map_err(|_| ServiceError::Unauthorized("You are not authorized".into()))?
         .claims;
 
+        // Every login token belongs to a session, tokens from before sessions existed are rejected.
+        let session_id = match (&claims.application_id, claims.session_id) {
+            (Some(_), _) => None,
+            (None, Some(session_id)) => {
+                match self.session_service.get_active_session(&session_id).await {
+                    Ok(session) if session.user_id == claims.sub => Some(session.id),
+                    Ok(_) | Err(ServiceError::NotFound(_)) => {
+                        return Err(ServiceError::Unauthorized(
+                            "Your session has expired".into(),
+                        ))
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            (None, None) => {
+                return Err(ServiceError::Unauthorized(
+                    "Your session has expired".into(),
+                ))
+            }
+        };
+
         let mut user = self.user_service.by_id(claims.sub).await?;
 
+        if user.suspended {
+            return Err(ServiceError::Unauthorized(
+                "Your account has been suspended".into(),
+            ));
+        }
+
         if !user.verified {
             if self.user_service.smtp_enabled() {
                 if !allow_unverified {
@@ -150,6 +452,7 @@ impl AuthService {
         }
 
         let mut application = None;
+        let mut scopes = ApplicationScope::ALL.to_vec();
 
         if let Some(application_id) = claims.application_id {
             let application_service = self.application_service.read().unwrap().clone().unwrap();
@@ -160,8 +463,30 @@ impl AuthService {
                         return Err(ServiceError::unauthorized());
                     }
 
+                    // Tokens from before the last rotation are revoked.
+                    if claims.token_version.unwrap_or(0) != v.token_version {
+                        return Err(ServiceError::Unauthorized(
+                            "This application token has been revoked".into(),
+                        ));
+                    }
+
+                    if v.expires_at
+                        .map_or(false, |expires_at| expires_at <= Utc::now())
+                    {
+                        return Err(ServiceError::Unauthorized(
+                            "This application has expired".into(),
+                        ));
+                    }
+
+                    // Scopes removed from the application also apply to existing tokens.
+                    // Tokens created before scopes existed only have the application scopes.
+                    scopes = ApplicationScope::parse_list(&v.scopes);
+                    if let Some(token_scopes) = &claims.scopes {
+                        scopes.retain(|scope| token_scopes.contains(scope));
+                    }
+
                     // Update last accessed
-                    application_service.update_accessed(&v.id).await?;
+                    application_service.update_accessed(&v.id, client).await?;
                     application = Some(v);
                 }
                 Err(e) => match e {
@@ -172,16 +497,29 @@ impl AuthService {
             }
         }
 
-        Ok((user, application))
+        Ok((user, application, scopes, session_id))
     }
 
     /// Create a new JWT for the user
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - User the token is for.
+    /// * `application` - Application the token is for, the token will have the scopes of the application.
+    /// * `session_id` - Session the token is for, this should be [`Some`] for login tokens.
     pub fn new_jwt(
         &self,
         user_id: &str,
-        application_id: Option<String>,
+        application: Option<&applications::Model>,
+        session_id: Option<&str>,
     ) -> ServiceResult<TokenResponse> {
-        let expire_time = (Utc::now() + chrono::Duration::weeks(1)).timestamp();
+        // Application tokens are valid until the application expires or the token is rotated.
+        let expire_time = match application {
+            Some(application) => application.expires_at.map(|v| v.timestamp()),
+            None => Some(
+                (Utc::now() + chrono::Duration::minutes(ACCESS_TOKEN_EXPIRY_MINUTES)).timestamp(),
+            ),
+        };
 
         let claims = JwtClaims {
             iss: self
@@ -189,10 +527,13 @@ impl AuthService {
                 .host()
                 .expect("API_URL must have host included")
                 .into(),
-            exp: Some(expire_time),
+            exp: expire_time,
             iat: Utc::now().timestamp(),
             sub: user_id.to_string(),
-            application_id: application_id,
+            application_id: application.map(|v| v.id.to_owned()),
+            scopes: application.map(|v| ApplicationScope::parse_list(&v.scopes)),
+            token_version: application.map(|v| v.token_version),
+            session_id: session_id.map(|v| v.to_owned()),
         };
 
         let jwt = encode(
@@ -202,12 +543,26 @@ impl AuthService {
         )
         .map_err(|e| ServiceError::ServerError(e.into()))?;
 
-        Ok(TokenResponse { token: jwt })
+        Ok(TokenResponse {
+            token: jwt,
+            refresh_token: None,
+        })
     }
 
     /// Check if the OAuth provider is enabled.
     pub fn oauth_enabled(&self, provider_type: OAuthProvider) -> bool {
-        self.get_oauth_client(provider_type).is_ok()
+        match provider_type {
+            OAuthProvider::Oidc(name) => self.get_oidc_client(&name).is_ok(),
+            provider_type => self.get_oauth_client(provider_type).is_ok(),
+        }
+    }
+
+    /// Get the name and display name of every OpenID Connect provider.
+    pub fn oidc_providers(&self) -> Vec<(String, String)> {
+        self.oidc_clients
+            .iter()
+            .map(|client| (client.name().to_owned(), client.display_name().to_owned()))
+            .collect()
     }
 
     /// Initiate an oauth login.
@@ -219,23 +574,51 @@ impl AuthService {
         redirect: Option<String>,
         include_redirect: bool,
     ) -> ServiceResult<oauth2::url::Url> {
-        self.get_oauth_client(provider_type)?
-            .login(user_id, redirect, include_redirect)
-            .await
+        if let Some(redirect) = &redirect {
+            if !self.is_client_url(redirect) {
+                return Err(ServiceError::InvalidData(
+                    "Redirect must be on the client URL".into(),
+                ));
+            }
+        }
+
+        match provider_type {
+            OAuthProvider::Oidc(name) => {
+                self.get_oidc_client(&name)?
+                    .login(user_id, redirect, include_redirect)
+                    .await
+            }
+            provider_type => {
+                self.get_oauth_client(provider_type)?
+                    .login(user_id, redirect, include_redirect)
+                    .await
+            }
+        }
     }
 
     /// Use auth params provided by the provider to get a JWT token.
     /// If a user dowes not exist with these parameters, create the user.
-    /// Returns new JWT key.
+    /// Returns new JWT key, or a two-factor challenge if the user has it enabled.
     pub async fn oauth_authenticate(
         &self,
         provider_type: OAuthProvider,
         auth_request: &OAuthRequest,
-    ) -> ServiceResult<(TokenResponse, Option<String>)> {
-        let (oauth_data, oauth_state) = self
-            .get_oauth_client(provider_type)?
-            .get_user_data(auth_request)
-            .await?;
+        client: &ClientInfo,
+    ) -> ServiceResult<(LoginResult, Option<String>)> {
+        let (oauth_data, oauth_state) = match &provider_type {
+            OAuthProvider::Oidc(name) => {
+                self.get_oidc_client(name)?
+                    .get_user_data(auth_request)
+                    .await?
+            }
+            provider_type => {
+                self.get_oauth_client(provider_type.clone())?
+                    .get_user_data(auth_request)
+                    .await?
+            }
+        };
+        let auth_method: AuthMethod = provider_type.clone().into();
+        let method_provider = provider_type.method_provider().to_owned();
 
         // let full_redirect = Uri::from(oauth_state.redirect)
 
@@ -243,7 +626,8 @@ impl AuthService {
         let user = match self
             .auth_method_service
             .get_user_by_value(
-                provider_type.clone().into(),
+                auth_method.clone(),
+                &method_provider,
                 &oauth_data.id,
                 Some(oauth_data.username.clone()),
             )
@@ -268,21 +652,21 @@ impl AuthService {
                         self.auth_method_service
                             .create_auth_method(
                                 &user.id,
-                                provider_type.into(),
+                                auth_method,
+                                &method_provider,
                                 Some(oauth_data.username),
                                 &oauth_data.id,
+                                client,
                             )
                             .await?;
 
-                        let token = self.new_jwt(&user.id, None)?;
-                        return Ok((
-                            token.clone(),
-                            make_redirect_url(
-                                oauth_state.redirect,
-                                &token.token,
-                                oauth_state.include_redirect,
-                            ),
-                        ));
+                        let result = self.login(&user.id, provider_type.name(), client).await?;
+                        let redirect = self.make_redirect_url(
+                            oauth_state.redirect,
+                            &result,
+                            oauth_state.include_redirect,
+                        );
+                        return Ok((result, redirect));
                     }
                 }
 
@@ -301,14 +685,16 @@ impl AuthService {
                     .await
                 {
                     Ok(user) => {
-                        let auth_method: AuthMethod = provider_type.into();
                         // Make sure an existing account with the same email and auth method doesn't exist.
                         if let Some(_) = self
                             .auth_method_service
                             .by_condition(
                                 Condition::all()
                                     .add(auth_methods::Column::UserId.eq(user.id.clone()))
-                                    .add(auth_methods::Column::AuthMethod.eq(auth_method.clone())),
+                                    .add(auth_methods::Column::AuthMethod.eq(auth_method.clone()))
+                                    .add(
+                                        auth_methods::Column::Provider.eq(method_provider.clone()),
+                                    ),
                             )
                             .await
                             .to_option()?
@@ -322,8 +708,10 @@ impl AuthService {
                             .create_auth_method(
                                 &user.id,
                                 auth_method,
+                                &method_provider,
                                 Some(oauth_data.username),
                                 &oauth_data.id,
+                                client,
                             )
                             .await?;
 
@@ -337,11 +725,13 @@ impl AuthService {
                                     self.new_unique_username(&oauth_data.username).await?,
                                     oauth_data.email,
                                     (
-                                        provider_type.into(),
+                                        auth_method,
                                         oauth_data.id,
                                         Some(oauth_data.username),
+                                        method_provider,
                                     ),
                                     None,
+                                    client,
                                 )
                                 .await?
                         }
@@ -351,15 +741,10 @@ impl AuthService {
             }
         };
 
-        let token = self.new_jwt(&user.id, None)?;
-        Ok((
-            token.clone(),
-            make_redirect_url(
-                oauth_state.redirect,
-                &token.token,
-                oauth_state.include_redirect,
-            ),
-        ))
+        let result = self.login(&user.id, provider_type.name(), client).await?;
+        let redirect =
+            self.make_redirect_url(oauth_state.redirect, &result, oauth_state.include_redirect);
+        Ok((result, redirect))
     }
 
     /// Validate or attempt to create a valid username based on an existing username.
@@ -385,11 +770,58 @@ impl AuthService {
         Ok(username)
     }
 
+    /// Redirect URL for a completed login.
+    /// Tokens are put in the URL fragment so they aren't sent to servers or logged with the URL.
+    /// Users with two-factor authentication get a `challenge` to complete instead.
+    fn make_redirect_url(
+        &self,
+        redirect: Option<String>,
+        result: &LoginResult,
+        include_redirect: bool,
+    ) -> Option<String> {
+        let redirect = redirect?;
+        if !self.is_client_url(&redirect) {
+            return None;
+        }
+
+        let mut url = Url::parse(&redirect).ok()?;
+
+        if include_redirect {
+            let mut fragment = form_urlencoded::Serializer::new(String::new());
+            match result {
+                LoginResult::Token(token) => {
+                    fragment.append_pair("token", &token.token);
+                    if let Some(refresh_token) = &token.refresh_token {
+                        fragment.append_pair("refreshToken", refresh_token);
+                    }
+                }
+                LoginResult::Challenge(challenge) => {
+                    fragment.append_pair("challenge", &challenge.challenge);
+                }
+            }
+
+            url.set_fragment(Some(&fragment.finish()));
+        }
+
+        Some(url.to_string())
+    }
+
+    /// Check if a URL has the same origin as the client.
+    /// Login redirects carry tokens, so they may only go to the client.
+    fn is_client_url(&self, url: &str) -> bool {
+        match (Url::parse(url), Url::parse(&self.client_url)) {
+            (Ok(url), Ok(client_url)) => url.origin() == client_url.origin(),
+            _ => false,
+        }
+    }
+
     fn get_oauth_client(&self, provider_type: OAuthProvider) -> ServiceResult<&OAuthClient> {
-        let provider = match provider_type {
-            OAuthProvider::Google => &self.google_oauth_client,
-            OAuthProvider::Github => &self.github_oauth_client,
-            OAuthProvider::Discord => &self.discord_oauth_client,
+        let provider = match &provider_type {
+            OAuthProvider::Google => self.google_oauth_client.as_ref(),
+            OAuthProvider::Github => self.github_oauth_client.as_ref(),
+            OAuthProvider::Discord => self.discord_oauth_client.as_ref(),
+            // OpenID Connect providers have their own clients.
+            OAuthProvider::Oidc(_) => None,
         };
 
         match provider {
@@ -400,6 +832,18 @@ impl AuthService {
             ))),
         }
     }
+
+    fn get_oidc_client(&self, name: &str) -> ServiceResult<&OidcClient> {
+        self.oidc_clients
+            .iter()
+            .find(|client| client.name() == name)
+            .ok_or_else(|| {
+                ServiceError::InvalidData(format!(
+                    "{} OAuth provider was not enabled for this service.",
+                    name
+                ))
+            })
+    }
 }
 
 // Validate a password.
@@ -431,7 +875,7 @@ struct JwtClaims {
     iat: i64,
 
     /// Expiration date.
-    /// This should be [`None`] if `application_id` is [`Some`].
+    /// This can be [`None`] if `application_id` is [`Some`].
     #[serde(skip_serializing_if = "Option::is_none")]
     exp: Option<i64>,
 
@@ -442,6 +886,32 @@ struct JwtClaims {
     /// This should be [`Some`] if the token was an application token.
     #[serde(skip_serializing_if = "Option::is_none")]
     application_id: Option<String>,
+
+    /// Scopes the application token was created with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scopes: Option<Vec<ApplicationScope>>,
+
+    /// Version of the application token, older versions have been rotated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token_version: Option<i32>,
+
+    /// ID of the session.
+    /// This should be [`Some`] if the token is a login token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
+}
+
+/// Data stored in a two-factor challenge.
+#[derive(Serialize, Deserialize)]
+struct ChallengeClaims {
+    /// Audience, always [`CHALLENGE_AUDIENCE`].
+    aud: String,
+
+    /// Expiration date.
+    exp: i64,
+
+    /// Subject, user ID the challenge is for.
+    sub: String,
 }
 
 /// Validate a password and hash it.
@@ -467,26 +937,3 @@ fn random_digit_str(digits: u32) -> i32 {
     let p = 10i32.pow(digits - 1);
     rand::thread_rng().gen_range(p..10 * p)
 }
-
-fn make_redirect_url(
-    redirect: Option<String>,
-    token: &str,
-    include_redirect: bool,
-) -> Option<String> {
-    match redirect {
-        Some(v) => {
-            match if include_redirect {
-                let mut map = HashMap::new();
-                map.insert("token", token.to_owned());
-
-                Url::parse_with_params(&v, map)
-            } else {
-                Url::parse(&v)
-            } {
-                Ok(v) => Some(v.to_string()),
-                Err(_) => None,
-            }
-        }
-        None => None,
-    }
-}
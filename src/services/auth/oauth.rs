@@ -1,6 +1,5 @@
-use std::pin::Pin;
+use std::{fmt, pin::Pin};
 
-use derive_more::Display;
 use futures::Future;
 use moka::future::Cache;
 use oauth2::{
@@ -8,8 +7,7 @@ use oauth2::{
     ClientSecret, CsrfToken, RedirectUrl, Scope, TokenResponse, TokenUrl,
 };
 use serde::de::DeserializeOwned;
-use serde::Deserialize;
-use utoipa::ToSchema;
+use serde::{Deserialize, Deserializer};
 
 use crate::config::OAuthConfig;
 use crate::database::entity::sea_orm_active_enums::AuthMethod;
@@ -17,12 +15,51 @@ use crate::models::OAuthRequest;
 use crate::services::{ServiceError, ServiceResult};
 
 /// All OAuth providers.
-#[derive(Debug, Display, Clone, Copy, Deserialize, ToSchema)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq)]
 pub enum OAuthProvider {
     Google,
     Github,
     Discord,
+    /// OpenID Connect provider configured by name.
+    Oidc(String),
+}
+
+impl OAuthProvider {
+    /// Name of the provider used in URLs.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Google => "google",
+            Self::Github => "github",
+            Self::Discord => "discord",
+            Self::Oidc(name) => name,
+        }
+    }
+
+    /// Provider stored with auth methods, this is only set for OpenID Connect providers.
+    pub fn method_provider(&self) -> &str {
+        match self {
+            Self::Oidc(name) => name,
+            _ => "",
+        }
+    }
+}
+
+impl fmt::Display for OAuthProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for OAuthProvider {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Every name which isn't a built in provider refers to an OpenID Connect provider.
+        Ok(match String::deserialize(deserializer)?.as_str() {
+            "google" => Self::Google,
+            "github" => Self::Github,
+            "discord" => Self::Discord,
+            name => Self::Oidc(name.to_owned()),
+        })
+    }
 }
 
 impl Into<AuthMethod> for OAuthProvider {
@@ -31,6 +68,7 @@ impl Into<AuthMethod> for OAuthProvider {
             Self::Google => AuthMethod::Google,
             Self::Github => AuthMethod::Github,
             Self::Discord => AuthMethod::Discord,
+            Self::Oidc(_) => AuthMethod::Oidc,
         }
     }
 }
@@ -52,6 +90,10 @@ impl OAuthProvider {
     ///
     /// * `config` - OAuth config.
     /// * `callback_url` - Callback URL.
+    ///
+    /// # Panics
+    ///
+    /// OpenID Connect providers use [`super::oidc::OidcClient`] instead.
     pub fn new_client(&self, config: OAuthConfig, callback_url: &str) -> OAuthClient {
         match self {
             OAuthProvider::Google => OAuthClient::new(
@@ -168,6 +210,9 @@ impl OAuthProvider {
                     })
                 },
             ),
+            OAuthProvider::Oidc(_) => {
+                panic!("OpenID Connect providers are not created from an OAuth config")
+            }
         }
     }
 }
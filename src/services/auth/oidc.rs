@@ -0,0 +1,362 @@
+//! Generic OpenID Connect providers.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use moka::future::Cache;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::oauth::{OAuthState, OAuthUserData};
+use crate::{
+    config::OidcConfig,
+    internal::random_string,
+    models::OAuthRequest,
+    services::{ServiceError, ServiceResult},
+};
+
+/// Discovery document fields which are used.
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// JSON web key, only RSA keys are supported.
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    #[serde(rename = "use")]
+    key_use: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+struct ProviderMetadata {
+    discovery: Discovery,
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    id_token: Option<String>,
+}
+
+pub struct OidcClient {
+    config: OidcConfig,
+    redirect_url: String,
+    http_client: reqwest::Client,
+    /// Discovery document and signing keys.
+    /// These are fetched on first use and refreshed hourly, or early if a token is signed with an unknown key.
+    metadata: Cache<bool, Arc<ProviderMetadata>>,
+    /// Cache stores state secrets to OAuth state and the nonce the ID token must contain.
+    state_cache: Cache<String, (OAuthState, String)>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig, redirect_url: &str) -> Self {
+        Self {
+            config,
+            redirect_url: redirect_url.to_owned(),
+            http_client: reqwest::Client::builder()
+                .user_agent("Backpack")
+                .build()
+                .unwrap(),
+            metadata: Cache::builder()
+                .time_to_live(Duration::from_secs(60 * 60))
+                .build(),
+            // 10 minute expiry time.
+            state_cache: Cache::builder()
+                .time_to_live(Duration::from_secs(60 * 10))
+                .build(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.config.display_name
+    }
+
+    /// Initiate a login.
+    /// Start the login session by redirecting the user to the provider URL.
+    pub async fn login(
+        &self,
+        user_id: Option<String>,
+        redirect: Option<String>,
+        include_redirect: bool,
+    ) -> ServiceResult<oauth2::url::Url> {
+        let metadata = self.metadata(false).await?;
+
+        let state = random_string(32);
+        let nonce = random_string(32);
+        let scopes = self.config.scopes.join(" ");
+
+        let url = oauth2::url::Url::parse_with_params(
+            &metadata.discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.redirect_url.as_str()),
+                ("scope", scopes.as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+            ],
+        )
+        .map_err(|e| ServiceError::ServerError(e.into()))?;
+
+        self.state_cache
+            .insert(
+                state,
+                (
+                    OAuthState {
+                        user_id,
+                        redirect,
+                        include_redirect,
+                    },
+                    nonce,
+                ),
+            )
+            .await;
+
+        Ok(url)
+    }
+
+    /// Use auth params provided by the provider to get the user data from the ID token.
+    pub async fn get_user_data(
+        &self,
+        oauth_request: &OAuthRequest,
+    ) -> ServiceResult<(OAuthUserData, OAuthState)> {
+        let (oauth_state, nonce) = match self.state_cache.get(&oauth_request.state) {
+            Some(v) => {
+                self.state_cache.invalidate(&oauth_request.state).await;
+                v
+            }
+            None => return Err(ServiceError::Unauthorized("Invalid Csrf token.".into())),
+        };
+
+        let metadata = self.metadata(false).await?;
+
+        // Exchange the code with a token.
+        let token = self
+            .http_client
+            .post(&metadata.discovery.token_endpoint)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", oauth_request.code.as_str()),
+                ("redirect_uri", self.redirect_url.as_str()),
+            ])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ServiceError::ServerError(e.into()))?
+            .json::<TokenResponse>()
+            .await
+            .map_err(|e| ServiceError::ServerError(e.into()))?;
+
+        let id_token = token.id_token.ok_or_else(|| {
+            ServiceError::ServerError(anyhow::anyhow!(
+                "OpenID Connect provider {} did not return an ID token",
+                self.config.name
+            ))
+        })?;
+
+        let mut claims = self.validate_id_token(&id_token, &nonce).await?;
+
+        // Some providers only include profile claims in the userinfo response.
+        if !claims.contains_key(&self.config.email_claim) {
+            if let Some(userinfo_endpoint) = &metadata.discovery.userinfo_endpoint {
+                let userinfo = self
+                    .http_client
+                    .get(userinfo_endpoint)
+                    .bearer_auth(&token.access_token)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| ServiceError::ServerError(e.into()))?
+                    .json::<HashMap<String, Value>>()
+                    .await
+                    .map_err(|e| ServiceError::ServerError(e.into()))?;
+
+                // The userinfo subject must match the ID token.
+                if userinfo.get("sub") == claims.get("sub") {
+                    for (claim, value) in userinfo {
+                        claims.entry(claim).or_insert(value);
+                    }
+                }
+            }
+        }
+
+        Ok((self.map_claims(&claims)?, oauth_state))
+    }
+
+    /// Map ID token claims to user data using the configured claims.
+    fn map_claims(&self, claims: &HashMap<String, Value>) -> ServiceResult<OAuthUserData> {
+        let claim = |name: &str| {
+            claims
+                .get(name)
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_owned())
+        };
+
+        let id = claim("sub").ok_or_else(|| {
+            ServiceError::ServerError(anyhow::anyhow!("ID token is missing the sub claim"))
+        })?;
+
+        let email = claim(&self.config.email_claim).ok_or_else(|| {
+            ServiceError::InvalidData(format!(
+                "{} did not provide an email for the account.",
+                self.config.display_name
+            ))
+        })?;
+
+        let username = match claim(&self.config.username_claim) {
+            Some(v) => v,
+            None => email[..email.find('@').unwrap_or(email.len())].to_owned(),
+        };
+
+        Ok(OAuthUserData {
+            id,
+            username,
+            verified: claims
+                .get("email_verified")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(self.config.trust_email),
+            email,
+        })
+    }
+
+    /// Validate the signature, issuer, audience, expiry and nonce of an ID token.
+    ///
+    /// Returns the claims of the token.
+    async fn validate_id_token(
+        &self,
+        id_token: &str,
+        nonce: &str,
+    ) -> ServiceResult<HashMap<String, Value>> {
+        let header = decode_header(id_token).map_err(|e| ServiceError::ServerError(e.into()))?;
+
+        match header.alg {
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => {}
+            alg => {
+                return Err(ServiceError::ServerError(anyhow::anyhow!(
+                    "ID token algorithm {:?} is not supported, only RSA keys can be used",
+                    alg
+                )))
+            }
+        }
+
+        // Keys may have been rotated since they were fetched.
+        let mut metadata = self.metadata(false).await?;
+        if find_key(&metadata.keys, header.kid.as_deref()).is_none() {
+            metadata = self.metadata(true).await?;
+        }
+
+        let (n, e) = find_key(&metadata.keys, header.kid.as_deref()).ok_or_else(|| {
+            ServiceError::ServerError(anyhow::anyhow!("ID token was signed with an unknown key"))
+        })?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&metadata.discovery.issuer]);
+
+        let claims = decode::<HashMap<String, Value>>(
+            id_token,
+            &DecodingKey::from_rsa_components(n, e)
+                .map_err(|e| ServiceError::ServerError(e.into()))?,
+            &validation,
+        )
+        .map_err(|_| ServiceError::Unauthorized("Invalid ID token".into()))?
+        .claims;
+
+        if claims.get("nonce").and_then(|v| v.as_str()) != Some(nonce) {
+            return Err(ServiceError::Unauthorized("Invalid ID token".into()));
+        }
+
+        Ok(claims)
+    }
+
+    /// Get the discovery document and signing keys.
+    ///
+    /// * `refresh` - Fetch them again even if they are cached.
+    async fn metadata(&self, refresh: bool) -> ServiceResult<Arc<ProviderMetadata>> {
+        if !refresh {
+            if let Some(metadata) = self.metadata.get(&true) {
+                return Ok(metadata);
+            }
+        }
+
+        let discovery = self
+            .fetch::<Discovery>(&format!(
+                "{}/.well-known/openid-configuration",
+                self.config.issuer
+            ))
+            .await?;
+
+        // The configured issuer has its trailing slash removed, the discovered one may keep it.
+        // Tokens are still checked against the exact issuer from the discovery document.
+        if discovery.issuer.trim_end_matches('/') != self.config.issuer {
+            return Err(ServiceError::ServerError(anyhow::anyhow!(
+                "OpenID Connect provider {} reported issuer {}, expected {}",
+                self.config.name,
+                discovery.issuer,
+                self.config.issuer
+            )));
+        }
+
+        let keys = self.fetch::<JwkSet>(&discovery.jwks_uri).await?.keys;
+
+        let metadata = Arc::new(ProviderMetadata { discovery, keys });
+        self.metadata.insert(true, metadata.clone()).await;
+
+        Ok(metadata)
+    }
+
+    async fn fetch<T: serde::de::DeserializeOwned>(&self, url: &str) -> ServiceResult<T> {
+        self.http_client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ServiceError::ServerError(e.into()))?
+            .json::<T>()
+            .await
+            .map_err(|e| ServiceError::ServerError(e.into()))
+    }
+}
+
+/// Find the RSA components of a signing key.
+/// Tokens without a key ID can only be verified if there is a single signing key.
+fn find_key<'a>(keys: &'a [Jwk], kid: Option<&str>) -> Option<(&'a str, &'a str)> {
+    let mut signing_keys = keys
+        .iter()
+        .filter(|key| key.kty == "RSA" && key.key_use.as_deref() != Some("enc"));
+
+    let key = match kid {
+        Some(kid) => signing_keys.find(|key| key.kid.as_deref() == Some(kid)),
+        None => match (signing_keys.next(), signing_keys.next()) {
+            (Some(key), None) => Some(key),
+            _ => None,
+        },
+    }?;
+
+    Some((key.n.as_deref()?, key.e.as_deref()?))
+}
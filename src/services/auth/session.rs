@@ -0,0 +1,199 @@
+use crate::{
+    database::entity::sessions,
+    internal::{auth::ClientInfo, random_string},
+    services::{
+        prelude::{data_service, DataService},
+        ServiceError, ServiceResult,
+    },
+};
+
+use chrono::Utc;
+use sea_orm::{prelude::*, sea_query::Expr, Condition, QueryOrder, Set};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Days a session can be unused before it expires.
+const SESSION_EXPIRY_DAYS: i64 = 30;
+
+/// Length of refresh tokens.
+const REFRESH_TOKEN_LENGTH: usize = 64;
+
+/// Login sessions of users.
+///
+/// A session is created every time a user logs in and holds a refresh token.
+/// Refresh tokens are exchanged for short-lived access tokens, which are only valid while their session exists.
+pub struct SessionService {
+    database: Arc<DatabaseConnection>,
+}
+
+data_service!(SessionService, sessions);
+
+impl SessionService {
+    pub fn new(database: Arc<DatabaseConnection>) -> Self {
+        Self { database }
+    }
+
+    /// Create a session for a user.
+    ///
+    /// Returns the session and its refresh token.
+    pub async fn create_session(
+        &self,
+        user_id: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<(sessions::Model, String)> {
+        let refresh_token = random_string(REFRESH_TOKEN_LENGTH);
+
+        let session = sessions::ActiveModel {
+            user_id: Set(user_id.to_owned()),
+            refresh_token: Set(hash_token(&refresh_token)),
+            ip: Set(client.ip.to_owned()),
+            user_agent: Set(client.user_agent.to_owned()),
+            expires_at: Set(Utc::now() + chrono::Duration::days(SESSION_EXPIRY_DAYS)),
+            ..Default::default()
+        }
+        .insert(self.database.as_ref())
+        .await
+        .map_err(|e| ServiceError::DbErr(e))?;
+
+        Ok((session, refresh_token))
+    }
+
+    /// Exchange a refresh token for a new one and extend the session.
+    /// The old refresh token can't be used again.
+    ///
+    /// Returns the session and its new refresh token.
+    pub async fn refresh_session(
+        &self,
+        old_refresh_token: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<(sessions::Model, String)> {
+        let session = sessions::Entity::find()
+            .filter(sessions::Column::RefreshToken.eq(hash_token(old_refresh_token)))
+            .one(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?
+            .ok_or_else(|| ServiceError::Unauthorized("Invalid refresh token".into()))?;
+
+        if session.expires_at <= Utc::now() {
+            session
+                .delete(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?;
+
+            return Err(ServiceError::Unauthorized(
+                "Your session has expired".into(),
+            ));
+        }
+
+        let refresh_token = random_string(REFRESH_TOKEN_LENGTH);
+        let session = sessions::Model {
+            refresh_token: hash_token(&refresh_token),
+            ip: client.ip.to_owned(),
+            user_agent: client.user_agent.to_owned(),
+            last_accessed: Utc::now(),
+            expires_at: Utc::now() + chrono::Duration::days(SESSION_EXPIRY_DAYS),
+            ..session
+        };
+
+        // Only rotate if the old token is still stored,
+        // a token used by two requests at once must not give both a new token.
+        let result = sessions::Entity::update_many()
+            .col_expr(
+                sessions::Column::RefreshToken,
+                Expr::value(session.refresh_token.to_owned()),
+            )
+            .col_expr(sessions::Column::Ip, Expr::value(session.ip.to_owned()))
+            .col_expr(
+                sessions::Column::UserAgent,
+                Expr::value(session.user_agent.to_owned()),
+            )
+            .col_expr(
+                sessions::Column::LastAccessed,
+                Expr::value(session.last_accessed),
+            )
+            .col_expr(sessions::Column::ExpiresAt, Expr::value(session.expires_at))
+            .filter(sessions::Column::Id.eq(session.id.to_owned()))
+            .filter(sessions::Column::RefreshToken.eq(hash_token(old_refresh_token)))
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        if result.rows_affected != 1 {
+            return Err(ServiceError::Unauthorized("Invalid refresh token".into()));
+        }
+
+        Ok((session, refresh_token))
+    }
+
+    /// Get a session which has not expired.
+    pub async fn get_active_session(&self, id: &str) -> ServiceResult<sessions::Model> {
+        self.by_condition(
+            Condition::all()
+                .add(sessions::Column::Id.eq(id.to_owned()))
+                .add(sessions::Column::ExpiresAt.gt(Utc::now())),
+        )
+        .await
+    }
+
+    /// Get all sessions of a user which have not expired, most recently used first.
+    pub async fn get_sessions(&self, user_id: &str) -> ServiceResult<Vec<sessions::Model>> {
+        sessions::Entity::find()
+            .filter(sessions::Column::UserId.eq(user_id.to_owned()))
+            .filter(sessions::Column::ExpiresAt.gt(Utc::now()))
+            .order_by_desc(sessions::Column::LastAccessed)
+            .all(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))
+    }
+
+    /// Revoke a session of a user.
+    /// Access tokens of the session stop working immediately.
+    pub async fn revoke_session(&self, id: &str, user_id: &str) -> ServiceResult<String> {
+        self.delete(
+            id.to_owned(),
+            false,
+            Some(Condition::all().add(sessions::Column::UserId.eq(user_id.to_owned()))),
+        )
+        .await
+    }
+
+    /// Revoke every session of a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - User to revoke sessions of.
+    /// * `except` - Session which should not be revoked, usually the current session.
+    pub async fn revoke_all(&self, user_id: &str, except: Option<&str>) -> ServiceResult<String> {
+        let mut condition = Condition::all().add(sessions::Column::UserId.eq(user_id.to_owned()));
+
+        if let Some(except) = except {
+            condition = condition.add(sessions::Column::Id.ne(except.to_owned()));
+        }
+
+        let result = sessions::Entity::delete_many()
+            .filter(condition)
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        Ok(format!("{} sessions were revoked", result.rows_affected))
+    }
+
+    /// Delete all expired sessions.
+    ///
+    /// Returns [`u64`] the amount of sessions deleted.
+    pub async fn delete_expired(&self) -> ServiceResult<u64> {
+        let result = sessions::Entity::delete_many()
+            .filter(sessions::Column::ExpiresAt.lte(Utc::now()))
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        Ok(result.rows_affected)
+    }
+}
+
+/// Refresh tokens are stored as a hex encoded SHA256 hash.
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,337 @@
+use crate::{
+    database::entity::{auth_methods, recovery_codes, sea_orm_active_enums::AuthMethod, users},
+    internal::{auth::ClientInfo, random_string, totp},
+    models::{AuditAction, TotpEnrollment},
+    services::{
+        rate_limit::{limits, RateLimitService},
+        settings::SettingsService,
+        ServiceError, ServiceResult, ToOption,
+    },
+};
+
+use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use chrono::{TimeZone, Utc};
+use rand::rngs::OsRng;
+use sea_orm::{prelude::*, sea_query::Expr, IntoActiveModel, Set};
+use std::sync::Arc;
+
+use super::auth_method::AuthMethodService;
+
+/// Amount of recovery codes generated at once.
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Length of recovery codes, they are shown split in two halves.
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// TOTP two-factor authentication and recovery codes.
+pub struct TwoFactorService {
+    database: Arc<DatabaseConnection>,
+    auth_method_service: Arc<AuthMethodService>,
+    settings_service: Arc<SettingsService>,
+    rate_limit_service: Arc<RateLimitService>,
+}
+
+impl TwoFactorService {
+    pub fn new(
+        database: Arc<DatabaseConnection>,
+        auth_method_service: Arc<AuthMethodService>,
+        settings_service: Arc<SettingsService>,
+        rate_limit_service: Arc<RateLimitService>,
+    ) -> Self {
+        Self {
+            database,
+            auth_method_service,
+            settings_service,
+            rate_limit_service,
+        }
+    }
+
+    /// Check if a user has confirmed TOTP.
+    pub async fn enabled(&self, user_id: &str) -> ServiceResult<bool> {
+        Ok(self
+            .auth_method_service
+            .get_auth_method(user_id, AuthMethod::Totp)
+            .await
+            .to_option()?
+            .map_or(false, |method| method.confirmed))
+    }
+
+    /// Start TOTP enrollment by generating a new secret.
+    /// The secret is not used until it is confirmed with [`TwoFactorService::confirm`].
+    pub async fn enroll(&self, user: &users::Model) -> ServiceResult<TotpEnrollment> {
+        let secret = totp::generate_secret();
+
+        match self
+            .auth_method_service
+            .get_auth_method(&user.id, AuthMethod::Totp)
+            .await
+            .to_option()?
+        {
+            Some(method) if method.confirmed => {
+                return Err(ServiceError::Conflict(
+                    "Two-factor authentication is already enabled".into(),
+                ))
+            }
+            // Replace an enrollment which was never confirmed.
+            Some(method) => {
+                let mut active_method = method.into_active_model();
+                active_method.value = Set(secret.clone());
+                active_method
+                    .update(self.database.as_ref())
+                    .await
+                    .map_err(|e| ServiceError::DbErr(e))?;
+            }
+            None => {
+                auth_methods::ActiveModel {
+                    user_id: Set(user.id.to_owned()),
+                    auth_method: Set(AuthMethod::Totp),
+                    value: Set(secret.clone()),
+                    confirmed: Set(false),
+                    ..Default::default()
+                }
+                .insert(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?;
+            }
+        }
+
+        let settings = self.settings_service.get_settings().await?;
+
+        Ok(TotpEnrollment {
+            uri: totp::otpauth_uri(&settings.app_name, &user.email, &secret),
+            secret,
+        })
+    }
+
+    /// Confirm TOTP enrollment with a code from the authenticator app.
+    ///
+    /// Returns new recovery codes.
+    pub async fn confirm(
+        &self,
+        user_id: &str,
+        code: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<Vec<String>> {
+        let method = self
+            .auth_method_service
+            .get_auth_method(user_id, AuthMethod::Totp)
+            .await
+            .to_option()?
+            .ok_or_else(|| {
+                ServiceError::InvalidData("Two-factor enrollment was not started".into())
+            })?;
+
+        if method.confirmed {
+            return Err(ServiceError::Conflict(
+                "Two-factor authentication is already enabled".into(),
+            ));
+        }
+
+        self.verify_totp(method.clone(), code, true).await?;
+        self.auth_method_service
+            .record(AuditAction::AuthMethodLink, &method, client)
+            .await;
+
+        self.new_recovery_codes(user_id).await
+    }
+
+    /// Disable TOTP and delete recovery codes.
+    pub async fn disable(
+        &self,
+        user_id: &str,
+        code: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<String> {
+        self.verify(user_id, code).await?;
+
+        let method = self
+            .auth_method_service
+            .get_auth_method(user_id, AuthMethod::Totp)
+            .await?;
+
+        auth_methods::Entity::delete_many()
+            .filter(auth_methods::Column::UserId.eq(user_id.to_owned()))
+            .filter(auth_methods::Column::AuthMethod.eq(AuthMethod::Totp))
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        self.delete_recovery_codes(user_id).await?;
+
+        self.auth_method_service
+            .record(AuditAction::AuthMethodUnlink, &method, client)
+            .await;
+
+        Ok("Two-factor authentication was disabled".into())
+    }
+
+    /// Replace all recovery codes of a user, this requires a current code.
+    pub async fn regenerate_recovery_codes(
+        &self,
+        user_id: &str,
+        code: &str,
+    ) -> ServiceResult<Vec<String>> {
+        self.verify(user_id, code).await?;
+        self.new_recovery_codes(user_id).await
+    }
+
+    /// Verify a TOTP code or a recovery code.
+    /// Recovery codes can only be used once.
+    pub async fn verify(&self, user_id: &str, code: &str) -> ServiceResult<()> {
+        self.rate_limit_service
+            .hit(limits::TWO_FACTOR, user_id)
+            .await?;
+
+        self.verify_code(user_id, code).await?;
+        self.rate_limit_service
+            .reset(limits::TWO_FACTOR, user_id)
+            .await
+    }
+
+    async fn verify_code(&self, user_id: &str, code: &str) -> ServiceResult<()> {
+        let method = match self
+            .auth_method_service
+            .get_auth_method(user_id, AuthMethod::Totp)
+            .await
+            .to_option()?
+        {
+            Some(method) if method.confirmed => method,
+            _ => {
+                return Err(ServiceError::InvalidData(
+                    "Two-factor authentication is not enabled".into(),
+                ))
+            }
+        };
+
+        // Recovery codes are longer than TOTP codes.
+        let recovery_code = normalize_recovery_code(code);
+        if recovery_code.len() != RECOVERY_CODE_LENGTH {
+            return self.verify_totp(method, code, false).await;
+        }
+
+        let codes = recovery_codes::Entity::find()
+            .filter(recovery_codes::Column::UserId.eq(user_id.to_owned()))
+            .all(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        for stored in codes {
+            let matches = PasswordHash::new(&stored.code)
+                .map(|hash| {
+                    Argon2::default()
+                        .verify_password(recovery_code.as_bytes(), &hash)
+                        .is_ok()
+                })
+                .unwrap_or(false);
+
+            if matches {
+                // Another request may have used the same code in the meantime.
+                let result = recovery_codes::Entity::delete_many()
+                    .filter(recovery_codes::Column::Id.eq(stored.id.to_owned()))
+                    .exec(self.database.as_ref())
+                    .await
+                    .map_err(|e| ServiceError::DbErr(e))?;
+
+                if result.rows_affected == 1 {
+                    return Ok(());
+                }
+
+                break;
+            }
+        }
+
+        Err(ServiceError::InvalidData("Invalid two-factor code".into()))
+    }
+
+    /// Check a TOTP code and record its time step so it can't be used again.
+    async fn verify_totp(
+        &self,
+        method: auth_methods::Model,
+        code: &str,
+        confirm: bool,
+    ) -> ServiceResult<()> {
+        let step = totp::verify(&method.value, code)
+            .ok_or_else(|| ServiceError::InvalidData("Invalid two-factor code".into()))?;
+
+        // `last_accessed` holds the start of the time step of the last used code.
+        // The update only applies if no code of this or a later step was used,
+        // so concurrent requests can't use the same code twice.
+        let used_at = Utc.timestamp(totp::step_start(step), 0);
+        let mut update = auth_methods::Entity::update_many()
+            .col_expr(auth_methods::Column::LastAccessed, Expr::value(used_at))
+            .filter(auth_methods::Column::Id.eq(method.id.to_owned()));
+
+        if method.confirmed {
+            update = update.filter(auth_methods::Column::LastAccessed.lt(used_at));
+        }
+
+        if confirm {
+            update = update.col_expr(auth_methods::Column::Confirmed, Expr::value(true));
+        }
+
+        let result = update
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        if result.rows_affected != 1 {
+            return Err(ServiceError::InvalidData(
+                "That two-factor code was already used".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Replace the recovery codes of a user.
+    ///
+    /// Returns the new codes, only hashes are stored.
+    async fn new_recovery_codes(&self, user_id: &str) -> ServiceResult<Vec<String>> {
+        self.delete_recovery_codes(user_id).await?;
+
+        let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+        let mut models = Vec::with_capacity(RECOVERY_CODE_COUNT);
+
+        for _ in 0..RECOVERY_CODE_COUNT {
+            let code = random_string(RECOVERY_CODE_LENGTH).to_lowercase();
+            let hash = Argon2::default()
+                .hash_password(code.as_bytes(), &SaltString::generate(&mut OsRng))
+                .map_err(|e| ServiceError::ServerError(e.into()))?
+                .to_string();
+
+            models.push(recovery_codes::ActiveModel {
+                user_id: Set(user_id.to_owned()),
+                code: Set(hash),
+                ..Default::default()
+            });
+
+            let (first, second) = code.split_at(RECOVERY_CODE_LENGTH / 2);
+            codes.push(format!("{}-{}", first, second));
+        }
+
+        recovery_codes::Entity::insert_many(models)
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        Ok(codes)
+    }
+
+    async fn delete_recovery_codes(&self, user_id: &str) -> ServiceResult<()> {
+        recovery_codes::Entity::delete_many()
+            .filter(recovery_codes::Column::UserId.eq(user_id.to_owned()))
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        Ok(())
+    }
+}
+
+/// Recovery codes are case insensitive and can be entered with or without the separator.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
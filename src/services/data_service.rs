@@ -2,7 +2,9 @@
 
 use super::{ServiceError, ServicePage, ServiceResult};
 use heck::AsTitleCase;
-use sea_orm::{prelude::*, Condition, FromQueryResult, IntoActiveModel};
+use sea_orm::{
+    prelude::*, sea_query::LikeExpr, Condition, FromQueryResult, IntoActiveModel, Order, QueryOrder,
+};
 use std::sync::Arc;
 
 /// Automatically implement a [`DataService`] with an associated entity.
@@ -130,15 +132,31 @@ pub trait DataService<
         page: usize,
         page_size: usize,
         condition: Option<Condition>,
+    ) -> ServiceResult<ServicePage<M>> {
+        self.get_sorted_page(page, page_size, condition, vec![])
+            .await
+    }
+
+    /// Get a [`ServicePage`] of [`M`] sorted by columns, the first column is sorted first.
+    async fn get_sorted_page(
+        &self,
+        page: usize,
+        page_size: usize,
+        condition: Option<Condition>,
+        order: Vec<(E::Column, Order)>,
     ) -> ServiceResult<ServicePage<M>> {
         let (db, _) = self.get_data_source();
 
-        let paginator = match condition {
+        let mut query = match condition {
             Some(condition) => E::find().filter(condition),
             None => E::find(),
+        };
+
+        for (column, order) in order {
+            query = query.order_by(column, order);
         }
-        .into_model::<M>()
-        .paginate(db.as_ref(), page_size);
+
+        let paginator = query.into_model::<M>().paginate(db.as_ref(), page_size);
 
         let total_pages = paginator
             .num_pages()
@@ -173,3 +191,16 @@ fn remove_first_and_last(value: String) -> String {
     chars.next_back();
     chars.collect()
 }
+
+/// `LIKE` pattern which matches values containing `search`.
+/// Wildcards in `search` are escaped so they match literally.
+///
+/// `!` is used as the escape character since backslashes are quoted differently by each database.
+pub fn contains_pattern(search: &str) -> LikeExpr {
+    let escaped = search
+        .replace('!', "!!")
+        .replace('%', "!%")
+        .replace('_', "!_");
+
+    LikeExpr::new(format!("%{}%", escaped)).escape('!')
+}
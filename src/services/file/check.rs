@@ -0,0 +1,182 @@
+use futures::TryStreamExt;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, Set,
+};
+use std::collections::HashSet;
+
+use super::{image_processing::variant_keys, stream::UploadDigest, FileService};
+use crate::{
+    database::entity::{blobs, files},
+    models::admin::file::StorageReport,
+    services::prelude::*,
+};
+
+impl FileService {
+    /// Compare stored objects to the database.
+    ///
+    /// Problems are only repaired if `repair` is true:
+    /// - Files and blobs without an object are deleted.
+    /// - Objects which aren't referenced by a blob are deleted from storage.
+    /// - Missing thumbnails are regenerated, or removed from the blob if that fails.
+    ///
+    /// Objects are only hashed if `verify_hashes` is true since every object has to be read.
+    /// Hash mismatches are reported but can't be repaired.
+    ///
+    /// Repairs should not run while files are being uploaded since an object is written before its blob is created.
+    pub async fn check_storage(
+        &self,
+        repair: bool,
+        verify_hashes: bool,
+    ) -> ServiceResult<StorageReport> {
+        let objects: HashSet<String> = self
+            .storage
+            .list_objects()
+            .await
+            .map_err(|e| ServiceError::ServerError(e))?
+            .into_iter()
+            .collect();
+
+        let blobs = blobs::Entity::find()
+            .all(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        let files = files::Entity::find()
+            .all(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        let mut report = StorageReport {
+            objects: objects.len(),
+            blobs: blobs.len(),
+            files: files.len(),
+            repaired: repair,
+            ..Default::default()
+        };
+
+        // Keys of all objects referenced by blobs.
+        let mut referenced = HashSet::new();
+        // Hashes of blobs which have an object.
+        let mut stored = HashSet::new();
+
+        for blob in &blobs {
+            let thumbnail = format!("thumb/{}", blob.name);
+
+            referenced.insert(blob.name.to_owned());
+            if blob.has_thumbnail {
+                referenced.insert(thumbnail.to_owned());
+            }
+            referenced.extend(variant_keys(&blob.name, &blob.variants));
+
+            if !objects.contains(&blob.name) {
+                report.missing_objects.push(blob.name.to_owned());
+                continue;
+            }
+
+            stored.insert(blob.hash.to_owned());
+
+            if blob.has_thumbnail && !objects.contains(&thumbnail) {
+                report.missing_thumbnails.push(blob.name.to_owned());
+            }
+
+            if verify_hashes {
+                // An object which can't be read doesn't match either.
+                match self.object_hash(&blob.name).await {
+                    Ok(hash) if hash == blob.hash => {}
+                    _ => report.hash_mismatches.push(blob.name.to_owned()),
+                }
+            }
+        }
+
+        report.missing_files = files
+            .iter()
+            .filter(|file| !stored.contains(&file.hash))
+            .map(|file| file.id.to_owned())
+            .collect();
+
+        report.orphaned_objects = objects
+            .into_iter()
+            .filter(|key| !referenced.contains(key))
+            .collect();
+        report.orphaned_objects.sort();
+
+        if repair {
+            self.repair_storage(&report).await?;
+        }
+
+        Ok(report)
+    }
+
+    /// Repair the problems found by [`FileService::check_storage`].
+    async fn repair_storage(&self, report: &StorageReport) -> ServiceResult<()> {
+        if !report.orphaned_objects.is_empty() {
+            self.storage
+                .delete_objects(report.orphaned_objects.clone())
+                .await
+                .map_err(|e| ServiceError::ServerError(e))?;
+        }
+
+        if !report.missing_files.is_empty() {
+            files::Entity::delete_many()
+                .filter(files::Column::Id.is_in(report.missing_files.clone()))
+                .exec(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?;
+        }
+
+        if !report.missing_objects.is_empty() {
+            blobs::Entity::delete_many()
+                .filter(blobs::Column::Name.is_in(report.missing_objects.clone()))
+                .exec(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?;
+        }
+
+        for name in &report.missing_thumbnails {
+            let blob = match blobs::Entity::find()
+                .filter(blobs::Column::Name.eq(name.to_owned()))
+                .one(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?
+            {
+                Some(v) => v,
+                None => continue,
+            };
+
+            if self.create_thumbnail(&blob.name, blob.size).await {
+                // Thumbnails have the same access as the blob.
+                self.storage
+                    .set_object_access(vec![format!("thumb/{}", blob.name)], blob.public)
+                    .await
+                    .map_err(|e| ServiceError::ServerError(e))?;
+                continue;
+            }
+
+            files::Entity::update_many()
+                .col_expr(files::Column::HasThumbnail, Expr::value(false))
+                .filter(files::Column::Hash.eq(blob.hash.to_owned()))
+                .exec(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?;
+
+            let mut active_blob = blob.into_active_model();
+            active_blob.has_thumbnail = Set(false);
+            active_blob
+                .update(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Hex encoded SHA-256 hash of a stored object.
+    async fn object_hash(&self, key: &str) -> Result<String, anyhow::Error> {
+        let (stream, digest) =
+            UploadDigest::wrap(self.storage.get_object_stream(key, None).await?, usize::MAX);
+        stream.try_for_each(|_| async { Ok(()) }).await?;
+
+        let hash = digest.lock().unwrap().hash();
+        Ok(hash)
+    }
+}
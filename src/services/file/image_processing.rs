@@ -0,0 +1,553 @@
+//! Processing of uploaded JPEG and PNG images.
+//!
+//! Metadata can be stripped, images can be converted to WebP or AVIF,
+//! and resized variants are created which are stored next to the image.
+
+#[cfg(feature = "avif")]
+use image::codecs::avif::AvifEncoder;
+use image::{
+    codecs::{
+        jpeg::JpegEncoder,
+        webp::{WebPEncoder, WebPQuality},
+    },
+    imageops::FilterType,
+    io::{Limits, Reader},
+    ColorType, DynamicImage, ImageEncoder, ImageOutputFormat,
+};
+use std::{convert::TryInto, io::Cursor};
+
+use crate::config::{ImageConfig, ImageFormat};
+
+/// Speed of the AVIF encoder from 1 to 10, slower speeds produce smaller files.
+#[cfg(feature = "avif")]
+const AVIF_SPEED: u8 = 6;
+
+/// Most memory the decoder may allocate for a single image.
+const DECODE_ALLOC_LIMIT: u64 = 256 * 1024 * 1024;
+
+/// EXIF tag of the orientation.
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Bytes needed to detect the format of an image.
+pub const HEADER_LENGTH: usize = 32;
+
+/// Format of a processed image.
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Jpeg,
+    Png,
+    WebP,
+    Avif,
+}
+
+impl Format {
+    fn detect(data: &[u8]) -> Option<Self> {
+        match infer::get(data)?.mime_type() {
+            "image/jpeg" => Some(Self::Jpeg),
+            "image/png" => Some(Self::Png),
+            "image/webp" => Some(Self::WebP),
+            "image/avif" => Some(Self::Avif),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::WebP => "webp",
+            Self::Avif => "avif",
+        }
+    }
+}
+
+impl From<ImageFormat> for Format {
+    fn from(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::WebP => Self::WebP,
+            ImageFormat::Avif => Self::Avif,
+        }
+    }
+}
+
+/// Result of processing an uploaded image.
+pub struct ProcessedImage {
+    /// Image which should be stored instead of the upload.
+    pub data: Vec<u8>,
+    /// Extension of the image if it was converted to another format.
+    pub extension: Option<&'static str>,
+    /// Names and contents of the resized variants.
+    pub variants: Vec<(String, Vec<u8>)>,
+}
+
+/// Processes images as configured by [`ImageConfig`].
+///
+/// Processing is CPU bound, so it should run on a blocking thread.
+pub struct ImageProcessor {
+    config: ImageConfig,
+}
+
+impl ImageProcessor {
+    pub fn new(config: ImageConfig) -> Self {
+        Self { config }
+    }
+
+    /// Whether an upload is processed.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - Start of the upload, this is used to detect the image format.
+    pub fn supports(&self, header: &[u8]) -> bool {
+        let enabled = self.config.strip_metadata
+            || self.config.format.is_some()
+            || !self.config.variants.is_empty();
+
+        enabled && matches!(Format::detect(header), Some(Format::Jpeg | Format::Png))
+    }
+
+    /// Largest image in bytes which can be processed.
+    pub fn max_size(&self) -> usize {
+        self.config.max_size
+    }
+
+    /// Process an uploaded image.
+    /// Converted images never contain metadata, their orientation is applied to the pixels instead.
+    pub fn process(&self, data: Vec<u8>) -> Result<ProcessedImage, anyhow::Error> {
+        if data.len() > self.config.max_size {
+            return Err(anyhow::anyhow!("Image is too large to process"));
+        }
+
+        let format = match Format::detect(&data) {
+            Some(format @ (Format::Jpeg | Format::Png)) => format,
+            _ => return Err(anyhow::anyhow!("Unsupported image format")),
+        };
+
+        let orientation = orientation(&data, format);
+
+        // Images are only decoded if their pixels are needed.
+        let image = match self.config.format.is_some() || !self.config.variants.is_empty() {
+            true => Some(orient(self.decode(&data)?, orientation)),
+            false => None,
+        };
+
+        let (data, extension, format) = match (self.config.format, &image) {
+            (Some(target), Some(image)) => {
+                let target = Format::from(target);
+                (
+                    encode(image, target, self.config.quality)?,
+                    Some(target.extension()),
+                    target,
+                )
+            }
+            _ if self.config.strip_metadata => {
+                let data = match format {
+                    Format::Jpeg => strip_jpeg(&data, orientation),
+                    _ => strip_png(&data, orientation),
+                }
+                .ok_or_else(|| anyhow::anyhow!("Invalid image"))?;
+
+                (data, None, format)
+            }
+            _ => (data, None, format),
+        };
+
+        let variants = match &image {
+            Some(image) => self.variants(image, format)?,
+            None => Vec::new(),
+        };
+
+        Ok(ProcessedImage {
+            data,
+            extension,
+            variants,
+        })
+    }
+
+    /// Whether variants can be created for a stored object.
+    /// AVIF images can't be decoded, so their variants can only be created when they are uploaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - Start of the object, this is used to detect the image format.
+    pub fn supports_stored(&self, header: &[u8]) -> bool {
+        matches!(
+            Format::detect(header),
+            Some(Format::Jpeg | Format::Png | Format::WebP)
+        )
+    }
+
+    /// Create the variants of an image which is already stored.
+    /// Variants are encoded in the format of the stored image.
+    pub fn variants_of_stored(&self, data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, anyhow::Error> {
+        let format = match Format::detect(data) {
+            Some(format @ (Format::Jpeg | Format::Png | Format::WebP)) => format,
+            _ => return Err(anyhow::anyhow!("Unsupported image format")),
+        };
+
+        if self.config.variants.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let image = orient(self.decode(data)?, orientation(data, format));
+        self.variants(&image, format)
+    }
+
+    /// Decode an image, images which are too large to decode safely are rejected.
+    fn decode(&self, data: &[u8]) -> Result<DynamicImage, anyhow::Error> {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(self.config.max_dimension);
+        limits.max_image_height = Some(self.config.max_dimension);
+        limits.max_alloc = Some(DECODE_ALLOC_LIMIT);
+
+        let mut reader = Reader::new(Cursor::new(data)).with_guessed_format()?;
+        reader.limits(limits);
+
+        Ok(reader.decode()?)
+    }
+
+    /// Resize an image to every variant which is smaller than the image.
+    /// Images are never enlarged, so small images may not have every variant.
+    fn variants(
+        &self,
+        image: &DynamicImage,
+        format: Format,
+    ) -> Result<Vec<(String, Vec<u8>)>, anyhow::Error> {
+        self.config
+            .variants
+            .iter()
+            .filter(|(_, size)| image.width().max(image.height()) > *size)
+            .map(|(name, size)| -> Result<(String, Vec<u8>), anyhow::Error> {
+                let resized = image.resize(*size, *size, FilterType::Lanczos3);
+                Ok((
+                    name.to_owned(),
+                    encode(&resized, format, self.config.quality)?,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Key of a variant of a stored object.
+/// Variants are stored next to the object as `{id}.{variant}.{extension}`.
+pub fn variant_key(name: &str, variant: &str) -> String {
+    match name.split_once('.') {
+        Some((stem, extension)) => format!("{}.{}.{}", stem, variant, extension),
+        None => format!("{}.{}", name, variant),
+    }
+}
+
+/// Keys of all variants of a stored object.
+///
+/// # Arguments
+///
+/// * `name` - Name of the object.
+/// * `variants` - Space separated variant names, as stored on blobs and files.
+pub fn variant_keys(name: &str, variants: &str) -> Vec<String> {
+    variants
+        .split_whitespace()
+        .map(|variant| variant_key(name, variant))
+        .collect()
+}
+
+/// Name of the object a variant key belongs to.
+/// Returns [`None`] if the key is not a variant key.
+pub fn variant_source(key: &str) -> Option<String> {
+    let mut parts = key.splitn(3, '.');
+    let (stem, _, extension) = (parts.next()?, parts.next()?, parts.next()?);
+
+    Some(format!("{}.{}", stem, extension))
+}
+
+/// Encode an image without metadata.
+fn encode(image: &DynamicImage, format: Format, quality: u8) -> Result<Vec<u8>, anyhow::Error> {
+    let mut buf = Vec::new();
+
+    match format {
+        // JPEG has no transparency.
+        Format::Jpeg => {
+            let image = image.to_rgb8();
+            JpegEncoder::new_with_quality(&mut buf, quality).write_image(
+                &image,
+                image.width(),
+                image.height(),
+                ColorType::Rgb8,
+            )?;
+        }
+        Format::Png => image.write_to(&mut Cursor::new(&mut buf), ImageOutputFormat::Png)?,
+        Format::WebP => {
+            let image = image.to_rgba8();
+            // Lossy encoding is deprecated in newer versions of image, but it is the only way to set a quality.
+            #[allow(deprecated)]
+            WebPEncoder::new_with_quality(&mut buf, WebPQuality::lossy(quality)).write_image(
+                &image,
+                image.width(),
+                image.height(),
+                ColorType::Rgba8,
+            )?;
+        }
+        #[cfg(feature = "avif")]
+        Format::Avif => {
+            let image = image.to_rgba8();
+            AvifEncoder::new_with_speed_quality(&mut buf, AVIF_SPEED, quality).write_image(
+                &image,
+                image.width(),
+                image.height(),
+                ColorType::Rgba8,
+            )?;
+        }
+        #[cfg(not(feature = "avif"))]
+        Format::Avif => return Err(anyhow::anyhow!("AVIF support is not enabled")),
+    }
+
+    Ok(buf)
+}
+
+/// Rotate and flip an image as described by its EXIF orientation.
+fn orient(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// EXIF orientation of an image, `1` if it is not set.
+fn orientation(data: &[u8], format: Format) -> u16 {
+    let exif = match format {
+        Format::Jpeg => jpeg_segments(data).and_then(|segments| {
+            segments
+                .into_iter()
+                .find(|(marker, segment)| *marker == 0xE1 && segment.starts_with(b"Exif\0\0"))
+                .map(|(_, segment)| &segment[6..])
+        }),
+        Format::Png => png_chunks(data).and_then(|chunks| {
+            chunks
+                .into_iter()
+                .find(|(kind, _)| *kind == b"eXIf")
+                .map(|(_, chunk)| chunk)
+        }),
+        _ => None,
+    };
+
+    exif.and_then(tiff_orientation)
+        .filter(|orientation| (1..=8).contains(orientation))
+        .unwrap_or(1)
+}
+
+/// Read the orientation from the first IFD of TIFF encoded EXIF data.
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+
+    let u16_at = |pos: usize| -> Option<u16> {
+        let bytes = [*tiff.get(pos)?, *tiff.get(pos + 1)?];
+        Some(match big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let bytes = [
+            *tiff.get(pos)?,
+            *tiff.get(pos + 1)?,
+            *tiff.get(pos + 2)?,
+            *tiff.get(pos + 3)?,
+        ];
+        Some(match big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let entries = u16_at(ifd)? as usize;
+
+    (0..entries)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|entry| u16_at(*entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))
+}
+
+/// Big endian TIFF data with only the orientation tag.
+fn orientation_tiff(orientation: u16) -> Vec<u8> {
+    let mut tiff = Vec::with_capacity(26);
+    tiff.extend_from_slice(b"MM\0\x2a");
+    // First IFD directly follows the header.
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    // SHORT with a count of 1.
+    tiff.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    // No next IFD.
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff
+}
+
+/// Split a JPEG into the marker and contents of every segment before the image data.
+/// Returns [`None`] if the JPEG is invalid.
+fn jpeg_segments(data: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let mut segments = Vec::new();
+    let mut pos = 2;
+
+    loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+
+        let marker = *data.get(pos + 1)?;
+        if marker == 0xFF {
+            // Fill byte.
+            pos += 1;
+            continue;
+        }
+
+        // Start of scan, image data follows.
+        if marker == 0xDA {
+            segments.push((marker, data.get(pos..)?));
+            return Some(segments);
+        }
+
+        let length = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        if length < 2 {
+            return None;
+        }
+
+        segments.push((marker, data.get(pos + 4..pos + 2 + length)?));
+        pos += 2 + length;
+    }
+}
+
+/// Remove metadata from a JPEG without re-encoding it.
+///
+/// JFIF, Adobe and ICC profile segments are kept since they affect how the image is displayed,
+/// anything appended after the image (like embedded previews) is removed.
+fn strip_jpeg(data: &[u8], orientation: u16) -> Option<Vec<u8>> {
+    let segments = jpeg_segments(data)?;
+    let mut stripped = Vec::with_capacity(data.len());
+    stripped.extend_from_slice(&[0xFF, 0xD8]);
+
+    let mut exif_written = orientation == 1;
+
+    for (marker, segment) in segments {
+        // The orientation is written after the JFIF segment, which has to come first.
+        if !exif_written && marker != 0xE0 {
+            let mut exif = b"Exif\0\0".to_vec();
+            exif.extend_from_slice(&orientation_tiff(orientation));
+
+            stripped.extend_from_slice(&[0xFF, 0xE1]);
+            stripped.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+            stripped.extend_from_slice(&exif);
+            exif_written = true;
+        }
+
+        if marker == 0xDA {
+            // Image data ends at the first end of image marker, which can't appear within it.
+            let end = segment
+                .windows(2)
+                .position(|bytes| bytes == [0xFF, 0xD9])
+                .map(|pos| pos + 2)
+                .unwrap_or(segment.len());
+
+            stripped.extend_from_slice(&segment[..end]);
+            break;
+        }
+
+        let keep = match marker {
+            // Application segments and comments.
+            0xE2 => segment.starts_with(b"ICC_PROFILE\0"),
+            0xE0 | 0xEE => true,
+            0xE1..=0xEF | 0xFE => false,
+            _ => true,
+        };
+
+        if keep {
+            stripped.extend_from_slice(&[0xFF, marker]);
+            stripped.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+            stripped.extend_from_slice(segment);
+        }
+    }
+
+    Some(stripped)
+}
+
+/// PNG signature, which comes before the first chunk.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Split a PNG into the type and data of every chunk, up to and including `IEND`.
+/// Returns [`None`] if the PNG is invalid.
+fn png_chunks(data: &[u8]) -> Option<Vec<(&[u8], &[u8])>> {
+    if !data.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+
+    loop {
+        let length = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let kind = data.get(pos + 4..pos + 8)?;
+        let chunk = data.get(pos + 8..(pos + 8).checked_add(length)?)?;
+
+        chunks.push((kind, chunk));
+        pos += 12 + length;
+
+        if kind == b"IEND" {
+            return Some(chunks);
+        }
+    }
+}
+
+/// Remove metadata from a PNG without re-encoding it.
+/// Color information like gamma and ICC profiles is kept.
+fn strip_png(data: &[u8], orientation: u16) -> Option<Vec<u8>> {
+    let chunks = png_chunks(data)?;
+    let mut stripped = Vec::with_capacity(data.len());
+    stripped.extend_from_slice(PNG_SIGNATURE);
+
+    for (kind, chunk) in chunks {
+        match kind {
+            b"eXIf" => {
+                if orientation != 1 {
+                    write_png_chunk(&mut stripped, b"eXIf", &orientation_tiff(orientation));
+                }
+            }
+            b"tEXt" | b"zTXt" | b"iTXt" | b"tIME" => {}
+            _ => write_png_chunk(&mut stripped, kind, chunk),
+        }
+    }
+
+    Some(stripped)
+}
+
+/// Append a chunk with its length and checksum to a PNG.
+fn write_png_chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc32(kind.iter().chain(data)).to_be_bytes());
+}
+
+/// CRC-32 used by PNG chunks.
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    !bytes.fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xEDB8_8320,
+            _ => crc >> 1,
+        })
+    })
+}
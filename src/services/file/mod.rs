@@ -1,40 +1,80 @@
+mod check;
+mod image_processing;
 mod providers;
+mod stream;
+mod thumbnail;
 
-use image::{io::Reader, ImageError};
+use bytes::Bytes;
+use chrono::Utc;
+use futures::{StreamExt, TryStreamExt};
 use migration::Alias;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait,
-    IntoActiveModel, ModelTrait, QueryFilter, QuerySelect, QueryTrait, Set,
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
+    EntityTrait, IntoActiveModel, ModelTrait, PaginatorTrait, QueryFilter, QuerySelect, QueryTrait,
+    Set, TransactionTrait,
 };
-use sha2::{Digest, Sha256};
 use std::{
     collections::HashSet,
     ffi::OsStr,
-    io::Cursor,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
-use self::providers::StorageProvider;
+pub use self::{
+    image_processing::{variant_key, variant_keys, ImageProcessor},
+    providers::{
+        local::{file_stream, object_path, shard_objects},
+        new_storage, ObjectRange, ObjectStream, StorageProvider,
+    },
+    stream::UploadDigest,
+    thumbnail::{TempInput, Thumbnailer, ThumbnailerRegistry},
+};
 
-use super::prelude::*;
+use super::{prelude::*, settings::SettingsService, webhook::WebhookService};
 use crate::{
-    config::StorageConfig,
-    database::entity::files,
-    models::{BatchDeleteResponse, BatchFileError, FileData, FileStats},
+    config::{ImageConfig, StorageConfig},
+    database::entity::{blobs, files, sea_orm_active_enums::Visibility, users},
+    internal::signature::Signer,
+    models::{BatchDeleteResponse, BatchFileError, FileData, FileLink, FileStats, WebhookEvent},
 };
 
+/// How long links in [`FileData`] for private files are valid.
+const PRIVATE_LINK_EXPIRY: Duration = Duration::from_secs(60 * 60);
+
+/// Bytes read from the start of an object to detect its type.
+const HEADER_SIZE: u64 = 8192;
+
+/// Longest time in seconds files can be kept for before expiring.
+pub const MAX_EXPIRATION: u64 = 10 * 365 * 24 * 60 * 60;
+
+/// Longest expiry allowed for signed links.
+/// S3 does not accept presigned URLs which are valid for longer than a week.
+pub const MAX_LINK_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
 /// Service for managing files.
 ///
 /// Files contain extra fields outside of the model which are located in the [`FileData`] model.
 /// Most operations in [`FileService`] return [`FileData`] instead of [`files::Model`].
+///
+/// Stored objects are tracked by [`blobs::Model`] and shared between files with the same hash.
+/// A blob is only removed from storage once no files reference it.
+///
+/// Objects can be read directly from storage while any file referencing them is not private.
+/// Private objects are read through signed links.
 pub struct FileService {
     /// Public storage handle.
     /// Use at your own risk.
-    pub storage: Box<dyn StorageProvider>,
+    pub storage: Arc<dyn StorageProvider>,
     database: Arc<DatabaseConnection>,
     storage_url: String,
-    file_size_limit: usize,
+    /// File size limit and default storage quota are read from settings.
+    settings_service: Arc<SettingsService>,
+    webhook_service: Arc<WebhookService>,
+    signer: Signer,
+    thumbnailers: Arc<ThumbnailerRegistry>,
+    /// Uploaded images are stored unchanged if this is not set.
+    images: Option<Arc<ImageProcessor>>,
 }
 
 data_service!(FileService, files);
@@ -43,33 +83,76 @@ data_service!(FileService, files);
 pub enum UploadResult {
     /// Upload success.
     Success(FileData),
-    /// File was already uploaded.
+    /// File was already uploaded by the same user.
     Conflict(FileData),
 }
 
+/// Largest file a user can currently upload.
+pub struct UploadLimit {
+    /// Smaller of the file size limit and the remaining storage quota.
+    pub limit: usize,
+    pub file_size_limit: usize,
+}
+
+impl UploadLimit {
+    /// Error returned when an upload is larger than the limit.
+    pub fn exceeded(&self) -> ServiceError {
+        if self.limit < self.file_size_limit {
+            ServiceError::TooLarge(format!(
+                "File was larger than the remaining storage quota of {} bytes",
+                self.limit
+            ))
+        } else {
+            ServiceError::TooLarge(format!(
+                "File was larger than the size limit of {} bytes",
+                self.file_size_limit
+            ))
+        }
+    }
+}
+
 impl FileService {
     pub async fn new(
         database: Arc<DatabaseConnection>,
         config: StorageConfig,
         storage_url: &str,
-        file_size_limit: usize,
+        settings_service: Arc<SettingsService>,
+        webhook_service: Arc<WebhookService>,
+        signing_key: &str,
+        image_config: Option<ImageConfig>,
     ) -> Self {
         Self {
             database,
-            storage: providers::new_storage(config).await,
+            storage: providers::new_storage(config).await.into(),
             storage_url: storage_url.into(),
-            file_size_limit: file_size_limit * 1000 * 1000,
+            settings_service,
+            webhook_service,
+            signer: Signer::new(signing_key),
+            thumbnailers: Arc::new(ThumbnailerRegistry::detect().await),
+            images: image_config.map(|config| Arc::new(ImageProcessor::new(config))),
+        }
+    }
+
+    /// Get a file by ID.
+    /// Expired files are not found even if they have not been deleted yet.
+    pub async fn file_by_id(&self, id: &str) -> ServiceResult<files::Model> {
+        let file = self.by_id(id.into()).await?;
+
+        if file.expires_at.map_or(false, |v| v <= Utc::now()) {
+            return Err(ServiceError::NotFound(self.resource_name()));
         }
+
+        Ok(file)
     }
 
-    /// Get a file. If you don't need ownership validation use `by_id`
+    /// Get a file. If you don't need ownership validation use `file_by_id`
     ///
     /// # Arguments
     ///
     /// * `id` - File ID.
     /// * `user_id` - User who owns this file. This will validate ownership.
     pub async fn get_file(&self, id: &str, user_id: Option<&str>) -> ServiceResult<FileData> {
-        let file = self.by_id(id.into()).await?;
+        let file = self.file_by_id(id).await?;
 
         if let Some(user_id) = user_id {
             if file.uploader != user_id {
@@ -80,7 +163,7 @@ impl FileService {
             }
         }
 
-        Ok(self.to_file_data(file))
+        self.file_data(file).await
     }
 
     /// Delete a file.
@@ -89,8 +172,14 @@ impl FileService {
     ///
     /// * `id` - File ID.
     /// * `user_id` - User who owns this file. If provided this will validate ownership.
-    pub async fn delete_file(&self, id: &str, user_id: Option<&str>) -> ServiceResult<String> {
-        let file = self.by_id(id.into()).await?;
+    /// * `application_id` - Application deleting the file, if an application token was used.
+    pub async fn delete_file(
+        &self,
+        id: &str,
+        user_id: Option<&str>,
+        application_id: Option<&str>,
+    ) -> ServiceResult<String> {
+        let file = self.file_by_id(id).await?;
 
         if let Some(user_id) = user_id {
             if file.uploader != user_id {
@@ -106,11 +195,8 @@ impl FileService {
             .await
             .map_err(|e| ServiceError::DbErr(e))?;
 
-        // We dont care about the result of this because of discrepancies
-        let _ = self
-            .storage
-            .delete_objects(vec![file.name.clone(), format!("thumb/{}", &file.name)])
-            .await;
+        self.release_blobs(&[file.clone()]).await?;
+        self.emit_deleted(&[file.clone()], application_id).await;
 
         Ok(format!("File {} was deleted", file.name))
     }
@@ -121,10 +207,12 @@ impl FileService {
     ///
     /// * `ids` - List of file IDs.
     /// * `user_id` - User who owns this file. If provided this will validate ownership.
+    /// * `application_id` - Application deleting the files, if an application token was used.
     pub async fn delete_batch(
         &self,
         ids: &Vec<String>,
         user_id: Option<&str>,
+        application_id: Option<&str>,
     ) -> ServiceResult<BatchDeleteResponse> {
         let mut response = BatchDeleteResponse::default();
 
@@ -147,6 +235,8 @@ impl FileService {
             }
         }
 
+        let mut deleted = vec![];
+
         for file in files {
             if let Some(user_id) = user_id {
                 if file.uploader != user_id {
@@ -164,33 +254,83 @@ impl FileService {
                 .await
                 .map_err(|e| ServiceError::DbErr(e))?;
 
-            // We dont care about the result of this because of possible discrepancies, just pretend the file is deleted.
-            let _ = self
-                .storage
-                .delete_objects(vec![file.name.clone(), format!("thumb/{}", &file.name)])
-                .await;
-
-            response.deleted.push(file.id);
+            response.deleted.push(file.id.to_owned());
+            deleted.push(file);
         }
 
+        self.release_blobs(&deleted).await?;
+        self.emit_deleted(&deleted, application_id).await;
+
         Ok(response)
     }
 
+    /// Get the largest file a user can currently upload.
+    /// Fails if the storage quota of the user is already used up.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - User uploading the file.
+    pub async fn upload_limit(&self, user: &users::Model) -> ServiceResult<UploadLimit> {
+        let settings = self.settings_service.get_settings().await?;
+        let file_size_limit = settings.file_size_limit.max(0) as usize;
+
+        // The upload can't be larger than the remaining storage quota.
+        let remaining = match user.storage_quota.or(settings.default_quota) {
+            Some(quota) => Some((quota - self.user_usage(&user.id).await?).max(0) as usize),
+            None => None,
+        };
+
+        if remaining == Some(0) {
+            return Err(ServiceError::TooLarge("Storage quota exceeded".into()));
+        }
+
+        Ok(UploadLimit {
+            limit: match remaining {
+                Some(remaining) => remaining.min(file_size_limit),
+                None => file_size_limit,
+            },
+            file_size_limit,
+        })
+    }
+
     /// Upload a file to the storage provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - User who is uploading the file.
+    /// * `application_id` - Application uploading the file, if an application token was used.
+    /// * `name` - Original name of the file.
+    /// * `visibility` - Who can access the file.
+    /// * `expiration` - Seconds until the file is deleted. The file is kept forever if this is [`None`].
+    /// * `stream` - File contents, this is written to storage while it is read.
+    ///   Images are read entirely before they are stored if image processing is enabled.
     pub async fn upload_file(
         &self,
-        user_id: &str,
+        user: &users::Model,
+        application_id: Option<&str>,
         name: &str,
-        buffer: &Vec<u8>,
+        visibility: Visibility,
+        expiration: Option<u64>,
+        stream: ObjectStream,
     ) -> ServiceResult<UploadResult> {
-        if buffer.len() > self.file_size_limit {
-            return Err(ServiceError::TooLarge(format!(
-                "File was larger than the size limit of {}mb",
-                self.file_size_limit / 1000 / 1000
-            )));
-        }
+        let expires_at = match expiration {
+            Some(v) => Some(Utc::now() + chrono::Duration::seconds(validate_expiration(v)?)),
+            None => None,
+        };
+
+        let limit = self.upload_limit(user).await?;
+        let (stream, converted, variants) = self.process_image(stream, &limit).await?;
+
+        // Converted images are named after their new format.
+        let original_name = match converted {
+            Some(extension) => Path::new(name)
+                .with_extension(extension)
+                .to_string_lossy()
+                .into_owned(),
+            None => name.to_owned(),
+        };
 
-        let extension = Path::new(&name)
+        let extension = Path::new(&original_name)
             .extension()
             .and_then(OsStr::to_str)
             .unwrap_or("");
@@ -198,65 +338,722 @@ impl FileService {
         // New filename, collision not likely with NanoID
         let filename = format!("{}.{}", nanoid::nanoid!(10), extension);
 
-        let hash = &format!("{:x}", Sha256::digest(&buffer));
+        // Hash and size are computed while the file is being written.
+        // Processed images are hashed as stored, so the hash can be verified against the object.
+        let (stream, digest) = UploadDigest::wrap(stream, limit.limit);
 
+        if let Err(err) = self.storage.put_object_stream(&filename, stream).await {
+            return Err(if digest.lock().unwrap().exceeded() {
+                limit.exceeded()
+            } else {
+                ServiceError::ServerError(err)
+            });
+        }
+
+        let (hash, size) = {
+            let digest = digest.lock().unwrap();
+            (digest.hash(), digest.size())
+        };
+
+        // Only report a conflict if the same user already uploaded this.
         let file_exists = files::Entity::find()
+            .filter(files::Column::Uploader.eq(user.id.to_owned()))
             .filter(files::Column::Hash.eq(hash.to_owned()))
             .one(self.database.as_ref())
             .await
             .map_err(|e| ServiceError::DbErr(e))?;
 
         if let Some(file) = file_exists {
-            return Ok(UploadResult::Conflict(self.to_file_data(file)));
+            let _ = self.storage.delete_objects(vec![filename]).await;
+            return Ok(UploadResult::Conflict(self.file_data(file).await?));
         }
 
-        let mut file = files::ActiveModel {
-            uploader: Set(user_id.into()),
-            name: Set(filename.to_owned()),
-            original_name: Set(name.into()),
+        let (blob, created) = match blobs::Entity::find()
+            .filter(blobs::Column::Hash.eq(hash.to_owned()))
+            .one(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?
+        {
+            Some(blob) => match self.acquire_blob(blob).await? {
+                // Identical content is already stored, the new copy isn't needed.
+                Some(blob) => {
+                    let _ = self.storage.delete_objects(vec![filename]).await;
+                    (blob, false)
+                }
+                // The blob was released while this file was uploaded, the new copy is used instead.
+                None => (
+                    self.create_blob(&filename, &hash, size as i64, variants)
+                        .await?,
+                    true,
+                ),
+            },
+            None => (
+                self.create_blob(&filename, &hash, size as i64, variants)
+                    .await?,
+                true,
+            ),
+        };
+
+        let file = match (files::ActiveModel {
+            uploader: Set(user.id.to_owned()),
+            name: Set(blob.name.to_owned()),
+            original_name: Set(original_name),
             hash: Set(hash.to_owned()),
-            size: Set(buffer.len() as i64),
+            size: Set(blob.size),
+            has_thumbnail: Set(blob.has_thumbnail),
+            variants: Set(blob.variants.to_owned()),
+            visibility: Set(visibility),
+            expires_at: Set(expires_at.map(|v| v.into())),
             ..Default::default()
+        })
+        .insert(self.database.as_ref())
+        .await
+        {
+            Ok(v) => v,
+            Err(err) => {
+                // Drop the reference which was taken for this file.
+                let _ = self.release_blob(&hash).await;
+                return Err(ServiceError::DbErr(err));
+            }
+        };
+
+        self.sync_blob_access(&hash).await?;
+
+        if created {
+            self.spawn_thumbnail(blob);
+        }
+
+        let file_data = self.file_data(file).await?;
+        self.webhook_service
+            .emit(
+                WebhookEvent::FileUploaded,
+                &user.id,
+                application_id,
+                &file_data,
+            )
+            .await;
+
+        Ok(UploadResult::Success(file_data))
+    }
+
+    /// Process an upload if it is an image supported by the [`ImageProcessor`].
+    /// Other uploads are passed through without being buffered,
+    /// supported images are read into memory up to [`ImageProcessor::max_size`].
+    ///
+    /// Returns the stream which should be stored, the new extension if the image was converted
+    /// and the variants which should be stored with it.
+    async fn process_image(
+        &self,
+        mut stream: ObjectStream,
+        limit: &UploadLimit,
+    ) -> ServiceResult<(ObjectStream, Option<&'static str>, Vec<(String, Vec<u8>)>)> {
+        let processor = match &self.images {
+            Some(v) => v.clone(),
+            None => return Ok((stream, None, Vec::new())),
+        };
+
+        // The format is detected from the start of the stream, which is put back in front of it.
+        // Chunks can be arbitrarily small, so they are buffered until the header is complete.
+        let mut header = Vec::new();
+        while header.len() < image_processing::HEADER_LENGTH {
+            match stream.next().await {
+                Some(chunk) => {
+                    header.extend_from_slice(&chunk.map_err(|e| ServiceError::ServerError(e))?)
+                }
+                None => break,
+            }
+        }
+
+        let supported = processor.supports(&header);
+        let header = Bytes::from(header);
+        let stream: ObjectStream =
+            Box::pin(futures::stream::once(async move { Ok(header) }).chain(stream));
+
+        if !supported {
+            return Ok((stream, None, Vec::new()));
+        }
+
+        // Images are processed in memory, so they are capped separately from the upload limit.
+        let mut stream = stream;
+        let mut data = Vec::new();
+
+        while let Some(chunk) = stream.next().await {
+            data.extend_from_slice(&chunk.map_err(|e| ServiceError::ServerError(e))?);
+
+            if data.len() > limit.limit {
+                return Err(limit.exceeded());
+            }
+
+            if data.len() > processor.max_size() {
+                return Err(ServiceError::TooLarge(format!(
+                    "Images larger than {} bytes can't be processed",
+                    processor.max_size()
+                )));
+            }
+        }
+
+        // Decoding and encoding is CPU bound, so it can't run on the async workers.
+        let image = tokio::task::spawn_blocking(move || processor.process(data))
+            .await
+            .map_err(|e| ServiceError::ServerError(e.into()))?
+            .map_err(|_| ServiceError::InvalidData("Unable to process image".into()))?;
+
+        let data = Bytes::from(image.data);
+        let stream: ObjectStream = Box::pin(futures::stream::once(async move { Ok(data) }));
+
+        Ok((stream, image.extension, image.variants))
+    }
+
+    /// Delete all files which have expired.
+    /// Objects of blobs which are no longer referenced are removed from storage.
+    ///
+    /// Returns [`usize`] the amount of files deleted.
+    pub async fn delete_expired(&self) -> ServiceResult<usize> {
+        let files = files::Entity::find()
+            .filter(files::Column::ExpiresAt.lte(Utc::now()))
+            .all(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        if files.is_empty() {
+            return Ok(0);
+        }
+
+        files::Entity::delete_many()
+            .filter(files::Column::Id.is_in(files.iter().map(|f| f.id.to_owned())))
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        self.release_blobs(&files).await?;
+        self.emit_deleted(&files, None).await;
+
+        Ok(files.len())
+    }
+
+    /// Release the blobs referenced by deleted files.
+    /// Blobs which are no longer referenced are removed from storage.
+    pub async fn release_blobs(&self, files: &[files::Model]) -> ServiceResult<()> {
+        let mut objects = vec![];
+
+        for file in files {
+            if self.release_blob(&file.hash).await? {
+                objects.extend(blob_objects(&file.name, &file.variants));
+            } else {
+                // The deleted file may have been the one which kept the blob private.
+                self.sync_blob_access(&file.hash).await?;
+            }
+        }
+
+        if !objects.is_empty() {
+            // We dont care about the result of this because of discrepancies
+            let _ = self.storage.delete_objects(objects).await;
         }
+
+        Ok(())
+    }
+
+    /// Drop a reference to a blob by hash.
+    ///
+    /// Returns [`bool`] whether the blob was unreferenced and deleted.
+    /// The objects of a deleted blob are not removed from storage.
+    async fn release_blob(&self, hash: &str) -> ServiceResult<bool> {
+        // The decrement locks the row until the blob is deleted,
+        // so it can't be acquired between reaching zero and being deleted.
+        let txn = self
+            .database
+            .begin()
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        blobs::Entity::update_many()
+            .col_expr(
+                blobs::Column::RefCount,
+                Expr::col(blobs::Column::RefCount).sub(1),
+            )
+            .filter(blobs::Column::Hash.eq(hash.to_owned()))
+            .exec(&txn)
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        let result = blobs::Entity::delete_many()
+            .filter(blobs::Column::Hash.eq(hash.to_owned()))
+            .filter(blobs::Column::RefCount.lte(0))
+            .exec(&txn)
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        txn.commit().await.map_err(|e| ServiceError::DbErr(e))?;
+
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Add a reference to an existing blob.
+    ///
+    /// Returns [`None`] if the blob is no longer referenced, it is about to be deleted and can't be used.
+    async fn acquire_blob(&self, blob: blobs::Model) -> ServiceResult<Option<blobs::Model>> {
+        let result = blobs::Entity::update_many()
+            .col_expr(
+                blobs::Column::RefCount,
+                Expr::col(blobs::Column::RefCount).add(1),
+            )
+            .filter(blobs::Column::Id.eq(blob.id.to_owned()))
+            .filter(blobs::Column::RefCount.gt(0))
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        Ok(match result.rows_affected {
+            0 => None,
+            _ => Some(blob),
+        })
+    }
+
+    /// Create a blob for an object which was just written to storage.
+    /// The object is deleted if the blob can't be created.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the object.
+    /// * `hash` - Hash of the object.
+    /// * `size` - Size of the object.
+    /// * `variants` - Names and contents of image variants, these are stored with the object.
+    async fn create_blob(
+        &self,
+        name: &str,
+        hash: &str,
+        size: i64,
+        variants: Vec<(String, Vec<u8>)>,
+    ) -> ServiceResult<blobs::Model> {
+        let blob = match (blobs::ActiveModel {
+            name: Set(name.to_owned()),
+            hash: Set(hash.to_owned()),
+            size: Set(size),
+            ref_count: Set(1),
+            // Objects are private until a file which isn't private references them.
+            public: Set(false),
+            ..Default::default()
+        })
         .insert(self.database.as_ref())
         .await
-        .map_err(|e| ServiceError::DbErr(e))?;
+        {
+            Ok(v) => v,
+            Err(err) => {
+                let _ = self.storage.delete_objects(vec![name.to_owned()]).await;
+                return Err(ServiceError::DbErr(err));
+            }
+        };
+
+        let variants = self.store_variants(name, variants).await;
 
-        // Upload file to storage provider
-        // If this fails attempt to delete the file from database
-        if let Err(err) = self.storage.put_object(&filename, &buffer).await {
-            let _ = file.delete(self.database.as_ref()).await;
-            return Err(ServiceError::ServerError(err));
+        if variants.is_empty() {
+            return Ok(blob);
         }
 
-        // Create thumbnail.
-        if IMAGE_EXTS
-            .into_iter()
-            .any(|ext| ext.eq(&extension.to_uppercase()))
+        let mut active_blob = blob.into_active_model();
+        active_blob.variants = Set(variants);
+        active_blob
+            .update(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))
+    }
+
+    /// Store the variants of an object.
+    /// Variants which can't be stored are skipped.
+    ///
+    /// Returns [`String`] the space separated names of the stored variants.
+    async fn store_variants(&self, name: &str, variants: Vec<(String, Vec<u8>)>) -> String {
+        let mut stored = vec![];
+
+        for (variant, data) in variants {
+            match self
+                .storage
+                .put_object(&variant_key(name, &variant), &data)
+                .await
+            {
+                Ok(_) => stored.push(variant),
+                Err(err) => log::warn!("Unable to store {} variant of {}: {}", variant, name, err),
+            }
+        }
+
+        stored.join(" ")
+    }
+
+    /// Create the thumbnail object of a stored object if a thumbnailer supports its type.
+    ///
+    /// Returns [`bool`] whether the thumbnail was created.
+    async fn create_thumbnail(&self, name: &str, size: i64) -> bool {
+        create_thumbnail(self.storage.as_ref(), &self.thumbnailers, name, size).await
+    }
+
+    /// Create the thumbnail of a new blob in the background, so uploads don't wait for it.
+    /// The blob and the files referencing it are updated once the thumbnail is stored.
+    fn spawn_thumbnail(&self, blob: blobs::Model) {
+        let database = self.database.clone();
+        let storage = self.storage.clone();
+        let thumbnailers = self.thumbnailers.clone();
+
+        tokio::spawn(async move {
+            if !create_thumbnail(storage.as_ref(), &thumbnailers, &blob.name, blob.size).await {
+                return;
+            }
+
+            let thumbnail = format!("thumb/{}", blob.name);
+
+            if let Err(err) =
+                store_thumbnail(database.as_ref(), storage.as_ref(), &blob, &thumbnail).await
+            {
+                log::warn!("Unable to store thumbnail of {}: {}", blob.name, err);
+                let _ = storage.delete_objects(vec![thumbnail]).await;
+            }
+        });
+    }
+
+    /// Create the thumbnail and image variants of a blob again.
+    /// Blobs which had no thumbnail get one if a thumbnailer supports them now.
+    /// Variants are created as currently configured, variants which are no longer configured are deleted.
+    ///
+    /// Returns [`bool`] whether the thumbnail was created.
+    pub async fn regenerate_previews(&self, blob: &blobs::Model) -> ServiceResult<bool> {
+        let has_thumbnail = self.create_thumbnail(&blob.name, blob.size).await;
+        let variants = self.regenerate_variants(blob).await?;
+
+        // Previews have the same access as the blob.
+        let mut objects = variant_keys(&blob.name, &variants);
+        if has_thumbnail {
+            objects.push(format!("thumb/{}", blob.name));
+        }
+
+        if !objects.is_empty() {
+            self.storage
+                .set_object_access(objects, blob.public)
+                .await
+                .map_err(|e| ServiceError::ServerError(e))?;
+        }
+
+        // Thumbnails which can't be created anymore are kept.
+        let has_thumbnail = has_thumbnail || blob.has_thumbnail;
+
+        if has_thumbnail != blob.has_thumbnail || variants != blob.variants {
+            let mut active_blob = blob.clone().into_active_model();
+            active_blob.has_thumbnail = Set(has_thumbnail);
+            active_blob.variants = Set(variants.to_owned());
+            active_blob
+                .update(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?;
+
+            files::Entity::update_many()
+                .col_expr(files::Column::HasThumbnail, Expr::value(has_thumbnail))
+                .col_expr(files::Column::Variants, Expr::value(variants))
+                .filter(files::Column::Hash.eq(blob.hash.to_owned()))
+                .exec(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?;
+        }
+
+        Ok(has_thumbnail)
+    }
+
+    /// Replace the variants of a blob with the currently configured variants.
+    /// Variants are kept as they are if image processing is disabled or the object can't be resized.
+    ///
+    /// Returns [`String`] the space separated names of the stored variants.
+    async fn regenerate_variants(&self, blob: &blobs::Model) -> ServiceResult<String> {
+        let processor = match &self.images {
+            Some(v) => v.clone(),
+            None => return Ok(blob.variants.to_owned()),
+        };
+
+        if blob.size as u64 > processor.max_size() as u64 {
+            return Ok(blob.variants.to_owned());
+        }
+
+        // Objects which aren't images are never read entirely.
+        match read_header(self.storage.as_ref(), &blob.name, blob.size).await {
+            Ok(header) if processor.supports_stored(&header) => {}
+            _ => return Ok(blob.variants.to_owned()),
+        }
+
+        let data = match self.storage.get_object(&blob.name).await {
+            Ok(v) => v,
+            Err(_) => return Ok(blob.variants.to_owned()),
+        };
+
+        let variants = tokio::task::spawn_blocking(move || processor.variants_of_stored(&data))
+            .await
+            .map_err(|e| ServiceError::ServerError(e.into()))?
+            .map_err(|e| ServiceError::ServerError(e))?;
+
+        let stored = self.store_variants(&blob.name, variants).await;
+
+        // Remove variants which were not created again.
+        let obsolete: Vec<String> = blob
+            .variants
+            .split_whitespace()
+            .filter(|variant| !stored.split_whitespace().any(|v| v == *variant))
+            .map(|variant| variant_key(&blob.name, variant))
+            .collect();
+
+        if !obsolete.is_empty() {
+            let _ = self.storage.delete_objects(obsolete).await;
+        }
+
+        Ok(stored)
+    }
+
+    /// Update who can access a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - File ID.
+    /// * `user_id` - User who owns this file. If provided this will validate ownership.
+    /// * `visibility` - New visibility of the file.
+    pub async fn set_visibility(
+        &self,
+        id: &str,
+        user_id: Option<&str>,
+        visibility: Visibility,
+    ) -> ServiceResult<FileData> {
+        let file = self.file_by_id(id).await?;
+
+        if let Some(user_id) = user_id {
+            if file.uploader != user_id {
+                return Err(ServiceError::Forbidden {
+                    id: id.into(),
+                    resource: self.resource_name(),
+                });
+            }
+        }
+
+        let mut active_file = file.into_active_model();
+        active_file.visibility = Set(visibility);
+
+        let file = active_file
+            .update(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        self.sync_blob_access(&file.hash).await?;
+
+        self.file_data(file).await
+    }
+
+    /// Make the objects of a blob public or private based on the files referencing it.
+    /// A blob is only public if no file referencing it is private,
+    /// other users may have uploaded the same content privately.
+    async fn sync_blob_access(&self, hash: &str) -> ServiceResult<()> {
+        let blob = match blobs::Entity::find()
+            .filter(blobs::Column::Hash.eq(hash.to_owned()))
+            .one(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?
         {
-            if let Ok(image) = &create_thumbnail_image(&buffer) {
-                if let Ok(_) = self
-                    .storage
-                    .put_object(&format!("thumb/{}", &filename), image)
-                    .await
-                {
-                    let mut active_file = file.into_active_model();
-                    active_file.has_thumbnail = Set(true);
-                    file = active_file
-                        .update(self.database.as_ref())
-                        .await
-                        .map_err(|e| ServiceError::DbErr(e))?;
-                }
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let private = files::Entity::find()
+            .filter(files::Column::Hash.eq(hash.to_owned()))
+            .filter(files::Column::Visibility.eq(Visibility::Private))
+            .count(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        let public = private == 0;
+
+        if blob.public == public {
+            return Ok(());
+        }
+
+        let mut objects = vec![blob.name.to_owned()];
+
+        if blob.has_thumbnail {
+            objects.push(format!("thumb/{}", blob.name));
+        }
+
+        objects.extend(variant_keys(&blob.name, &blob.variants));
+
+        self.storage
+            .set_object_access(objects, public)
+            .await
+            .map_err(|e| ServiceError::ServerError(e))?;
+
+        let mut active_blob = blob.into_active_model();
+        active_blob.public = Set(public);
+        active_blob
+            .update(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        Ok(())
+    }
+
+    /// Create a signed link to a file which expires.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - File ID.
+    /// * `user_id` - User who owns this file. If provided this will validate ownership.
+    /// * `expires_in` - How long the link is valid for, at most [`MAX_LINK_EXPIRY`].
+    pub async fn file_link(
+        &self,
+        id: &str,
+        user_id: Option<&str>,
+        expires_in: Duration,
+    ) -> ServiceResult<FileLink> {
+        if expires_in.is_zero() || expires_in > MAX_LINK_EXPIRY {
+            return Err(ServiceError::InvalidData(format!(
+                "Links must expire within {} seconds",
+                MAX_LINK_EXPIRY.as_secs()
+            )));
+        }
+
+        let file = self.file_by_id(id).await?;
+
+        if let Some(user_id) = user_id {
+            if file.uploader != user_id {
+                return Err(ServiceError::Forbidden {
+                    id: id.into(),
+                    resource: self.resource_name(),
+                });
             }
         }
 
-        Ok(UploadResult::Success(self.to_file_data(file)))
+        let expires = Utc::now() + chrono::Duration::seconds(expires_in.as_secs() as i64);
+
+        Ok(FileLink {
+            url: self.object_link(&file.name, expires.timestamp()),
+            expires,
+        })
     }
 
-    pub async fn user_stats(&self, user_id: &str) -> ServiceResult<FileStats> {
+    /// Check if a signed link to an object is valid.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Object key.
+    /// * `expires` - Unix timestamp the link expires at.
+    /// * `signature` - Signature of the link.
+    pub fn verify_link(&self, key: &str, expires: i64, signature: &str) -> bool {
+        self.signer.verify(key, expires, signature)
+    }
+
+    /// Check if an object can be read from local storage.
+    /// Public objects can always be read, private objects require a signed link.
+    /// Objects which are only referenced by expired files can't be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Object key.
+    /// * `expires` - Unix timestamp the link expires at.
+    /// * `signature` - Signature of the link.
+    pub async fn can_read_object(
+        &self,
+        key: &str,
+        expires: Option<i64>,
+        signature: Option<&str>,
+    ) -> ServiceResult<bool> {
+        // Thumbnails and variants can be read if their blob can be read.
+        let name = match key.strip_prefix("thumb/") {
+            Some(name) => name.to_owned(),
+            None => image_processing::variant_source(key).unwrap_or_else(|| key.to_owned()),
+        };
+
+        let blob = blobs::Entity::find()
+            .filter(blobs::Column::Name.eq(name))
+            .one(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        let blob = match blob {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+
+        // Expired files are only deleted periodically, their objects can't be read until then.
+        let unexpired = files::Entity::find()
+            .filter(files::Column::Hash.eq(blob.hash.to_owned()))
+            .filter(
+                Condition::any()
+                    .add(files::Column::ExpiresAt.is_null())
+                    .add(files::Column::ExpiresAt.gt(Utc::now())),
+            )
+            .count(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        if unexpired == 0 {
+            return Ok(false);
+        }
+
+        Ok(match (expires, signature) {
+            _ if blob.public => true,
+            (Some(expires), Some(signature)) => self.verify_link(key, expires, signature),
+            _ => false,
+        })
+    }
+
+    /// Link to an object which can be read until the expiry timestamp.
+    /// Presigned URLs are used if the storage provider supports them.
+    fn object_link(&self, key: &str, expires: i64) -> String {
+        let expires_in = Duration::from_secs((expires - Utc::now().timestamp()).max(0) as u64);
+
+        match self.storage.presigned_url(key, expires_in) {
+            Some(url) => url,
+            None => format!(
+                "{}/{}?expires={}&signature={}",
+                self.storage_url.trim_end_matches('/'),
+                key,
+                expires,
+                self.signer.sign(key, expires)
+            ),
+        }
+    }
+
+    /// Get a stream of a file's contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - File to read.
+    /// * `range` - Byte range to read. The whole file is read if this is [`None`].
+    pub async fn get_content(
+        &self,
+        file: &files::Model,
+        range: Option<ObjectRange>,
+    ) -> ServiceResult<ObjectStream> {
+        self.storage
+            .get_object_stream(&file.name, range)
+            .await
+            .map_err(|e| ServiceError::ServerError(e))
+    }
+
+    /// Get file stats for a user.
+    pub async fn user_stats(&self, user: &users::Model) -> ServiceResult<FileStats> {
+        let usage = self.user_usage(&user.id).await?;
+        let limit = self.user_quota(user).await?;
+
+        Ok(FileStats {
+            usage,
+            limit,
+            remaining: limit.map(|limit| (limit - usage).max(0)),
+        })
+    }
+
+    /// Storage quota of a user in bytes.
+    /// Returns [`None`] if the user has unlimited storage.
+    async fn user_quota(&self, user: &users::Model) -> ServiceResult<Option<i64>> {
+        Ok(match user.storage_quota {
+            Some(quota) => Some(quota),
+            None => self.settings_service.get_settings().await?.default_quota,
+        })
+    }
+
+    /// Total size of all files uploaded by a user in bytes.
+    async fn user_usage(&self, user_id: &str) -> ServiceResult<i64> {
         let expr = files::Entity::find()
             .select_only()
-            .filter(files::Column::Uploader.eq(user_id.clone()))
+            .filter(files::Column::Uploader.eq(user_id))
             .column_as(
                 files::Column::Size.sum().cast_as(Alias::new("BIGINT")),
                 "sum",
@@ -270,15 +1067,13 @@ impl FileService {
             .await
             .map_err(|e| ServiceError::DbErr(e))?;
 
-        Ok(FileStats {
-            usage: match usage {
-                // The query can fail if no files are uploaded.
-                Some(v) => match v.try_get("", "sum") {
-                    Ok(v) => v,
-                    Err(_) => 0,
-                },
-                None => 0,
+        Ok(match usage {
+            // The query can fail if no files are uploaded.
+            Some(v) => match v.try_get("", "sum") {
+                Ok(v) => v,
+                Err(_) => 0,
             },
+            None => 0,
         })
     }
 
@@ -297,10 +1092,21 @@ impl FileService {
         }
 
         if let Some(query) = query {
-            conditions = conditions.add(files::Column::Name.like(&format!("%{}%", query)));
+            conditions = conditions
+                .add(Expr::tbl(files::Entity, files::Column::Name).like(contains_pattern(&query)));
         }
 
+        // Expired files are hidden until they are deleted.
+        conditions = conditions.add(
+            Condition::any()
+                .add(files::Column::ExpiresAt.is_null())
+                .add(files::Column::ExpiresAt.gt(Utc::now())),
+        );
+
         let page = self.get_page(page, page_size, Some(conditions)).await?;
+        let public = self
+            .public_hashes(page.items.iter().map(|f| f.hash.to_owned()).collect())
+            .await?;
 
         Ok(ServicePage {
             page: page.page,
@@ -308,14 +1114,86 @@ impl FileService {
             items: page
                 .items
                 .into_iter()
-                .map(|f| self.to_file_data(f))
+                .map(|f| {
+                    let blob_public = public.contains(&f.hash);
+                    self.to_file_data(f, blob_public)
+                })
                 .collect(),
         })
     }
 
+    /// Send `file.deleted` to the webhooks of the uploaders of deleted files.
+    async fn emit_deleted(&self, files: &[files::Model], application_id: Option<&str>) {
+        for file in files {
+            self.webhook_service
+                .emit(
+                    WebhookEvent::FileDeleted,
+                    &file.uploader,
+                    application_id,
+                    // The objects may be gone, links to them are always signed.
+                    &self.to_file_data(file.clone(), false),
+                )
+                .await;
+        }
+    }
+
+    /// Hashes of blobs whose objects can be read without a signed link.
+    async fn public_hashes(&self, hashes: Vec<String>) -> ServiceResult<HashSet<String>> {
+        if hashes.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        Ok(blobs::Entity::find()
+            .filter(blobs::Column::Hash.is_in(hashes))
+            .filter(blobs::Column::Public.eq(true))
+            .all(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?
+            .into_iter()
+            .map(|blob| blob.hash)
+            .collect())
+    }
+
+    /// Convert a model to [`FileData`], looking up whether its blob is public.
+    async fn file_data(&self, model: files::Model) -> ServiceResult<FileData> {
+        let blob_public = self
+            .public_hashes(vec![model.hash.to_owned()])
+            .await?
+            .contains(&model.hash);
+
+        Ok(self.to_file_data(model, blob_public))
+    }
+
     /// Convert a model to [`FileData`].
-    fn to_file_data(&self, model: files::Model) -> FileData {
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - File to convert.
+    /// * `blob_public` - Whether the objects of the file can be read without a signed link.
+    fn to_file_data(&self, model: files::Model, blob_public: bool) -> FileData {
         let mut file_data = FileData::from(model.clone());
+
+        // Private files and files sharing a private blob are only accessible through signed links.
+        if model.visibility == Visibility::Private || !blob_public {
+            let expires = Utc::now().timestamp() + PRIVATE_LINK_EXPIRY.as_secs() as i64;
+
+            file_data.url = Some(self.object_link(&model.name, expires));
+
+            if model.has_thumbnail {
+                file_data.thumbnail_url =
+                    Some(self.object_link(&format!("thumb/{}", model.name), expires));
+            }
+
+            for variant in model.variants.split_whitespace() {
+                file_data.variants.insert(
+                    variant.to_owned(),
+                    self.object_link(&variant_key(&model.name, variant), expires),
+                );
+            }
+
+            return file_data;
+        }
+
         let root_path = PathBuf::from(&self.storage_url);
 
         file_data.set_url(root_path.clone());
@@ -324,21 +1202,152 @@ impl FileService {
             file_data.set_thumbnail_url(root_path.clone());
         }
 
+        for variant in model.variants.split_whitespace() {
+            file_data.set_variant_url(
+                root_path.clone(),
+                variant,
+                &variant_key(&model.name, variant),
+            );
+        }
+
         file_data
     }
 }
 
-const IMAGE_EXTS: &'static [&'static str] =
-    &["PNG", "JPG", "JPEG", "GIF", "WEBP", "JFIF", "PJPEG", "PJP"];
+/// Validate an expiration in seconds.
+///
+/// Returns [`i64`] the expiration which can be stored.
+pub fn validate_expiration(expiration: u64) -> ServiceResult<i64> {
+    if expiration == 0 || expiration > MAX_EXPIRATION {
+        return Err(ServiceError::InvalidData(format!(
+            "Expiration must be between 1 and {} seconds",
+            MAX_EXPIRATION
+        )));
+    }
+
+    Ok(expiration as i64)
+}
+
+/// All objects which may be stored for a blob.
+/// Not every blob has a thumbnail, deleting a missing object is not an error.
+fn blob_objects(name: &str, variants: &str) -> Vec<String> {
+    let mut objects = vec![name.to_owned(), format!("thumb/{}", name)];
+    objects.extend(variant_keys(name, variants));
+    objects
+}
+
+/// Read the first bytes of a stored object, which are enough to detect its type.
+async fn read_header(
+    storage: &dyn StorageProvider,
+    name: &str,
+    size: i64,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let range = ObjectRange {
+        start: 0,
+        length: (size.max(0) as u64).min(HEADER_SIZE),
+    };
+
+    if range.length == 0 {
+        return Ok(Vec::new());
+    }
+
+    storage
+        .get_object_stream(name, Some(range))
+        .await?
+        .try_fold(Vec::new(), |mut header, chunk| async move {
+            header.extend_from_slice(&chunk);
+            Ok(header)
+        })
+        .await
+}
+
+/// Create the thumbnail object of a stored object if a thumbnailer supports its type.
+/// The object is streamed into a temporary file for the thumbnailer.
+///
+/// Returns [`bool`] whether the thumbnail was created.
+async fn create_thumbnail(
+    storage: &dyn StorageProvider,
+    thumbnailers: &ThumbnailerRegistry,
+    name: &str,
+    size: i64,
+) -> bool {
+    // The type is detected from the start of the object, unsupported objects are never read entirely.
+    let header = match read_header(storage, name, size).await {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+
+    let thumbnailer = match thumbnailers.find(&header) {
+        Some(v) => v,
+        None => return false,
+    };
+
+    let input = match storage.get_object_stream(name, None).await {
+        Ok(stream) => TempInput::from_stream(stream).await,
+        Err(err) => Err(err),
+    };
+
+    let input = match input {
+        Ok(v) => v,
+        Err(err) => {
+            log::warn!("Unable to read {} for its thumbnail: {}", name, err);
+            return false;
+        }
+    };
+
+    match thumbnailer.thumbnail(&input.path).await {
+        Ok(image) => storage
+            .put_object(&format!("thumb/{}", name), &image)
+            .await
+            .is_ok(),
+        Err(err) => {
+            log::warn!(
+                "Unable to create thumbnail of {} ({}): {}",
+                name,
+                thumbnailer.name(),
+                err
+            );
+            false
+        }
+    }
+}
+
+/// Mark a blob and its files as having a thumbnail once the thumbnail object is stored.
+/// The thumbnail gets the current access of the blob, which may have changed while it was created.
+async fn store_thumbnail(
+    database: &DatabaseConnection,
+    storage: &dyn StorageProvider,
+    blob: &blobs::Model,
+    thumbnail: &str,
+) -> Result<(), anyhow::Error> {
+    let updated = blobs::Entity::update_many()
+        .col_expr(blobs::Column::HasThumbnail, Expr::value(true))
+        .filter(blobs::Column::Id.eq(blob.id.to_owned()))
+        .exec(database)
+        .await?;
+
+    // The blob was removed while the thumbnail was created.
+    if updated.rows_affected == 0 {
+        return Err(anyhow::anyhow!("Blob no longer exists"));
+    }
+
+    let public = match blobs::Entity::find_by_id(blob.id.to_owned())
+        .one(database)
+        .await?
+    {
+        Some(v) => v.public,
+        None => return Err(anyhow::anyhow!("Blob no longer exists")),
+    };
 
-fn create_thumbnail_image(bytes: &Vec<u8>) -> Result<Vec<u8>, ImageError> {
-    let mut buf = Vec::new();
+    storage
+        .set_object_access(vec![thumbnail.to_owned()], public)
+        .await?;
 
-    Reader::new(Cursor::new(bytes))
-        .with_guessed_format()?
-        .decode()?
-        .thumbnail(500, 500)
-        .write_to(&mut Cursor::new(&mut buf), image::ImageOutputFormat::Png)?;
+    files::Entity::update_many()
+        .col_expr(files::Column::HasThumbnail, Expr::value(true))
+        .filter(files::Column::Hash.eq(blob.hash.to_owned()))
+        .exec(database)
+        .await?;
 
-    Ok(buf)
+    Ok(())
 }
@@ -1,25 +1,161 @@
-use std::path::PathBuf;
+use std::{
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use super::StorageProvider;
+use super::{ObjectRange, ObjectStream, StorageProvider};
 
 use async_trait::async_trait;
-use tokio::io::AsyncWriteExt;
+use bytes::Bytes;
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+
+/// Size of the chunks read when streaming an object.
+const READ_CHUNK_SIZE: u64 = 64 * 1024;
 
 pub struct LocalProvider {
     path: PathBuf,
+    sharded: bool,
 }
 
 impl LocalProvider {
-    pub fn new(path: PathBuf) -> Self {
-        LocalProvider { path: path }
+    pub fn new(path: PathBuf, sharded: bool) -> Self {
+        LocalProvider { path, sharded }
+    }
+
+    /// Path an object should be written to.
+    /// Shard directories are created if they don't exist yet.
+    async fn write_path(&self, key: &str) -> Result<PathBuf, anyhow::Error> {
+        if !self.sharded {
+            return Ok(self.path.join(key));
+        }
+
+        let path = shard_path(&self.path, key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        Ok(path)
+    }
+}
+
+/// Path of an object in the sharded layout.
+///
+/// Objects are placed in two levels of directories named after the start of the SHA256 hash of their name.
+/// Any directory in the key such as `thumb/` is kept in front of the shard directories.
+///
+/// `abc.png` is stored at `3f/a1/abc.png` and `thumb/abc.png` at `thumb/3f/a1/abc.png`.
+pub fn shard_path(root: &Path, key: &str) -> PathBuf {
+    let (directory, name) = match key.rsplit_once('/') {
+        Some((directory, name)) => (Some(directory), name),
+        None => (None, key),
+    };
+
+    let hash = format!("{:x}", Sha256::digest(name.as_bytes()));
+
+    let mut path = root.to_path_buf();
+    if let Some(directory) = directory {
+        path.push(directory);
+    }
+    path.push(&hash[0..2]);
+    path.push(&hash[2..4]);
+    path.push(name);
+
+    path
+}
+
+/// Path of an existing object.
+///
+/// Objects which haven't been moved to the sharded layout yet are still read from the flat layout.
+pub async fn object_path(root: &Path, key: &str, sharded: bool) -> PathBuf {
+    if sharded {
+        let path = shard_path(root, key);
+        if tokio::fs::metadata(&path).await.is_ok() {
+            return path;
+        }
+    }
+
+    root.join(key)
+}
+
+/// Move all objects stored in the flat layout into the sharded layout.
+/// Returns the amount of objects moved.
+pub async fn shard_objects(root: &Path) -> Result<usize, anyhow::Error> {
+    let mut moved = 0;
+
+    // Objects are stored in the root and thumbnails in the thumb directory.
+    for (directory, prefix) in [(root.to_path_buf(), ""), (root.join("thumb"), "thumb/")] {
+        if tokio::fs::metadata(&directory).await.is_err() {
+            continue;
+        }
+
+        let mut entries = tokio::fs::read_dir(&directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            // Shard directories and the thumbnail directory are skipped.
+            if !entry.file_type().await?.is_file() {
+                continue;
+            }
+
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+
+            let target = shard_path(root, &format!("{}{}", prefix, name));
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+
+            tokio::fs::rename(entry.path(), target).await?;
+            moved += 1;
+        }
     }
+
+    Ok(moved)
+}
+
+/// Stream a file from its current position.
+///
+/// # Arguments
+///
+/// * `file` - File to read from.
+/// * `length` - Amount of bytes to read.
+pub fn file_stream(file: File, length: u64) -> ObjectStream {
+    Box::pin(futures::stream::try_unfold((file, length), read_chunk))
+}
+
+/// Read the next chunk of a file being streamed.
+///
+/// # Arguments
+///
+/// * `state` - File being read and the amount of bytes remaining.
+async fn read_chunk(
+    (mut file, remaining): (File, u64),
+) -> Result<Option<(Bytes, (File, u64))>, anyhow::Error> {
+    if remaining == 0 {
+        return Ok(None);
+    }
+
+    let mut buffer = vec![0; remaining.min(READ_CHUNK_SIZE) as usize];
+    let read = file.read(&mut buffer).await?;
+
+    if read == 0 {
+        return Err(anyhow::anyhow!("Unexpected end of file"));
+    }
+
+    buffer.truncate(read);
+    Ok(Some((Bytes::from(buffer), (file, remaining - read as u64))))
 }
 
 #[async_trait]
 impl StorageProvider for LocalProvider {
     async fn put_object(&self, name: &str, data: &Vec<u8>) -> Result<(), anyhow::Error> {
-        let mut path = self.path.clone();
-        path.push(name);
+        let path = self.write_path(name).await?;
 
         let mut file = tokio::fs::OpenOptions::new()
             .write(true)
@@ -32,10 +168,91 @@ impl StorageProvider for LocalProvider {
         Ok(())
     }
 
+    async fn put_object_stream(
+        &self,
+        name: &str,
+        mut stream: ObjectStream,
+    ) -> Result<(), anyhow::Error> {
+        let path = self.write_path(name).await?;
+
+        let result: Result<(), anyhow::Error> = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)
+                .await?;
+
+            while let Some(chunk) = stream.next().await {
+                file.write_all(&chunk?).await?;
+            }
+
+            file.flush().await?;
+            Ok(())
+        }
+        .await;
+
+        // Don't leave partially written files behind.
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&path).await;
+        }
+
+        result
+    }
+
+    async fn set_object_access(
+        &self,
+        _keys: Vec<String>,
+        _public: bool,
+    ) -> Result<(), anyhow::Error> {
+        // Access is checked when local files are served.
+        Ok(())
+    }
+
+    fn presigned_url(&self, _key: &str, _expires_in: Duration) -> Option<String> {
+        None
+    }
+
+    async fn list_objects(&self) -> Result<Vec<String>, anyhow::Error> {
+        let mut keys = vec![];
+        let mut directories = vec![self.path.clone()];
+
+        // Objects can be in the flat or sharded layout so every directory is walked.
+        while let Some(directory) = directories.pop() {
+            let mut entries = tokio::fs::read_dir(&directory).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let file_type = entry.file_type().await?;
+                if file_type.is_dir() {
+                    directories.push(entry.path());
+                    continue;
+                }
+
+                let name = match entry.file_name().into_string() {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                };
+
+                // Shard directories aren't part of the key.
+                let thumbnail = entry
+                    .path()
+                    .strip_prefix(&self.path)
+                    .map(|path| path.starts_with("thumb"))
+                    .unwrap_or(false);
+
+                keys.push(if thumbnail {
+                    format!("thumb/{}", name)
+                } else {
+                    name
+                });
+            }
+        }
+
+        Ok(keys)
+    }
+
     async fn delete_objects(&self, keys: Vec<String>) -> Result<(), anyhow::Error> {
         for key in keys {
-            let mut path = self.path.clone();
-            path.push(key);
+            let path = object_path(&self.path, &key, self.sharded).await;
 
             let _ = tokio::fs::remove_file(path).await;
         }
@@ -44,9 +261,24 @@ impl StorageProvider for LocalProvider {
     }
 
     async fn get_object(&self, path: &str) -> Result<Vec<u8>, anyhow::Error> {
-        let mut path_buf = self.path.clone();
-        path_buf.push(path);
+        Ok(tokio::fs::read(object_path(&self.path, path, self.sharded).await).await?)
+    }
+
+    async fn get_object_stream(
+        &self,
+        path: &str,
+        range: Option<ObjectRange>,
+    ) -> Result<ObjectStream, anyhow::Error> {
+        let mut file = File::open(object_path(&self.path, path, self.sharded).await).await?;
+
+        let length = match range {
+            Some(range) => {
+                file.seek(SeekFrom::Start(range.start)).await?;
+                range.length
+            }
+            None => file.metadata().await?.len(),
+        };
 
-        Ok(tokio::fs::read(path_buf).await?)
+        Ok(file_stream(file, length))
     }
 }
@@ -1,12 +1,29 @@
 pub mod local;
 pub mod s3;
+pub mod webdav;
+
+use std::{pin::Pin, time::Duration};
 
 use async_trait::async_trait;
+use bytes::Bytes;
+use futures::Stream;
 use tokio::fs;
 
 use crate::config::StorageConfig;
 
-use self::{local::LocalProvider, s3::S3Provider};
+use self::{local::LocalProvider, s3::S3Provider, webdav::WebDavProvider};
+
+/// Stream of object data.
+pub type ObjectStream = Pin<Box<dyn Stream<Item = Result<Bytes, anyhow::Error>> + Send>>;
+
+/// Byte range within an object.
+#[derive(Clone, Copy, Debug)]
+pub struct ObjectRange {
+    /// Offset of the first byte.
+    pub start: u64,
+    /// Amount of bytes in the range.
+    pub length: u64,
+}
 
 #[async_trait]
 /// Base storage provider type
@@ -14,11 +31,40 @@ pub trait StorageProvider: Sync + Send {
     /// Put the object.
     async fn put_object(&self, name: &str, data: &Vec<u8>) -> Result<(), anyhow::Error>;
 
+    /// Put the object from a stream.
+    /// The object is written while the stream is consumed and is never fully held in memory.
+    /// If the stream yields an error the object must not be left in storage.
+    async fn put_object_stream(
+        &self,
+        name: &str,
+        stream: ObjectStream,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Set whether objects can be read directly from storage without a signed link.
+    /// Objects are private when they are created.
+    async fn set_object_access(&self, keys: Vec<String>, public: bool)
+        -> Result<(), anyhow::Error>;
+
+    /// Get a presigned URL which allows reading the object until it expires.
+    /// Returns [`None`] if the provider can't sign URLs itself.
+    fn presigned_url(&self, key: &str, expires_in: Duration) -> Option<String>;
+
+    /// List the keys of every stored object, including thumbnails.
+    async fn list_objects(&self) -> Result<Vec<String>, anyhow::Error>;
+
     /// Delete multiple objects.
     async fn delete_objects(&self, keys: Vec<String>) -> Result<(), anyhow::Error>;
 
     /// Get the buffer of the object.
     async fn get_object(&self, path: &str) -> Result<Vec<u8>, anyhow::Error>;
+
+    /// Get a stream of the object.
+    /// Only the bytes within `range` are streamed if it is provided.
+    async fn get_object_stream(
+        &self,
+        path: &str,
+        range: Option<ObjectRange>,
+    ) -> Result<ObjectStream, anyhow::Error>;
 }
 
 /// Create a new storage based on [`StorageConfig`].
@@ -42,7 +88,7 @@ pub async fn new_storage(config: StorageConfig) -> Box<dyn StorageProvider> {
                     .expect("Unable to create thumbnail directory");
             }
 
-            Box::new(LocalProvider::new(v.path.clone()))
+            Box::new(LocalProvider::new(v.path.clone(), v.sharded))
         }
         StorageConfig::S3(v) => Box::new(S3Provider::new(
             &v.bucket,
@@ -50,5 +96,16 @@ pub async fn new_storage(config: StorageConfig) -> Box<dyn StorageProvider> {
             &v.secret_key,
             v.region.clone(),
         )),
+        StorageConfig::WebDav(v) => {
+            let provider = WebDavProvider::new(&v.url, v.username, v.password);
+
+            // Thumbnail collection
+            provider
+                .create_collection("thumb")
+                .await
+                .expect("Unable to create thumbnail collection");
+
+            Box::new(provider)
+        }
     }
 }
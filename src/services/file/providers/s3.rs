@@ -1,18 +1,34 @@
-use super::StorageProvider;
+use std::time::Duration;
+
+use super::{ObjectRange, ObjectStream, StorageProvider};
 use async_trait::async_trait;
 use futures::TryStreamExt;
 use infer;
 
-use rusoto_core::{credential, ByteStream, HttpClient, Region};
+use rusoto_core::{
+    credential::{self, AwsCredentials},
+    ByteStream, HttpClient, Region,
+};
 
 use rusoto_s3::{
-    Delete, DeleteObjectsRequest, GetObjectRequest, ObjectIdentifier, PutObjectRequest, S3Client,
-    S3,
+    util::{PreSignedRequest, PreSignedRequestOption},
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
+    CompletedPart, CreateMultipartUploadRequest, Delete, DeleteObjectsRequest, GetObjectRequest,
+    ListObjectsV2Request, ObjectIdentifier, PutObjectAclRequest, PutObjectRequest, S3Client,
+    UploadPartRequest, S3,
 };
 
+/// Size of each part in a multipart upload.
+/// Streams smaller than this are uploaded with a single request.
+/// S3 requires every part except the last to be at least 5MB.
+const MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
+
 pub struct S3Provider {
     bucket: String,
     client: S3Client,
+    region: Region,
+    // Used to sign presigned URLs.
+    credentials: AwsCredentials,
 }
 
 impl S3Provider {
@@ -23,11 +39,72 @@ impl S3Provider {
             client: S3Client::new_with(
                 HttpClient::new().expect("S3 dispatcher could not be created"),
                 credential_provider,
-                s3_region,
+                s3_region.clone(),
             ),
             bucket: bucket.into(),
+            region: s3_region,
+            credentials: AwsCredentials::new(access_key, secret_key, None, None),
         }
     }
+
+    /// Upload the rest of a stream as parts of a multipart upload.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Object key.
+    /// * `upload_id` - ID of the multipart upload.
+    /// * `buffer` - Data which was already read from the stream.
+    /// * `stream` - Remaining data.
+    async fn upload_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        mut buffer: Vec<u8>,
+        mut stream: ObjectStream,
+    ) -> Result<Vec<CompletedPart>, anyhow::Error> {
+        let mut parts = vec![];
+        let mut finished = false;
+
+        while !finished {
+            // Fill a full part unless the stream ends first.
+            while buffer.len() < MULTIPART_PART_SIZE {
+                match stream.try_next().await? {
+                    Some(chunk) => buffer.extend_from_slice(&chunk),
+                    None => {
+                        finished = true;
+                        break;
+                    }
+                }
+            }
+
+            if buffer.is_empty() {
+                break;
+            }
+
+            let part_number = parts.len() as i64 + 1;
+            let part = std::mem::take(&mut buffer);
+
+            let output = self
+                .client
+                .upload_part(UploadPartRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_string(),
+                    upload_id: upload_id.to_string(),
+                    part_number,
+                    content_length: Some(part.len() as i64),
+                    body: Some(ByteStream::from(part)),
+                    ..Default::default()
+                })
+                .await?;
+
+            parts.push(CompletedPart {
+                e_tag: output.e_tag,
+                part_number: Some(part_number),
+            });
+        }
+
+        Ok(parts)
+    }
 }
 
 #[async_trait]
@@ -44,7 +121,6 @@ impl StorageProvider for S3Provider {
                 bucket: self.bucket.clone(),
                 body: Some(ByteStream::from(data.clone())),
                 key: name.strip_prefix("./").unwrap_or(name).to_string(),
-                acl: Some("public-read".into()),
                 content_type: content_type,
                 ..Default::default()
             })
@@ -53,6 +129,141 @@ impl StorageProvider for S3Provider {
         Ok(())
     }
 
+    async fn put_object_stream(
+        &self,
+        name: &str,
+        mut stream: ObjectStream,
+    ) -> Result<(), anyhow::Error> {
+        let key = name.strip_prefix("./").unwrap_or(name).to_string();
+        let mut buffer = Vec::new();
+
+        // Read the first part, small objects don't need a multipart upload.
+        while buffer.len() < MULTIPART_PART_SIZE {
+            match stream.try_next().await? {
+                Some(chunk) => buffer.extend_from_slice(&chunk),
+                None => return self.put_object(name, &buffer).await,
+            }
+        }
+
+        // Attempt to detect content type
+        let content_type = match infer::get(&buffer) {
+            Some(kind) => Some(kind.mime_type().to_string()),
+            None => None,
+        };
+
+        let upload_id = self
+            .client
+            .create_multipart_upload(CreateMultipartUploadRequest {
+                bucket: self.bucket.clone(),
+                key: key.clone(),
+                content_type: content_type,
+                ..Default::default()
+            })
+            .await?
+            .upload_id
+            .ok_or_else(|| anyhow::anyhow!("No upload ID was returned for {}", key))?;
+
+        match self.upload_parts(&key, &upload_id, buffer, stream).await {
+            Ok(parts) => {
+                self.client
+                    .complete_multipart_upload(CompleteMultipartUploadRequest {
+                        bucket: self.bucket.clone(),
+                        key: key,
+                        upload_id: upload_id,
+                        multipart_upload: Some(CompletedMultipartUpload { parts: Some(parts) }),
+                        ..Default::default()
+                    })
+                    .await?;
+
+                Ok(())
+            }
+            Err(err) => {
+                // Uploaded parts are stored (and billed) until the upload is aborted.
+                let _ = self
+                    .client
+                    .abort_multipart_upload(AbortMultipartUploadRequest {
+                        bucket: self.bucket.clone(),
+                        key: key,
+                        upload_id: upload_id,
+                        ..Default::default()
+                    })
+                    .await;
+
+                Err(err)
+            }
+        }
+    }
+
+    async fn set_object_access(
+        &self,
+        keys: Vec<String>,
+        public: bool,
+    ) -> Result<(), anyhow::Error> {
+        let acl = if public { "public-read" } else { "private" };
+
+        for key in keys {
+            self.client
+                .put_object_acl(PutObjectAclRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.strip_prefix("./").unwrap_or(&key).to_string(),
+                    acl: Some(acl.into()),
+                    ..Default::default()
+                })
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    fn presigned_url(&self, key: &str, expires_in: Duration) -> Option<String> {
+        Some(
+            GetObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.strip_prefix("./").unwrap_or(key).to_string(),
+                ..Default::default()
+            }
+            .get_presigned_url(
+                &self.region,
+                &self.credentials,
+                &PreSignedRequestOption { expires_in },
+            ),
+        )
+    }
+
+    async fn list_objects(&self) -> Result<Vec<String>, anyhow::Error> {
+        let mut keys = vec![];
+        let mut continuation_token = None;
+
+        // Keys are listed in pages of up to 1000.
+        loop {
+            let output = self
+                .client
+                .list_objects_v2(ListObjectsV2Request {
+                    bucket: self.bucket.clone(),
+                    continuation_token,
+                    ..Default::default()
+                })
+                .await?;
+
+            keys.extend(
+                output
+                    .contents
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|object| object.key),
+            );
+
+            match output.next_continuation_token {
+                Some(token) if output.is_truncated == Some(true) => {
+                    continuation_token = Some(token)
+                }
+                _ => break,
+            }
+        }
+
+        Ok(keys)
+    }
+
     async fn delete_objects(&self, keys: Vec<String>) -> Result<(), anyhow::Error> {
         self.client
             .delete_objects(DeleteObjectsRequest {
@@ -90,4 +301,28 @@ impl StorageProvider for S3Provider {
             None => Err(anyhow::anyhow!(format!("No file stream found on {}", key))),
         }
     }
+
+    async fn get_object_stream(
+        &self,
+        key: &str,
+        range: Option<ObjectRange>,
+    ) -> Result<ObjectStream, anyhow::Error> {
+        match self
+            .client
+            .get_object(GetObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.strip_prefix("./").unwrap_or(key).to_string(),
+                range: range.map(|range| {
+                    format!("bytes={}-{}", range.start, range.start + range.length - 1)
+                }),
+                ..Default::default()
+            })
+            .await?
+            .body
+            .take()
+        {
+            Some(stream) => Ok(Box::pin(stream.map_err(|e| e.into()))),
+            None => Err(anyhow::anyhow!(format!("No file stream found on {}", key))),
+        }
+    }
 }
@@ -0,0 +1,230 @@
+use std::time::Duration;
+
+use super::{ObjectRange, ObjectStream, StorageProvider};
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use regex::Regex;
+use reqwest::{header, Body, Client, Method, RequestBuilder, StatusCode};
+
+lazy_static! {
+    /// Matches href elements in a PROPFIND response with any namespace prefix.
+    static ref HREF_REGEX: Regex =
+        Regex::new(r"<(?:[A-Za-z0-9]+:)?href>([^<]*)</(?:[A-Za-z0-9]+:)?href>").unwrap();
+}
+
+/// Decode a percent encoded path segment.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+pub struct WebDavProvider {
+    client: Client,
+    /// Base URL of the collection objects are stored in, always ends with a slash.
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl WebDavProvider {
+    pub fn new(url: &str, username: Option<String>, password: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            url: format!("{}/", url.trim_end_matches('/')),
+            username,
+            password,
+        }
+    }
+
+    /// Build an authenticated request for a path relative to the base URL.
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.client.request(method, format!("{}{}", self.url, path));
+
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+
+    /// List the names of objects in a collection.
+    async fn list_collection(&self, path: &str) -> Result<Vec<String>, anyhow::Error> {
+        let body = self
+            .request(Method::from_bytes(b"PROPFIND")?, path)
+            .header("Depth", "1")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        // Collections end with a slash, this includes the listed collection itself.
+        Ok(HREF_REGEX
+            .captures_iter(&body)
+            .map(|captures| captures[1].trim().to_owned())
+            .filter(|href| !href.ends_with('/'))
+            .filter_map(|href| href.rsplit('/').next().map(percent_decode))
+            .collect())
+    }
+
+    /// Create a collection if it doesn't exist yet.
+    pub async fn create_collection(&self, path: &str) -> Result<(), anyhow::Error> {
+        let response = self
+            .request(Method::from_bytes(b"MKCOL")?, &format!("{}/", path))
+            .send()
+            .await?;
+
+        // Servers respond with 405 Method Not Allowed if the collection already exists.
+        match response.status() {
+            status if status.is_success() || status == StatusCode::METHOD_NOT_ALLOWED => Ok(()),
+            status => Err(anyhow::anyhow!(
+                "Unable to create WebDAV collection {}: {}",
+                path,
+                status
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl StorageProvider for WebDavProvider {
+    async fn put_object(&self, name: &str, data: &Vec<u8>) -> Result<(), anyhow::Error> {
+        self.request(Method::PUT, name)
+            .body(data.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    async fn put_object_stream(
+        &self,
+        name: &str,
+        mut stream: ObjectStream,
+    ) -> Result<(), anyhow::Error> {
+        // Request bodies have to be Sync, so the stream is forwarded through a channel.
+        let (mut sender, receiver) = futures::channel::mpsc::channel(4);
+        let forward = async move {
+            while let Some(chunk) = stream.next().await {
+                let failed = chunk.is_err();
+                if sender.send(chunk).await.is_err() || failed {
+                    break;
+                }
+            }
+        };
+
+        let upload = self
+            .request(Method::PUT, name)
+            .body(Body::wrap_stream(receiver))
+            .send();
+
+        let (result, _) = futures::join!(upload, forward);
+
+        if let Err(err) = result.and_then(|response| response.error_for_status()) {
+            // Don't leave partially written objects behind.
+            let _ = self.request(Method::DELETE, name).send().await;
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    async fn set_object_access(
+        &self,
+        _keys: Vec<String>,
+        _public: bool,
+    ) -> Result<(), anyhow::Error> {
+        // WebDAV has no standard way to control access to single objects.
+        Ok(())
+    }
+
+    fn presigned_url(&self, _key: &str, _expires_in: Duration) -> Option<String> {
+        None
+    }
+
+    async fn list_objects(&self) -> Result<Vec<String>, anyhow::Error> {
+        let mut keys = self.list_collection("").await?;
+        keys.extend(
+            self.list_collection("thumb/")
+                .await?
+                .into_iter()
+                .map(|name| format!("thumb/{}", name)),
+        );
+
+        Ok(keys)
+    }
+
+    async fn delete_objects(&self, keys: Vec<String>) -> Result<(), anyhow::Error> {
+        for key in keys {
+            let response = self.request(Method::DELETE, &key).send().await?;
+
+            // Objects which are already gone don't need to be deleted.
+            if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+                return Err(anyhow::anyhow!(
+                    "Unable to delete {}: {}",
+                    key,
+                    response.status()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_object(&self, path: &str) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(self
+            .request(Method::GET, path)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec())
+    }
+
+    async fn get_object_stream(
+        &self,
+        path: &str,
+        range: Option<ObjectRange>,
+    ) -> Result<ObjectStream, anyhow::Error> {
+        let mut request = self.request(Method::GET, path);
+        if let Some(range) = range {
+            request = request.header(
+                header::RANGE,
+                format!(
+                    "bytes={}-{}",
+                    range.start,
+                    (range.start + range.length).saturating_sub(1)
+                ),
+            );
+        }
+
+        let response = request.send().await?.error_for_status()?;
+
+        // A server which ignores the range would send the whole object.
+        if range.is_some() && response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(anyhow::anyhow!(
+                "WebDAV server does not support range requests"
+            ));
+        }
+
+        Ok(Box::pin(response.bytes_stream().map_err(|e| e.into())))
+    }
+}
@@ -0,0 +1,65 @@
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+
+use futures::StreamExt;
+
+use super::providers::ObjectStream;
+
+/// Hash and size of an upload.
+/// This is computed while the upload is being streamed to storage.
+pub struct UploadDigest {
+    hasher: Sha256,
+    size: usize,
+    limit: usize,
+    exceeded: bool,
+}
+
+impl UploadDigest {
+    /// Wrap a stream so it is hashed and measured while it is consumed.
+    /// The wrapped stream yields an error as soon as more than `limit` bytes pass through it.
+    ///
+    /// Returns the wrapped stream and the shared digest, which is complete once the stream ends.
+    pub fn wrap(stream: ObjectStream, limit: usize) -> (ObjectStream, Arc<Mutex<UploadDigest>>) {
+        let digest = Arc::new(Mutex::new(UploadDigest {
+            hasher: Sha256::new(),
+            size: 0,
+            limit,
+            exceeded: false,
+        }));
+
+        let state = digest.clone();
+        let stream = stream.map(move |chunk| {
+            let chunk = chunk?;
+            let mut state = state.lock().unwrap();
+
+            state.size += chunk.len();
+            if state.size > state.limit {
+                state.exceeded = true;
+                return Err(anyhow::anyhow!(
+                    "Upload exceeded the limit of {} bytes",
+                    state.limit
+                ));
+            }
+
+            state.hasher.update(&chunk);
+            Ok(chunk)
+        });
+
+        (Box::pin(stream), digest)
+    }
+
+    /// Did the stream exceed the size limit.
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+
+    /// Amount of bytes consumed.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Hex encoded SHA-256 hash of the consumed bytes.
+    pub fn hash(&self) -> String {
+        format!("{:x}", self.hasher.clone().finalize())
+    }
+}
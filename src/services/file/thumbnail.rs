@@ -0,0 +1,275 @@
+//! Thumbnails are created by the first [`Thumbnailer`] which supports the MIME type detected from the file contents.
+//!
+//! Images are always supported, other formats depend on which tools are installed.
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use image::{
+    io::{Limits, Reader},
+    ImageOutputFormat,
+};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use super::ObjectStream;
+
+/// Thumbnails fit within a square of this size.
+const THUMBNAIL_SIZE: u32 = 500;
+
+/// Largest width or height of images which are decoded, larger images get no thumbnail.
+const MAX_IMAGE_DIMENSION: u32 = 16384;
+
+/// Longest time an external tool can take to create a thumbnail.
+const TOOL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Creates PNG thumbnails for some kinds of files.
+#[async_trait]
+pub trait Thumbnailer: Send + Sync {
+    /// Name used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Whether files of a MIME type are supported.
+    fn supports(&self, mime: &str) -> bool;
+
+    /// Create a thumbnail of a file.
+    ///
+    /// * `input` - Path of a temporary copy of the file.
+    async fn thumbnail(&self, input: &Path) -> Result<Vec<u8>, anyhow::Error>;
+}
+
+/// Thumbnailers which can be used, checked in the order they were registered.
+#[derive(Default)]
+pub struct ThumbnailerRegistry {
+    thumbnailers: Vec<Box<dyn Thumbnailer>>,
+}
+
+impl ThumbnailerRegistry {
+    /// Registry with every thumbnailer which is available on this system.
+    pub async fn detect() -> Self {
+        let mut registry = Self::default();
+        registry.register(Box::new(ImageThumbnailer));
+
+        if tool_installed("ffmpeg", "-version").await {
+            registry.register(Box::new(FfmpegThumbnailer));
+        }
+
+        if tool_installed("pdftoppm", "-v").await {
+            registry.register(Box::new(PdfThumbnailer));
+        }
+
+        log::info!(
+            "Thumbnails enabled for: {}",
+            registry
+                .thumbnailers
+                .iter()
+                .map(|thumbnailer| thumbnailer.name())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        );
+
+        registry
+    }
+
+    pub fn register(&mut self, thumbnailer: Box<dyn Thumbnailer>) {
+        self.thumbnailers.push(thumbnailer);
+    }
+
+    /// Find a thumbnailer for a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - Start of the file, this is used to detect the MIME type.
+    pub fn find(&self, header: &[u8]) -> Option<&dyn Thumbnailer> {
+        let mime = infer::get(header)?.mime_type();
+
+        self.thumbnailers
+            .iter()
+            .find(|thumbnailer| thumbnailer.supports(mime))
+            .map(|thumbnailer| thumbnailer.as_ref())
+    }
+}
+
+/// Resizes images the `image` crate can decode.
+struct ImageThumbnailer;
+
+#[async_trait]
+impl Thumbnailer for ImageThumbnailer {
+    fn name(&self) -> &'static str {
+        "images"
+    }
+
+    fn supports(&self, mime: &str) -> bool {
+        [
+            "image/png",
+            "image/jpeg",
+            "image/gif",
+            "image/webp",
+            "image/bmp",
+            "image/tiff",
+        ]
+        .contains(&mime)
+    }
+
+    async fn thumbnail(&self, input: &Path) -> Result<Vec<u8>, anyhow::Error> {
+        let input = input.to_path_buf();
+
+        // Decoding is CPU bound, so it can't run on the async workers.
+        tokio::task::spawn_blocking(move || -> Result<Vec<u8>, anyhow::Error> {
+            let mut buf = Vec::new();
+
+            let mut limits = Limits::default();
+            limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+            limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+
+            let mut reader = Reader::open(input)?.with_guessed_format()?;
+            reader.limits(limits);
+
+            reader
+                .decode()?
+                .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+                .write_to(&mut Cursor::new(&mut buf), ImageOutputFormat::Png)?;
+
+            Ok(buf)
+        })
+        .await?
+    }
+}
+
+/// Picks a keyframe of videos with ffmpeg.
+/// This also extracts the cover art of audio files.
+struct FfmpegThumbnailer;
+
+#[async_trait]
+impl Thumbnailer for FfmpegThumbnailer {
+    fn name(&self) -> &'static str {
+        "video and audio (ffmpeg)"
+    }
+
+    fn supports(&self, mime: &str) -> bool {
+        mime.starts_with("video/") || mime.starts_with("audio/")
+    }
+
+    async fn thumbnail(&self, input: &Path) -> Result<Vec<u8>, anyhow::Error> {
+        // Most containers need to be seekable, so the file can't be piped in.
+        let scale = format!(
+            "thumbnail,scale={0}:{0}:force_original_aspect_ratio=decrease",
+            THUMBNAIL_SIZE
+        );
+
+        run_tool(
+            Command::new("ffmpeg")
+                .args(["-hide_banner", "-loglevel", "error", "-skip_frame", "nokey"])
+                // Uploads could be playlists which make ffmpeg open URLs.
+                .args(["-protocol_whitelist", "file"])
+                .arg("-i")
+                .arg(input)
+                .args(["-vf", scale.as_str(), "-frames:v", "1", "-f", "image2pipe"])
+                .args(["-c:v", "png", "-"]),
+        )
+        .await
+    }
+}
+
+/// Renders the first page of PDFs with pdftoppm.
+struct PdfThumbnailer;
+
+#[async_trait]
+impl Thumbnailer for PdfThumbnailer {
+    fn name(&self) -> &'static str {
+        "PDF (pdftoppm)"
+    }
+
+    fn supports(&self, mime: &str) -> bool {
+        mime == "application/pdf"
+    }
+
+    async fn thumbnail(&self, input: &Path) -> Result<Vec<u8>, anyhow::Error> {
+        // The image is written to stdout if the output name is "-".
+        run_tool(
+            Command::new("pdftoppm")
+                .args(["-png", "-singlefile", "-f", "1", "-l", "1"])
+                .arg("-scale-to")
+                .arg(THUMBNAIL_SIZE.to_string())
+                .arg(input)
+                .arg("-"),
+        )
+        .await
+    }
+}
+
+/// File passed to a thumbnailer, it is deleted when this is dropped.
+pub struct TempInput {
+    pub path: PathBuf,
+}
+
+impl TempInput {
+    /// Write a stream to a new temporary file, so it is never held in memory.
+    pub async fn from_stream(mut stream: ObjectStream) -> Result<Self, anyhow::Error> {
+        let input = Self {
+            path: std::env::temp_dir().join(format!("backpack-thumbnail-{}", nanoid::nanoid!(16))),
+        };
+
+        let mut file = tokio::fs::File::create(&input.path).await?;
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await?;
+
+        Ok(input)
+    }
+}
+
+impl Drop for TempInput {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Run a tool and return what it wrote to stdout.
+/// The tool is killed if it takes longer than [`TOOL_TIMEOUT`].
+async fn run_tool(command: &mut Command) -> Result<Vec<u8>, anyhow::Error> {
+    let output = tokio::time::timeout(
+        TOOL_TIMEOUT,
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("Timed out"))??;
+
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Check if a tool can be run.
+///
+/// # Arguments
+///
+/// * `program` - Name of the tool, which is looked up in `PATH`.
+/// * `version_arg` - Argument which makes the tool print its version and exit.
+async fn tool_installed(program: &str, version_arg: &str) -> bool {
+    Command::new(program)
+        .arg(version_arg)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
@@ -5,11 +5,16 @@ use serde::Serialize;
 use thiserror::Error;
 
 pub mod application;
+pub mod audit_log;
 pub mod auth;
 pub mod data_service;
 pub mod file;
+pub mod rate_limit;
 pub mod registration_key;
+pub mod settings;
+pub mod upload;
 pub mod user;
+pub mod webhook;
 
 pub mod prelude {
     pub use super::{
@@ -108,6 +113,8 @@ pub enum ServiceError {
     TooLarge(String),
     #[error("{0}")]
     Unauthorized(String),
+    #[error("{0}")]
+    TooManyRequests(String),
     #[error("You are not allowed to access this {resource}")]
     Forbidden { id: String, resource: String },
 }
@@ -138,6 +145,7 @@ impl ServiceError {
             Self::Conflict(_) => StatusCode::CONFLICT,
             Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
             Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
             Self::Forbidden { id: _, resource: _ } => StatusCode::FORBIDDEN,
         }
     }
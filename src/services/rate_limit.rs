@@ -0,0 +1,237 @@
+use async_trait::async_trait;
+use moka::future::Cache;
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use super::prelude::*;
+use crate::internal::auth::ClientInfo;
+
+/// A limit of hits within a fixed window.
+/// The window starts with the first hit.
+#[derive(Clone, Copy)]
+pub struct RateLimit {
+    /// Prefix of keys counted by this limit.
+    pub name: &'static str,
+    pub max: u32,
+    pub window: Duration,
+    /// Message of the error returned when the limit is exceeded.
+    pub message: &'static str,
+}
+
+/// Limits used by services.
+pub mod limits {
+    use super::RateLimit;
+    use std::time::Duration;
+
+    /// Password logins from an IP.
+    pub const LOGIN_IP: RateLimit = RateLimit {
+        name: "login_ip",
+        max: 30,
+        window: Duration::from_secs(60 * 15),
+        message: "Too many login attempts, try again later",
+    };
+
+    /// Password logins with a username or email.
+    pub const LOGIN_IDENTIFIER: RateLimit = RateLimit {
+        name: "login_identifier",
+        max: 10,
+        window: Duration::from_secs(60 * 15),
+        message: "Too many login attempts, try again later",
+    };
+
+    /// Failed password checks of a user, the account is locked once this is reached.
+    pub const PASSWORD_FAILURES: RateLimit = RateLimit {
+        name: "password_failures",
+        max: 5,
+        window: Duration::from_secs(60 * 15),
+        message:
+            "This account is temporarily locked after too many incorrect passwords, try again later",
+    };
+
+    /// Two-factor codes entered for a user.
+    pub const TWO_FACTOR: RateLimit = RateLimit {
+        name: "two_factor",
+        max: 10,
+        window: Duration::from_secs(60 * 15),
+        message: "Too many two-factor codes were entered, try again later",
+    };
+
+    /// Verification, registration and password reset codes entered from an IP.
+    pub const CODE_IP: RateLimit = RateLimit {
+        name: "code_ip",
+        max: 20,
+        window: Duration::from_secs(60 * 15),
+        message: "Too many codes were entered, try again later",
+    };
+
+    /// Registration keys entered by a user.
+    pub const REGISTRATION_KEY: RateLimit = RateLimit {
+        name: "registration_key",
+        max: 10,
+        window: Duration::from_secs(60 * 15),
+        message: "Too many registration keys were entered, try again later",
+    };
+
+    /// Password reset emails requested for an email.
+    pub const PASSWORD_RESET_EMAIL: RateLimit = RateLimit {
+        name: "password_reset_email",
+        max: 3,
+        window: Duration::from_secs(60 * 60),
+        message: "Too many password resets were requested, try again later",
+    };
+}
+
+#[async_trait]
+/// Store of rate limit counters.
+/// Counters are kept in memory by default, a shared store is needed to enforce limits across multiple instances.
+pub trait RateLimitStore: Sync + Send {
+    /// Count a hit on the key.
+    ///
+    /// Returns the amount of hits in the current window, including this one.
+    async fn hit(&self, key: &str, window: Duration) -> Result<u32, anyhow::Error>;
+
+    /// Get the amount of hits in the current window.
+    async fn get(&self, key: &str) -> Result<u32, anyhow::Error>;
+
+    /// Reset the counter of the key.
+    async fn reset(&self, key: &str) -> Result<(), anyhow::Error>;
+}
+
+struct Counter {
+    hits: AtomicU32,
+    expires: Instant,
+}
+
+impl Counter {
+    fn new(window: Duration) -> Self {
+        Self {
+            hits: AtomicU32::new(0),
+            expires: Instant::now() + window,
+        }
+    }
+}
+
+/// Rate limit store which keeps counters in memory.
+/// Limits are only enforced per instance.
+pub struct MemoryStore {
+    counters: Cache<String, Arc<Counter>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            // Counters are only kept until their window expires, this is an upper bound for memory use.
+            counters: Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(Duration::from_secs(60 * 60 * 24))
+                .build(),
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn hit(&self, key: &str, window: Duration) -> Result<u32, anyhow::Error> {
+        let mut counter = self
+            .counters
+            .get_with(key.to_owned(), async { Arc::new(Counter::new(window)) })
+            .await;
+
+        // Start a new window.
+        if counter.expires <= Instant::now() {
+            counter = Arc::new(Counter::new(window));
+            self.counters.insert(key.to_owned(), counter.clone()).await;
+        }
+
+        Ok(counter.hits.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    async fn get(&self, key: &str) -> Result<u32, anyhow::Error> {
+        Ok(match self.counters.get(key) {
+            Some(counter) if counter.expires > Instant::now() => {
+                counter.hits.load(Ordering::Relaxed)
+            }
+            _ => 0,
+        })
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), anyhow::Error> {
+        self.counters.invalidate(key).await;
+        Ok(())
+    }
+}
+
+/// Throttles actions which can be used to guess secrets, such as passwords and codes.
+pub struct RateLimitService {
+    store: Box<dyn RateLimitStore>,
+}
+
+impl RateLimitService {
+    pub fn new(store: Box<dyn RateLimitStore>) -> Self {
+        Self { store }
+    }
+
+    /// Count a hit.
+    ///
+    /// Returns [`ServiceError::TooManyRequests`] if the limit was exceeded.
+    ///
+    /// * `limit` - Limit to count the hit towards.
+    /// * `key` - What is limited, such as an IP or user ID.
+    pub async fn hit(&self, limit: RateLimit, key: &str) -> ServiceResult<()> {
+        let hits = self
+            .store
+            .hit(&limit_key(limit, key), limit.window)
+            .await
+            .map_err(|e| ServiceError::ServerError(e))?;
+
+        if hits > limit.max {
+            return Err(ServiceError::TooManyRequests(limit.message.into()));
+        }
+
+        Ok(())
+    }
+
+    /// Count a hit from the IP of a client.
+    /// Clients without a known IP are not limited.
+    pub async fn hit_client(&self, limit: RateLimit, client: &ClientInfo) -> ServiceResult<()> {
+        match &client.ip {
+            Some(ip) => self.hit(limit, ip).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Check if a limit was reached without counting a hit.
+    ///
+    /// Returns [`ServiceError::TooManyRequests`] if no more hits are allowed.
+    pub async fn check(&self, limit: RateLimit, key: &str) -> ServiceResult<()> {
+        let hits = self
+            .store
+            .get(&limit_key(limit, key))
+            .await
+            .map_err(|e| ServiceError::ServerError(e))?;
+
+        if hits >= limit.max {
+            return Err(ServiceError::TooManyRequests(limit.message.into()));
+        }
+
+        Ok(())
+    }
+
+    /// Reset the hits of a key, this is used after a successful attempt.
+    pub async fn reset(&self, limit: RateLimit, key: &str) -> ServiceResult<()> {
+        self.store
+            .reset(&limit_key(limit, key))
+            .await
+            .map_err(|e| ServiceError::ServerError(e))
+    }
+}
+
+/// Keys are case insensitive so usernames and emails can't be varied to avoid limits.
+fn limit_key(limit: RateLimit, key: &str) -> String {
+    format!("{}:{}", limit.name, key.to_lowercase())
+}
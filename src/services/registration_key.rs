@@ -4,18 +4,25 @@ use sea_orm::{prelude::*, Condition, IntoActiveModel};
 use std::sync::Arc;
 use uuid::Uuid;
 
-use super::prelude::*;
-use crate::database::entity::registration_keys;
+use super::{
+    audit_log::{AuditLogService, AuditTarget},
+    prelude::*,
+};
+use crate::{database::entity::registration_keys, internal::auth::ClientInfo, models::AuditAction};
 
 pub struct RegistrationKeyService {
     database: Arc<DatabaseConnection>,
+    audit_log_service: Arc<AuditLogService>,
 }
 
 data_service!(RegistrationKeyService, registration_keys);
 
 impl RegistrationKeyService {
-    pub fn new(database: Arc<DatabaseConnection>) -> Self {
-        Self { database }
+    pub fn new(database: Arc<DatabaseConnection>, audit_log_service: Arc<AuditLogService>) -> Self {
+        Self {
+            database,
+            audit_log_service,
+        }
     }
 
     /// Get a registration key by UUID.
@@ -33,13 +40,15 @@ impl RegistrationKeyService {
     /// * `issuer` - User who issued the registration key.
     /// * `uses_left` - Amount of times the key should be used.
     /// * `expiration` - Expiration from now in milliseconds.
+    /// * `client` - Client which issued the key.
     pub async fn create_registration_key(
         &self,
         issuer: &str,
         uses_left: Option<i32>,
         expiration: Option<i64>,
+        client: &ClientInfo,
     ) -> ServiceResult<registration_keys::Model> {
-        registration_keys::ActiveModel {
+        let key = registration_keys::ActiveModel {
             issuer: Set(issuer.into()),
             uses_left: Set(uses_left),
             expiry_date: Set(match expiration {
@@ -50,12 +59,43 @@ impl RegistrationKeyService {
         }
         .insert(self.database.as_ref())
         .await
-        .map_err(|e| ServiceError::DbErr(e))
+        .map_err(|e| ServiceError::DbErr(e))?;
+
+        self.audit_log_service
+            .record(
+                AuditAction::RegistrationKeyCreate,
+                Some(issuer),
+                Some(AuditTarget::RegistrationKey(&key.id)),
+                None,
+                client,
+            )
+            .await;
+
+        Ok(key)
     }
 
     /// Use a key once by its code.
-    pub async fn use_key(&self, code: &str) -> ServiceResult<()> {
+    ///
+    /// * `user_id` - User registering with the key, [`None`] if the user is being created.
+    /// * `client` - Client which used the key.
+    pub async fn use_key(
+        &self,
+        code: &str,
+        user_id: Option<&str>,
+        client: &ClientInfo,
+    ) -> ServiceResult<()> {
         let code = self.get_by_code(code).await?;
+
+        self.audit_log_service
+            .record(
+                AuditAction::RegistrationKeyUse,
+                user_id,
+                Some(AuditTarget::RegistrationKey(&code.id)),
+                None,
+                client,
+            )
+            .await;
+
         if let Some(uses_left) = code.uses_left {
             if uses_left - 1 <= 0 {
                 // Uses left has hit zero so we delete the code.
@@ -75,6 +115,32 @@ impl RegistrationKeyService {
         Ok(())
     }
 
+    /// Delete a registration key.
+    ///
+    /// * `id` - ID of the key.
+    /// * `user_id` - User deleting the key.
+    /// * `client` - Client which deleted the key.
+    pub async fn delete_registration_key(
+        &self,
+        id: &str,
+        user_id: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<String> {
+        let message = self.delete(id.to_owned(), true, None).await?;
+
+        self.audit_log_service
+            .record(
+                AuditAction::RegistrationKeyDelete,
+                Some(user_id),
+                Some(AuditTarget::RegistrationKey(id)),
+                None,
+                client,
+            )
+            .await;
+
+        Ok(message)
+    }
+
     fn to_uuid(uuid_str: &str) -> ServiceResult<Uuid> {
         Uuid::parse_str(uuid_str)
             .map_err(|_| ServiceError::InvalidData("Invalid registration key".to_string()))
@@ -0,0 +1,149 @@
+use moka::future::Cache;
+use sea_orm::{
+    sea_query::Expr, ActiveEnum, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, Set,
+};
+use std::{sync::Arc, time::Duration};
+
+use super::prelude::*;
+use crate::{
+    database::entity::{sea_orm_active_enums::ThemeColor, settings},
+    models::admin::settings::UpdateSettings,
+};
+
+/// Service for reading and updating server settings.
+///
+/// Settings are stored in a single database row.
+/// The row is cached for a short time since it is read on most requests,
+/// other instances of the server will see changes once their cache expires.
+pub struct SettingsService {
+    database: Arc<DatabaseConnection>,
+    cache: Cache<bool, settings::Model>,
+}
+
+impl SettingsService {
+    pub fn new(database: Arc<DatabaseConnection>) -> Self {
+        Self {
+            database,
+            cache: Cache::builder()
+                .time_to_live(Duration::from_secs(30))
+                .build(),
+        }
+    }
+
+    /// Seed the size limits if they have never been set.
+    /// The file size limit is empty until the settings have been seeded,
+    /// after that this does nothing so changes made by admins are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_size_limit` - File size limit in bytes
+    /// * `default_quota` - Default storage quota in bytes, `None` is unlimited
+    pub async fn seed_limits(
+        &self,
+        file_size_limit: i64,
+        default_quota: Option<i64>,
+    ) -> ServiceResult<()> {
+        settings::Entity::update_many()
+            .col_expr(
+                settings::Column::FileSizeLimit,
+                Expr::value(file_size_limit),
+            )
+            .col_expr(settings::Column::DefaultQuota, Expr::value(default_quota))
+            .filter(settings::Column::FileSizeLimit.is_null())
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        self.cache.invalidate(&true).await;
+
+        Ok(())
+    }
+
+    /// Get the server settings.
+    pub async fn get_settings(&self) -> ServiceResult<settings::Model> {
+        if let Some(settings) = self.cache.get(&true) {
+            return Ok(settings);
+        }
+
+        let settings = settings::Entity::find_by_id(true)
+            .one(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?
+            .ok_or_else(|| ServiceError::ServerError(anyhow::anyhow!("Settings row is missing")))?;
+
+        self.cache.insert(true, settings.clone()).await;
+
+        Ok(settings)
+    }
+
+    /// Validate and update server settings.
+    /// Fields which are not provided are left unchanged.
+    pub async fn update_settings(&self, form: UpdateSettings) -> ServiceResult<settings::Model> {
+        let mut active_settings = self.get_settings().await?.into_active_model();
+
+        if let Some(app_name) = form.app_name {
+            let length = app_name.trim().chars().count();
+            if length < 1 || length > 64 {
+                return Err(ServiceError::InvalidData(
+                    "App name must be between 1 and 64 characters".into(),
+                ));
+            }
+
+            active_settings.app_name = Set(app_name.trim().to_owned());
+        }
+
+        if let Some(app_description) = form.app_description {
+            if app_description.chars().count() > 512 {
+                return Err(ServiceError::InvalidData(
+                    "App description too long (maximum 512 characters)".into(),
+                ));
+            }
+
+            active_settings.app_description = Set(app_description);
+        }
+
+        if let Some(color) = form.color {
+            active_settings.color = Set(ThemeColor::try_from_value(&color).map_err(|_| {
+                ServiceError::InvalidData(format!("{} is not a valid theme color", color))
+            })?);
+        }
+
+        if let Some(registration_open) = form.registration_open {
+            active_settings.registration_open = Set(registration_open);
+        }
+
+        if let Some(default_quota) = form.default_quota {
+            if default_quota < 0 {
+                return Err(ServiceError::InvalidData(
+                    "Default quota can not be negative".into(),
+                ));
+            }
+
+            // A quota of 0 removes the default quota.
+            active_settings.default_quota = Set(match default_quota {
+                0 => None,
+                v => Some(v),
+            });
+        }
+
+        if let Some(file_size_limit) = form.file_size_limit {
+            if file_size_limit < 1 {
+                return Err(ServiceError::InvalidData(
+                    "File size limit must be at least 1 byte".into(),
+                ));
+            }
+
+            active_settings.file_size_limit = Set(file_size_limit);
+        }
+
+        let settings = active_settings
+            .update(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        self.cache.insert(true, settings.clone()).await;
+
+        Ok(settings)
+    }
+}
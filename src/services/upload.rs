@@ -0,0 +1,398 @@
+use chrono::Utc;
+use futures::StreamExt;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, IntoActiveModel,
+    PaginatorTrait, QueryFilter, Set,
+};
+use std::{
+    collections::HashSet,
+    io::SeekFrom,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use super::{
+    file::{file_stream, validate_expiration, FileService, ObjectStream, UploadResult},
+    prelude::*,
+    settings::SettingsService,
+};
+use crate::database::entity::{sea_orm_active_enums::Visibility, uploads, users};
+
+/// Unfinished uploads a user can have.
+const MAX_UPLOADS: usize = 20;
+
+/// How long an upload can go without receiving data before it is deleted.
+const UPLOAD_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Service for resumable uploads.
+///
+/// Uploads are created with their total length and receive data in any number of requests,
+/// each request continues at the offset stored in the database.
+/// Received data is kept in a temporary directory, once every byte has been received
+/// the file is passed to [`FileService::upload_file`] like a regular upload.
+pub struct UploadService {
+    database: Arc<DatabaseConnection>,
+    file_service: Arc<FileService>,
+    settings_service: Arc<SettingsService>,
+    /// Directory where received data is stored.
+    path: PathBuf,
+    /// Uploads which are currently receiving data on this instance.
+    active: Mutex<HashSet<String>>,
+}
+
+data_service!(UploadService, uploads);
+
+/// Result from appending data to an upload.
+pub struct UploadProgress {
+    /// Bytes received so far.
+    pub offset: u64,
+    /// Result of uploading the file, only set once the upload is complete.
+    pub file: Option<UploadResult>,
+}
+
+/// Marks an upload as receiving data until it is dropped.
+struct ActiveUpload<'a> {
+    active: &'a Mutex<HashSet<String>>,
+    id: String,
+}
+
+impl<'a> ActiveUpload<'a> {
+    /// Returns [`None`] if the upload is already receiving data.
+    fn acquire(active: &'a Mutex<HashSet<String>>, id: &str) -> Option<Self> {
+        match active.lock().unwrap().insert(id.to_owned()) {
+            true => Some(Self {
+                active,
+                id: id.to_owned(),
+            }),
+            false => None,
+        }
+    }
+}
+
+impl Drop for ActiveUpload<'_> {
+    fn drop(&mut self) {
+        self.active.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl UploadService {
+    pub fn new(
+        database: Arc<DatabaseConnection>,
+        file_service: Arc<FileService>,
+        settings_service: Arc<SettingsService>,
+        path: PathBuf,
+    ) -> Self {
+        std::fs::create_dir_all(&path).expect("Unable to create the upload directory");
+
+        Self {
+            database,
+            file_service,
+            settings_service,
+            path,
+            active: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Largest upload which can be created, this does not include storage quotas.
+    pub async fn max_size(&self) -> ServiceResult<u64> {
+        Ok(self
+            .settings_service
+            .get_settings()
+            .await?
+            .file_size_limit
+            .max(0) as u64)
+    }
+
+    /// Create an upload.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - User who is uploading the file.
+    /// * `application_id` - Application uploading the file, if an application token was used.
+    /// * `filename` - Original name of the file.
+    /// * `visibility` - Who can access the file.
+    /// * `expiration` - Seconds until the file is deleted once the upload is complete.
+    /// * `length` - Total size of the file.
+    pub async fn create_upload(
+        &self,
+        user: &users::Model,
+        application_id: Option<&str>,
+        filename: &str,
+        visibility: Visibility,
+        expiration: Option<u64>,
+        length: u64,
+    ) -> ServiceResult<uploads::Model> {
+        let expiration = match expiration {
+            Some(v) => Some(validate_expiration(v)?),
+            None => None,
+        };
+
+        // Uploads which would not fit are rejected before any data is sent.
+        let limit = self.file_service.upload_limit(user).await?;
+        if length > limit.limit as u64 {
+            return Err(limit.exceeded());
+        }
+
+        let uploads = uploads::Entity::find()
+            .filter(uploads::Column::UserId.eq(user.id.to_owned()))
+            .count(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        if uploads >= MAX_UPLOADS {
+            return Err(ServiceError::TooManyRequests(format!(
+                "You can only have {} unfinished uploads",
+                MAX_UPLOADS
+            )));
+        }
+
+        let upload = uploads::ActiveModel {
+            user_id: Set(user.id.to_owned()),
+            application_id: Set(application_id.map(|v| v.to_owned())),
+            filename: Set(filename.to_owned()),
+            visibility: Set(visibility),
+            expiration: Set(expiration),
+            length: Set(length as i64),
+            offset: Set(0),
+            created: Set(Utc::now()),
+            expires_at: Set(Utc::now() + chrono::Duration::from_std(UPLOAD_EXPIRY).unwrap()),
+            ..Default::default()
+        }
+        .insert(self.database.as_ref())
+        .await
+        .map_err(|e| ServiceError::DbErr(e))?;
+
+        if let Err(err) = tokio::fs::File::create(self.part_path(&upload.id)).await {
+            let _ = self.delete(upload.id.to_owned(), false, None).await;
+            return Err(ServiceError::ServerError(err.into()));
+        }
+
+        Ok(upload)
+    }
+
+    /// Get an upload of a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Upload ID.
+    /// * `user_id` - User who created the upload.
+    pub async fn get_upload(&self, id: &str, user_id: &str) -> ServiceResult<uploads::Model> {
+        self.by_condition(
+            Condition::all()
+                .add(uploads::Column::Id.eq(id.to_owned()))
+                .add(uploads::Column::UserId.eq(user_id.to_owned())),
+        )
+        .await
+    }
+
+    /// Append data to an upload.
+    /// The file is uploaded once every byte has been received.
+    ///
+    /// Data which was received before the stream failed is kept, so the client can resume from the new offset.
+    /// If the file could not be stored because of a server error, sending no data at the final offset retries it.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Upload ID.
+    /// * `user` - User who created the upload.
+    /// * `offset` - Offset the client is sending from, this must match the offset of the upload.
+    /// * `stream` - Data to append.
+    pub async fn append(
+        &self,
+        id: &str,
+        user: &users::Model,
+        offset: u64,
+        mut stream: ObjectStream,
+    ) -> ServiceResult<UploadProgress> {
+        let _active = match ActiveUpload::acquire(&self.active, id) {
+            Some(v) => v,
+            None => {
+                return Err(ServiceError::Conflict(
+                    "Upload is already receiving data".into(),
+                ))
+            }
+        };
+
+        let upload = self.get_upload(id, &user.id).await?;
+        if offset != upload.offset as u64 {
+            return Err(ServiceError::Conflict(format!(
+                "Upload offset is {}",
+                upload.offset
+            )));
+        }
+
+        let length = upload.length as u64;
+        let mut received = offset;
+
+        let result: ServiceResult<()> = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .open(self.part_path(id))
+                .await
+                .map_err(|e| ServiceError::ServerError(e.into()))?;
+
+            // Drop anything written after the last recorded offset.
+            file.set_len(offset)
+                .await
+                .map_err(|e| ServiceError::ServerError(e.into()))?;
+            file.seek(SeekFrom::Start(offset))
+                .await
+                .map_err(|e| ServiceError::ServerError(e.into()))?;
+
+            let result = async {
+                while let Some(chunk) = stream.next().await {
+                    let chunk = chunk.map_err(|e| ServiceError::ServerError(e))?;
+
+                    if received + chunk.len() as u64 > length {
+                        return Err(ServiceError::InvalidData(format!(
+                            "Upload is larger than its length of {} bytes",
+                            length
+                        )));
+                    }
+
+                    file.write_all(&chunk)
+                        .await
+                        .map_err(|e| ServiceError::ServerError(e.into()))?;
+                    received += chunk.len() as u64;
+                }
+
+                Ok(())
+            }
+            .await;
+
+            // Whatever was written is recorded even if the stream failed,
+            // unless it may not have reached the file.
+            if let Err(err) = file.flush().await {
+                received = offset;
+                return Err(ServiceError::ServerError(err.into()));
+            }
+
+            result
+        }
+        .await;
+
+        // Receiving data extends the expiry of an upload.
+        if received != offset {
+            let mut active_model = upload.clone().into_active_model();
+            active_model.offset = Set(received as i64);
+            active_model.expires_at =
+                Set(Utc::now() + chrono::Duration::from_std(UPLOAD_EXPIRY).unwrap());
+            active_model
+                .update(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?;
+        }
+
+        result?;
+
+        if received < length {
+            return Ok(UploadProgress {
+                offset: received,
+                file: None,
+            });
+        }
+
+        let result = async {
+            let file = tokio::fs::File::open(self.part_path(id))
+                .await
+                .map_err(|e| ServiceError::ServerError(e.into()))?;
+
+            self.file_service
+                .upload_file(
+                    user,
+                    upload.application_id.as_deref(),
+                    &upload.filename,
+                    upload.visibility.to_owned(),
+                    upload.expiration.map(|v| v as u64),
+                    file_stream(file, length),
+                )
+                .await
+        }
+        .await;
+
+        match result {
+            // Storage or database failures may be temporary, the upload is kept so it can be retried.
+            Err(ServiceError::ServerError(_)) | Err(ServiceError::DbErr(_)) => {}
+            _ => self.remove(&upload.id).await?,
+        }
+
+        Ok(UploadProgress {
+            offset: received,
+            file: Some(result?),
+        })
+    }
+
+    /// Delete all uploads which have expired.
+    /// Stored data without an upload is also removed once it is older than the upload expiry,
+    /// other files in the upload directory are never touched.
+    pub async fn delete_expired(&self) -> ServiceResult<usize> {
+        let expired = uploads::Entity::find()
+            .filter(uploads::Column::ExpiresAt.lte(Utc::now()))
+            .all(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        let mut removed = 0;
+
+        for upload in expired.iter() {
+            // Uploads which are receiving data are left alone, their expiry is extended afterwards.
+            let _active = match ActiveUpload::acquire(&self.active, &upload.id) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            self.remove(&upload.id).await?;
+            removed += 1;
+        }
+
+        // Data is left behind if an upload was deleted along with its user.
+        let result: Result<(), std::io::Error> = async {
+            let mut entries = tokio::fs::read_dir(&self.path).await?;
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().and_then(|v| v.to_str()) != Some("part") {
+                    continue;
+                }
+
+                let modified = entry.metadata().await?.modified()?;
+                let stale = SystemTime::now()
+                    .duration_since(modified)
+                    .map(|age| age > UPLOAD_EXPIRY)
+                    .unwrap_or(false);
+
+                // Uploads expire before their data can become this old.
+                if stale {
+                    tokio::fs::remove_file(path).await?;
+                }
+            }
+
+            Ok(())
+        }
+        .await;
+
+        if let Err(err) = result {
+            log::error!("Failed to remove stale upload data: {}", err);
+        }
+
+        Ok(removed)
+    }
+
+    /// Delete an upload and its stored data.
+    async fn remove(&self, id: &str) -> ServiceResult<()> {
+        self.delete(id.to_owned(), false, None).await?;
+
+        match tokio::fs::remove_file(self.part_path(id)).await {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(ServiceError::ServerError(err.into())),
+        }
+    }
+
+    /// Path where data of an upload is stored.
+    fn part_path(&self, id: &str) -> PathBuf {
+        self.path.join(format!("{}.part", id))
+    }
+}
@@ -1,34 +1,48 @@
+use chrono::Utc;
 use lettre::{
     transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message,
     Tokio1Executor,
 };
 use regex::Regex;
 use sea_orm::{
+    sea_query::{Expr, Func},
     ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, IntoActiveModel,
     ModelTrait, QueryFilter, Set,
 };
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
 
 use super::{
-    auth::{auth_method::AuthMethodService, new_password, validate_password},
-    file::FileService,
+    auth::{auth_method::AuthMethodService, new_password, session::SessionService},
+    file::{validate_expiration, FileService},
     prelude::*,
+    rate_limit::{limits, RateLimitService},
     registration_key::RegistrationKeyService,
+    webhook::WebhookService,
     ToOption,
 };
 use crate::{
     config::SMTPConfig,
     database::entity::{
-        auth_methods, files, sea_orm_active_enums::AuthMethod, users, verifications,
+        auth_methods, files, password_resets,
+        sea_orm_active_enums::{AuthMethod, Role},
+        users, verifications,
     },
-    internal::random_string,
+    internal::{auth::ClientInfo, random_string},
+    models::{UserData, WebhookEvent},
 };
 
+/// Minutes a password reset code is valid for.
+const PASSWORD_RESET_EXPIRY_MINUTES: i64 = 60;
+
 pub struct UserService {
     database: Arc<DatabaseConnection>,
     registration_key_service: Arc<RegistrationKeyService>,
     file_service: Arc<FileService>,
     auth_method_service: Arc<AuthMethodService>,
+    session_service: Arc<SessionService>,
+    rate_limit_service: Arc<RateLimitService>,
+    webhook_service: Arc<WebhookService>,
     // If we need to send more emails, this should be split into an email service.
     smtp: Option<(AsyncSmtpTransport<Tokio1Executor>, String)>,
     client_url: String,
@@ -43,6 +57,9 @@ impl UserService {
         registration_key_service: Arc<RegistrationKeyService>,
         file_service: Arc<FileService>,
         auth_method_service: Arc<AuthMethodService>,
+        session_service: Arc<SessionService>,
+        rate_limit_service: Arc<RateLimitService>,
+        webhook_service: Arc<WebhookService>,
         smtp_config: Option<SMTPConfig>,
         client_url: &str,
         use_key: bool,
@@ -52,6 +69,9 @@ impl UserService {
             registration_key_service,
             file_service,
             auth_method_service,
+            session_service,
+            rate_limit_service,
+            webhook_service,
             smtp: match smtp_config {
                 Some(config) => {
                     let creds = Credentials::new(config.username.clone(), config.password);
@@ -102,15 +122,20 @@ impl UserService {
     ///
     /// * `username` - Users username
     /// * `email` - User email
-    /// * `auth_method` - (method, identifier, optional username)
+    /// * `auth_method` - (method, identifier, optional username, OpenID Connect provider name)
     /// * `registration_key` - Registration key. This can always be validated later.
+    /// * `client` - Client creating the user.
     pub async fn create_user(
         &self,
         username: String,
         email: String,
-        auth_method: (AuthMethod, String, Option<String>),
+        auth_method: (AuthMethod, String, Option<String>, String),
         registration_key: Option<String>,
+        client: &ClientInfo,
     ) -> ServiceResult<users::Model> {
+        // Directory users are managed by the directory, they can always sign up.
+        let directory_user = auth_method.0 == AuthMethod::Ldap;
+
         validate_username(&username)?;
         if !EMAIL_REGEX.is_match(&email) {
             return Err(ServiceError::InvalidData(
@@ -146,10 +171,12 @@ impl UserService {
             _ => auth_method.1,
         };
 
-        let registered = if self.invite_only() {
+        let registered = if self.invite_only() && !directory_user {
             if let Some(key) = registration_key {
                 // This will validate and use the key. Will return proper error.
-                self.registration_key_service.use_key(&key).await?;
+                self.registration_key_service
+                    .use_key(&key, None, client)
+                    .await?;
                 true
             } else {
                 // Registration key is required for password version of this method.
@@ -181,6 +208,7 @@ impl UserService {
         auth_methods::ActiveModel {
             user_id: Set(user.id.clone()),
             auth_method: Set(auth_method.0.clone()),
+            provider: Set(auth_method.3),
             cached_username: Set(auth_method.2),
             value: Set(method_value),
             ..Default::default()
@@ -203,6 +231,7 @@ impl UserService {
         &self,
         user: &users::Model,
         registration_key: &str,
+        client: &ClientInfo,
     ) -> ServiceResult<users::Model> {
         if !self.invite_only() {
             return Err(ServiceError::InvalidData(
@@ -210,8 +239,12 @@ impl UserService {
             ));
         }
 
+        self.rate_limit_service
+            .hit(limits::REGISTRATION_KEY, &user.id)
+            .await?;
+
         self.registration_key_service
-            .use_key(registration_key)
+            .use_key(registration_key, Some(&user.id), client)
             .await?;
 
         let mut active_user = user.clone().into_active_model();
@@ -230,6 +263,7 @@ impl UserService {
     /// * `username` - New username.
     /// * `new_password` - New password.
     /// * `current_password` - Current password is required to change settings (if current password existed).
+    /// * `client` - Client changing the settings.
     ///
     /// # Returns
     ///
@@ -241,6 +275,7 @@ impl UserService {
         username: Option<String>,
         password: Option<String>,
         current_password: Option<String>,
+        client: &ClientInfo,
     ) -> ServiceResult<users::Model> {
         self.verify_password_action(user, current_password).await?;
 
@@ -292,7 +327,7 @@ impl UserService {
         // This is done last since it is a seperate operation and occurs last.
         if let Some(password) = &password {
             self.auth_method_service
-                .create_or_set_method(&user.id, AuthMethod::Password, None, password)
+                .create_or_set_method(&user.id, AuthMethod::Password, None, password, client)
                 .await?;
         }
 
@@ -311,6 +346,117 @@ impl UserService {
         Ok(self.by_id(user.id.to_owned()).await?)
     }
 
+    /// Set the default time until files uploaded by a user expire.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - User to update.
+    /// * `expiration` - Seconds until files expire, files are kept forever if this is [`None`].
+    pub async fn set_default_expiration(
+        &self,
+        user: &users::Model,
+        expiration: Option<u64>,
+    ) -> ServiceResult<users::Model> {
+        let expiration = match expiration {
+            Some(v) => Some(validate_expiration(v)?),
+            None => None,
+        };
+
+        let mut active_user = user.clone().into_active_model();
+        active_user.default_expiration = Set(expiration);
+
+        active_user
+            .update(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))
+    }
+
+    /// Get a page of users.
+    ///
+    /// # Arguments
+    ///
+    /// * `page` - Page number (starts at 1).
+    /// * `page_size` - Amount of users per page.
+    /// * `search` - Search by username or email.
+    pub async fn get_user_page(
+        &self,
+        page: usize,
+        page_size: usize,
+        search: Option<String>,
+    ) -> ServiceResult<ServicePage<users::Model>> {
+        let condition = search.map(|search| {
+            Condition::any()
+                .add(
+                    Expr::tbl(users::Entity, users::Column::Username)
+                        .like(contains_pattern(&search)),
+                )
+                .add(Expr::tbl(users::Entity, users::Column::Email).like(contains_pattern(&search)))
+        });
+
+        self.get_page(page, page_size, condition).await
+    }
+
+    /// Set the role of a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the user.
+    /// * `role` - New role of the user.
+    pub async fn set_role(&self, id: &str, role: Role) -> ServiceResult<users::Model> {
+        let mut active_user = self.by_id(id.to_owned()).await?.into_active_model();
+        active_user.role = Set(role);
+
+        active_user
+            .update(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))
+    }
+
+    /// Suspend or unsuspend a user.
+    /// Suspended users can't use any of their tokens.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the user.
+    /// * `suspended` - Whether the user is suspended.
+    pub async fn set_suspended(&self, id: &str, suspended: bool) -> ServiceResult<users::Model> {
+        let mut active_user = self.by_id(id.to_owned()).await?.into_active_model();
+        active_user.suspended = Set(suspended);
+
+        active_user
+            .update(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))
+    }
+
+    /// Set the storage quota of a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the user.
+    /// * `quota` - Storage quota in bytes, the server default is used if this is [`None`].
+    pub async fn set_storage_quota(
+        &self,
+        id: &str,
+        quota: Option<i64>,
+    ) -> ServiceResult<users::Model> {
+        if let Some(quota) = quota {
+            if quota < 0 {
+                return Err(ServiceError::InvalidData(
+                    "Storage quota can not be negative".into(),
+                ));
+            }
+        }
+
+        let mut active_user = self.by_id(id.to_owned()).await?.into_active_model();
+        active_user.storage_quota = Set(quota);
+
+        active_user
+            .update(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))
+    }
+
     /// Resend a verification code.
     /// This should be triggered only if the user is not verified.
     ///
@@ -334,7 +480,7 @@ impl UserService {
             .await
             .map_err(|e| ServiceError::DbErr(e))?;
 
-        users::ActiveModel {
+        let verified_user = users::ActiveModel {
             id: Set(user.id.to_owned()),
             verified: Set(true),
             ..Default::default()
@@ -343,15 +489,33 @@ impl UserService {
         .await
         .map_err(|e| ServiceError::DbErr(e))?;
 
+        if !user.verified {
+            self.webhook_service
+                .emit(
+                    WebhookEvent::UserVerified,
+                    &user.id,
+                    None,
+                    &UserData::from(verified_user),
+                )
+                .await;
+        }
+
         Ok(())
     }
 
     /// Verify a user by a verification code.
-    pub async fn verify_by_code(&self, code: &str) -> ServiceResult<()> {
+    ///
+    /// * `code` - Verification code.
+    /// * `client` - Client using the code, codes entered from an IP are limited.
+    pub async fn verify_by_code(&self, code: &str, client: &ClientInfo) -> ServiceResult<()> {
         if let None = self.smtp {
             return Err(ServiceError::Conflict("SMTP is disabled".into()));
         }
 
+        self.rate_limit_service
+            .hit_client(limits::CODE_IP, client)
+            .await?;
+
         match verifications::Entity::find()
             .filter(verifications::Column::Code.eq(code.to_owned()))
             .find_also_related(users::Entity)
@@ -385,6 +549,122 @@ impl UserService {
         }
     }
 
+    /// Email a password reset code to a user.
+    ///
+    /// Nothing is sent if no account has the email or too many resets were requested for it,
+    /// the caller is not told about either so this can't be used to find accounts.
+    pub async fn request_password_reset(&self, email: &str) -> ServiceResult<()> {
+        let smtp = match &self.smtp {
+            Some(v) => v,
+            None => return Err(ServiceError::Conflict("SMTP is disabled".into())),
+        };
+
+        let email = email.trim().to_lowercase();
+        match self
+            .rate_limit_service
+            .hit(limits::PASSWORD_RESET_EMAIL, &email)
+            .await
+        {
+            Err(ServiceError::TooManyRequests(_)) => return Ok(()),
+            result => result?,
+        }
+
+        // Emails are stored as they were entered, so they are compared case-insensitively.
+        let user = match users::Entity::find()
+            .filter(Expr::expr(Func::lower(Expr::col(users::Column::Email))).eq(email))
+            .one(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?
+        {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let code = random_string(72);
+
+        // Only the latest code can be used.
+        password_resets::Entity::delete_many()
+            .filter(password_resets::Column::UserId.eq(user.id.to_owned()))
+            .exec(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        password_resets::ActiveModel {
+            user_id: Set(user.id.to_owned()),
+            code: Set(hash_reset_code(&code)),
+            expires_at: Set(Utc::now() + chrono::Duration::minutes(PASSWORD_RESET_EXPIRY_MINUTES)),
+            ..Default::default()
+        }
+        .insert(self.database.as_ref())
+        .await
+        .map_err(|e| ServiceError::DbErr(e))?;
+
+        let email = password_reset_email(&self.client_url, &smtp.1, &user.email, &code);
+        let mailer = smtp.clone().0;
+        tokio::spawn(async move {
+            let _ = mailer.send(email).await;
+        });
+
+        Ok(())
+    }
+
+    /// Set a new password using a reset code.
+    /// The code can only be used once and every session of the user is revoked.
+    ///
+    /// * `code` - Password reset code.
+    /// * `password` - New password.
+    /// * `client` - Client using the code, codes entered from an IP are limited.
+    pub async fn reset_password(
+        &self,
+        code: &str,
+        password: &str,
+        client: &ClientInfo,
+    ) -> ServiceResult<()> {
+        if let None = self.smtp {
+            return Err(ServiceError::Conflict("SMTP is disabled".into()));
+        }
+
+        self.rate_limit_service
+            .hit_client(limits::CODE_IP, client)
+            .await?;
+
+        let (reset, user) = match password_resets::Entity::find()
+            .filter(password_resets::Column::Code.eq(hash_reset_code(code)))
+            .find_also_related(users::Entity)
+            .one(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?
+        {
+            Some((reset, Some(user))) if reset.expires_at > Utc::now() => (reset, user),
+            _ => {
+                return Err(ServiceError::InvalidData(
+                    "Invalid or expired reset code was provided".into(),
+                ))
+            }
+        };
+
+        // Validate the password before using up the code.
+        new_password(password)?;
+
+        reset
+            .delete(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        self.auth_method_service
+            .create_or_set_method(&user.id, AuthMethod::Password, None, password, client)
+            .await?;
+
+        self.session_service.revoke_all(&user.id, None).await?;
+
+        // The reset code was sent to the users email, so it is verified.
+        if !user.verified {
+            self.verify_user(&user).await?;
+        }
+
+        Ok(())
+    }
+
     /// Delete a user.
     ///
     /// # Arguments
@@ -393,23 +673,21 @@ impl UserService {
     /// * `password` - If provided, will make sure that the correct password is provided.
     pub async fn delete(&self, user: &users::Model, password: Option<String>) -> ServiceResult<()> {
         self.verify_password_action(user, password).await?;
+        self.force_delete(user).await
+    }
 
-        let mut files: Vec<String> = user
+    /// Delete a user without verifying their password.
+    /// This should only be used for administrative actions.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - User to delete.
+    pub async fn force_delete(&self, user: &users::Model) -> ServiceResult<()> {
+        let files = user
             .find_related(files::Entity)
             .all(self.database.as_ref())
             .await
-            .map_err(|e| ServiceError::DbErr(e))?
-            .iter()
-            .map(|f| (f.name.to_owned()))
-            .collect();
-
-        // Add all thumbnails, not all files will have thumbnails but this will work.
-        files.extend(
-            files
-                .iter()
-                .map(|f| format!("thumb/{}", f))
-                .collect::<Vec<String>>(),
-        );
+            .map_err(|e| ServiceError::DbErr(e))?;
 
         // Delete the user before deleting the files.
         // File deletion may take a while, if something happens to the server we would rather keep the actual files rather than the records.
@@ -419,8 +697,8 @@ impl UserService {
             .await
             .map_err(|e| ServiceError::DbErr(e))?;
 
-        // Delete every file.
-        let _ = self.file_service.storage.delete_objects(files).await;
+        // Release every file, blobs shared with other users are kept.
+        self.file_service.release_blobs(&files).await?;
 
         Ok(())
     }
@@ -490,7 +768,9 @@ impl UserService {
             .to_option()?
         {
             if let Some(password) = password {
-                validate_password(&v.value, &password)?
+                self.auth_method_service
+                    .validate_user_password(&v, &password)
+                    .await?
             } else {
                 return Err(ServiceError::InvalidData(
                     "Password is required since you have a password.".into(),
@@ -526,6 +806,24 @@ fn verification_email(client_url: &str, from_email: &str, email: &str, code: &st
         .unwrap()
 }
 
+/// Create password reset email.
+fn password_reset_email(client_url: &str, from_email: &str, email: &str, code: &str) -> Message {
+    Message::builder()
+        .from(from_email.parse().unwrap())
+        .to(email.parse().unwrap())
+        .subject("Reset your password")
+        .body(format!(
+            "Please click on this link to reset your password, it expires in {} minutes\n{}user/reset?code={}\n\nIf you did not request this, you can ignore this email.",
+            PASSWORD_RESET_EXPIRY_MINUTES, client_url, code
+        ))
+        .unwrap()
+}
+
+/// Reset codes are stored as a hex encoded SHA256 hash.
+fn hash_reset_code(code: &str) -> String {
+    format!("{:x}", Sha256::digest(code.as_bytes()))
+}
+
 /// Validate a username.
 fn validate_username(username: &str) -> ServiceResult<()> {
     let username_length = username.len();
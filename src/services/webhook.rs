@@ -0,0 +1,610 @@
+use chrono::Utc;
+use futures::future::join_all;
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use sea_orm::{
+    prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection,
+    EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter, Set,
+};
+use serde::Serialize;
+use sha2::Sha256;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use url::Host;
+
+use super::prelude::*;
+use crate::{
+    database::entity::{applications, webhook_deliveries, webhooks},
+    internal::random_string,
+    models::{WebhookData, WebhookEvent},
+};
+
+/// Webhooks a user can have.
+const MAX_WEBHOOKS: usize = 10;
+
+/// Attempts made to deliver an event before it is given up on.
+const MAX_ATTEMPTS: i32 = 5;
+
+/// Receivers must respond within this time.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before the first retry, this is multiplied by 4 after every attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Body posted to webhooks.
+#[derive(Serialize)]
+struct Payload<'a, T: Serialize> {
+    event: &'a str,
+    created: DateTimeUtc,
+    data: &'a T,
+}
+
+/// Delivery log, this is only used for its data service.
+struct DeliveryLog {
+    database: Arc<DatabaseConnection>,
+}
+
+data_service!(DeliveryLog, webhook_deliveries);
+
+/// Sends events to URLs chosen by users.
+///
+/// Every event is stored as a delivery before it is sent, failed deliveries are retried with backoff by [`WebhookService::retry_pending`].
+/// Deliveries are signed with HMAC-SHA256 using the secret of the webhook:
+///
+/// * `X-Backpack-Event` - Event name.
+/// * `X-Backpack-Delivery` - Delivery ID, this is the same for retries.
+/// * `X-Backpack-Timestamp` - Unix timestamp of the attempt.
+/// * `X-Backpack-Signature` - `sha256=` followed by the hex encoded HMAC of `{timestamp}.{body}`.
+///
+/// Every instance retries pending deliveries, so receivers should ignore delivery IDs they have already seen.
+///
+/// Receivers on private, loopback and link-local addresses are refused unless `allow_local` is set,
+/// the host is resolved and checked before every attempt and the request is sent to the checked address.
+pub struct WebhookService {
+    database: Arc<DatabaseConnection>,
+    deliveries: DeliveryLog,
+    allow_local: bool,
+}
+
+data_service!(WebhookService, webhooks);
+
+impl WebhookService {
+    pub fn new(database: Arc<DatabaseConnection>, allow_local: bool) -> Self {
+        Self {
+            deliveries: DeliveryLog {
+                database: database.clone(),
+            },
+            database,
+            allow_local,
+        }
+    }
+
+    /// Create a webhook.
+    ///
+    /// * `user_id` - User who owns the webhook.
+    /// * `url` - `http` or `https` URL events are posted to.
+    /// * `events` - Events sent to the webhook.
+    /// * `application_id` - Only send events caused by this application, it must be owned by the user.
+    ///
+    /// Returns [`WebhookData`] with the secret.
+    pub async fn create_webhook(
+        &self,
+        user_id: &str,
+        url: &str,
+        events: &[WebhookEvent],
+        application_id: Option<&str>,
+    ) -> ServiceResult<WebhookData> {
+        match url::Url::parse(url) {
+            Ok(v) if v.scheme() == "http" || v.scheme() == "https" => {}
+            _ => {
+                return Err(ServiceError::InvalidData(
+                    "Webhook URL must be a valid http or https URL".into(),
+                ))
+            }
+        }
+
+        // This is checked again before every attempt since DNS records can change.
+        receiver_client(url, self.allow_local)
+            .await
+            .map_err(|e| ServiceError::InvalidData(e))?;
+
+        let events = WebhookEvent::format_list(events);
+        if events.is_empty() {
+            return Err(ServiceError::InvalidData(
+                "Webhooks need at least one event".into(),
+            ));
+        }
+
+        if let Some(application_id) = application_id {
+            applications::Entity::find()
+                .filter(applications::Column::Id.eq(application_id.to_owned()))
+                .filter(applications::Column::UserId.eq(user_id.to_owned()))
+                .one(self.database.as_ref())
+                .await
+                .map_err(|e| ServiceError::DbErr(e))?
+                .ok_or_else(|| ServiceError::NotFound("Application".into()))?;
+        }
+
+        let count = webhooks::Entity::find()
+            .filter(webhooks::Column::UserId.eq(user_id.to_owned()))
+            .count(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        if count >= MAX_WEBHOOKS {
+            return Err(ServiceError::InvalidData(format!(
+                "You can only have {} webhooks",
+                MAX_WEBHOOKS
+            )));
+        }
+
+        let webhook = webhooks::ActiveModel {
+            user_id: Set(user_id.to_owned()),
+            application_id: Set(application_id.map(|v| v.to_owned())),
+            url: Set(url.to_owned()),
+            secret: Set(random_string(32)),
+            events: Set(events),
+            ..Default::default()
+        }
+        .insert(self.database.as_ref())
+        .await
+        .map_err(|e| ServiceError::DbErr(e))?;
+
+        let secret = webhook.secret.clone();
+        let mut webhook_data = WebhookData::from(webhook);
+        webhook_data.secret = Some(secret);
+
+        Ok(webhook_data)
+    }
+
+    /// Get a webhook owned by a user.
+    pub async fn get_webhook(&self, id: &str, user_id: &str) -> ServiceResult<webhooks::Model> {
+        self.by_condition(
+            Condition::all()
+                .add(webhooks::Column::Id.eq(id.to_owned()))
+                .add(webhooks::Column::UserId.eq(user_id.to_owned())),
+        )
+        .await
+    }
+
+    /// Get a page of deliveries of a webhook owned by a user.
+    pub async fn get_delivery_page(
+        &self,
+        id: &str,
+        user_id: &str,
+        page: usize,
+        page_size: usize,
+    ) -> ServiceResult<ServicePage<webhook_deliveries::Model>> {
+        let webhook = self.get_webhook(id, user_id).await?;
+
+        self.deliveries
+            .get_page(
+                page,
+                page_size,
+                Some(Condition::all().add(webhook_deliveries::Column::WebhookId.eq(webhook.id))),
+            )
+            .await
+    }
+
+    /// Send an event to the webhooks of a user.
+    /// Events are delivered in the background, failures are logged and never fail the action which caused the event.
+    ///
+    /// * `event` - Event which happened.
+    /// * `user_id` - User the event belongs to.
+    /// * `application_id` - Application which caused the event, if it was caused by an application token.
+    /// * `data` - Resource the event is about, this is sent as `data`.
+    pub async fn emit<T: Serialize>(
+        &self,
+        event: WebhookEvent,
+        user_id: &str,
+        application_id: Option<&str>,
+        data: &T,
+    ) {
+        if let Err(e) = self.queue(event, user_id, application_id, data).await {
+            log::error!("Failed to queue {} webhooks: {}", event.as_str(), e);
+        }
+    }
+
+    async fn queue<T: Serialize>(
+        &self,
+        event: WebhookEvent,
+        user_id: &str,
+        application_id: Option<&str>,
+        data: &T,
+    ) -> ServiceResult<()> {
+        // Webhooks without an application receive events from everything the user does.
+        let mut application_condition =
+            Condition::any().add(webhooks::Column::ApplicationId.is_null());
+        if let Some(application_id) = application_id {
+            application_condition = application_condition
+                .add(webhooks::Column::ApplicationId.eq(application_id.to_owned()));
+        }
+
+        let subscribed = webhooks::Entity::find()
+            .filter(webhooks::Column::UserId.eq(user_id.to_owned()))
+            .filter(application_condition)
+            .all(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?
+            .into_iter()
+            .filter(|webhook| WebhookEvent::parse_list(&webhook.events).contains(&event))
+            .collect::<Vec<_>>();
+
+        if subscribed.is_empty() {
+            return Ok(());
+        }
+
+        let payload = serialize_payload(event, data)?;
+
+        for webhook in subscribed {
+            let delivery = self.create_delivery(&webhook, event, &payload).await?;
+
+            let database = self.database.clone();
+            let allow_local = self.allow_local;
+            tokio::spawn(async move {
+                if let Err(e) = attempt(&database, allow_local, &webhook, delivery).await {
+                    log::error!("Failed to deliver webhook {}: {}", webhook.id, e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Send a `ping` event to a webhook and wait for the receiver to respond.
+    /// Pings are not retried.
+    ///
+    /// Returns the delivery with the result.
+    pub async fn test_webhook(
+        &self,
+        id: &str,
+        user_id: &str,
+    ) -> ServiceResult<webhook_deliveries::Model> {
+        let webhook = self.get_webhook(id, user_id).await?;
+        let payload = serialize_payload(WebhookEvent::Ping, &WebhookData::from(webhook.clone()))?;
+        let delivery = self
+            .create_delivery(&webhook, WebhookEvent::Ping, &payload)
+            .await?;
+
+        attempt(&self.database, self.allow_local, &webhook, delivery).await
+    }
+
+    /// Attempt deliveries which are due for a retry.
+    /// This includes deliveries which were queued before a restart.
+    ///
+    /// Returns [`usize`] the amount of deliveries attempted.
+    pub async fn retry_pending(&self) -> ServiceResult<usize> {
+        let pending = webhook_deliveries::Entity::find()
+            .filter(webhook_deliveries::Column::NextAttemptAt.lte(Utc::now()))
+            .find_also_related(webhooks::Entity)
+            .all(self.database.as_ref())
+            .await
+            .map_err(|e| ServiceError::DbErr(e))?;
+
+        let attempts = pending
+            .into_iter()
+            .filter_map(|(delivery, webhook)| Some((delivery, webhook?)))
+            .map(|(delivery, webhook)| async move {
+                if let Err(e) = attempt(&self.database, self.allow_local, &webhook, delivery).await
+                {
+                    log::error!("Failed to deliver webhook {}: {}", webhook.id, e);
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let count = attempts.len();
+        join_all(attempts).await;
+
+        Ok(count)
+    }
+
+    async fn create_delivery(
+        &self,
+        webhook: &webhooks::Model,
+        event: WebhookEvent,
+        payload: &str,
+    ) -> ServiceResult<webhook_deliveries::Model> {
+        webhook_deliveries::ActiveModel {
+            webhook_id: Set(webhook.id.to_owned()),
+            event: Set(event.as_str().to_owned()),
+            payload: Set(payload.to_owned()),
+            // The first attempt is made right away, this only applies if it never completes.
+            next_attempt_at: Set(match event {
+                WebhookEvent::Ping => None,
+                _ => Some(Utc::now() + retry_delay(1)),
+            }),
+            ..Default::default()
+        }
+        .insert(self.database.as_ref())
+        .await
+        .map_err(|e| ServiceError::DbErr(e))
+    }
+}
+
+/// Attempt to deliver an event and record the result.
+async fn attempt(
+    database: &DatabaseConnection,
+    allow_local: bool,
+    webhook: &webhooks::Model,
+    delivery: webhook_deliveries::Model,
+) -> ServiceResult<webhook_deliveries::Model> {
+    let timestamp = Utc::now().timestamp();
+
+    let http_client = match receiver_client(&webhook.url, allow_local).await {
+        Ok(v) => v,
+        Err(e) => return record_attempt(database, delivery, None, Some(e)).await,
+    };
+
+    let result = http_client
+        .post(&webhook.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header("X-Backpack-Event", &delivery.event)
+        .header("X-Backpack-Delivery", &delivery.id)
+        .header("X-Backpack-Timestamp", timestamp)
+        .header(
+            "X-Backpack-Signature",
+            format!(
+                "sha256={}",
+                sign(&webhook.secret, timestamp, &delivery.payload)
+            ),
+        )
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    let (status_code, error) = match result {
+        Ok(response) if response.status().is_success() => {
+            (Some(response.status().as_u16() as i32), None)
+        }
+        Ok(response) => (
+            Some(response.status().as_u16() as i32),
+            Some(format!("Receiver responded with {}", response.status())),
+        ),
+        // Errors are shown to the owner of the webhook, so they don't include details about the network.
+        Err(e) => {
+            log::debug!("Webhook {} delivery failed: {}", webhook.id, e);
+            (
+                None,
+                Some(
+                    match e {
+                        e if e.is_timeout() => "Receiver did not respond in time",
+                        e if e.is_connect() => "Unable to connect to the receiver",
+                        _ => "Request to the receiver failed",
+                    }
+                    .to_owned(),
+                ),
+            )
+        }
+    };
+
+    record_attempt(database, delivery, status_code, error).await
+}
+
+/// Record the result of an attempt and schedule a retry if it failed.
+async fn record_attempt(
+    database: &DatabaseConnection,
+    delivery: webhook_deliveries::Model,
+    status_code: Option<i32>,
+    error: Option<String>,
+) -> ServiceResult<webhook_deliveries::Model> {
+    let attempts = delivery.attempts + 1;
+    let delivered = error.is_none();
+    let retry =
+        !delivered && attempts < MAX_ATTEMPTS && delivery.event != WebhookEvent::Ping.as_str();
+
+    let mut active_delivery = delivery.into_active_model();
+    active_delivery.attempts = Set(attempts);
+    active_delivery.delivered = Set(delivered);
+    active_delivery.status_code = Set(status_code);
+    active_delivery.error = Set(error);
+    active_delivery.next_attempt_at = Set(match retry {
+        true => Some(Utc::now() + retry_delay(attempts + 1)),
+        false => None,
+    });
+
+    active_delivery
+        .update(database)
+        .await
+        .map_err(|e| ServiceError::DbErr(e))
+}
+
+/// Build a client which sends requests to the checked address of a receiver.
+/// The host is resolved here and the client doesn't resolve it again, so DNS can't send the request anywhere else.
+///
+/// Returns an error which can be shown to the owner of the webhook.
+async fn receiver_client(url: &str, allow_local: bool) -> Result<reqwest::Client, String> {
+    let url = url::Url::parse(url).map_err(|_| "Webhook URL is invalid".to_owned())?;
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| "Webhook URL is invalid".to_owned())?;
+
+    let addresses: Vec<SocketAddr> = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|_| format!("Unable to resolve {}", domain))?
+            .collect(),
+        None => return Err("Webhook URL is invalid".into()),
+    };
+
+    // Every address has to be public, otherwise the connection could fall back to a private one.
+    if !allow_local && addresses.iter().any(|v| !is_public(&v.ip())) {
+        return Err("Webhook receivers must have a public address".into());
+    }
+
+    let address = addresses
+        .first()
+        .ok_or_else(|| "Webhook host has no addresses".to_owned())?;
+
+    let mut builder = reqwest::Client::builder()
+        .user_agent("Backpack-Webhook")
+        .timeout(DELIVERY_TIMEOUT)
+        // Proxies and redirects could be used to reach other hosts than the one which was checked.
+        .no_proxy()
+        .redirect(reqwest::redirect::Policy::none());
+
+    if let Some(Host::Domain(domain)) = url.host() {
+        builder = builder.resolve(domain, *address);
+    }
+
+    builder
+        .build()
+        .map_err(|_| "Unable to create the webhook request".into())
+}
+
+/// Check if an address can be reached from the internet.
+/// Loopback, private, link-local and other reserved ranges aren't.
+fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // 0.0.0.0/8 "this network"
+                || octets[0] == 0
+                // 100.64.0.0/10 shared address space
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+                // 192.0.0.0/24 protocol assignments
+                || (octets[0] == 192 && octets[1] == 0 && octets[2] == 0)
+                // 198.18.0.0/15 benchmarking
+                || (octets[0] == 198 && (octets[1] & 0xfe) == 18)
+                // 240.0.0.0/4 reserved
+                || octets[0] >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = embedded_ipv4(ip) {
+                return is_public(&IpAddr::V4(ip));
+            }
+
+            let segments = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // fc00::/7 unique local
+                || (segments[0] & 0xfe00) == 0xfc00
+                // fe80::/10 link-local
+                || (segments[0] & 0xffc0) == 0xfe80
+                // 2001:db8::/32 documentation
+                || (segments[0] == 0x2001 && segments[1] == 0xdb8))
+        }
+    }
+}
+
+/// IPv4 address an IPv6 address is translated to or tunneled through.
+/// These reach the IPv4 address, so its rules apply.
+fn embedded_ipv4(ip: &Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = ip.segments();
+    let octets = ip.octets();
+
+    match segments {
+        // ::ffff:0:0/96 IPv4-mapped
+        [0, 0, 0, 0, 0, 0xffff, _, _]
+        // ::/96 IPv4-compatible, this includes the unspecified and loopback addresses
+        | [0, 0, 0, 0, 0, 0, _, _]
+        // 64:ff9b::/96 NAT64
+        | [0x64, 0xff9b, 0, 0, 0, 0, _, _] => {
+            Some(Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15]))
+        }
+        // 2002::/16 6to4
+        [0x2002, _, _, _, _, _, _, _] => {
+            Some(Ipv4Addr::new(octets[2], octets[3], octets[4], octets[5]))
+        }
+        _ => None,
+    }
+}
+
+/// Delay before an attempt, starting at [`RETRY_DELAY`] before the second attempt.
+fn retry_delay(attempt: i32) -> chrono::Duration {
+    let multiplier = 4u32.pow((attempt - 2).max(0) as u32);
+    chrono::Duration::from_std(RETRY_DELAY * multiplier).unwrap()
+}
+
+fn serialize_payload<T: Serialize>(event: WebhookEvent, data: &T) -> ServiceResult<String> {
+    serde_json::to_string(&Payload {
+        event: event.as_str(),
+        created: Utc::now(),
+        data,
+    })
+    .map_err(|e| ServiceError::ServerError(e.into()))
+}
+
+/// Sign a delivery body.
+///
+/// Returns the hex encoded signature.
+fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(&ip.parse().unwrap())
+    }
+
+    #[test]
+    fn allows_public_addresses() {
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111"] {
+            assert!(public(ip), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn denies_reserved_ipv4() {
+        for ip in [
+            "0.0.0.0",
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "192.0.0.1",
+            "198.18.0.1",
+            "255.255.255.255",
+            "224.0.0.1",
+        ] {
+            assert!(!public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn denies_reserved_ipv6() {
+        for ip in ["::", "::1", "fc00::1", "fe80::1", "ff02::1", "2001:db8::1"] {
+            assert!(!public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn applies_ipv4_rules_to_embedded_addresses() {
+        for ip in [
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "::127.0.0.1",
+            "::10.0.0.1",
+            "64:ff9b::127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:7f00:1::",
+            "2002:c0a8:101::1",
+        ] {
+            assert!(!public(ip), "{} should not be public", ip);
+        }
+
+        for ip in ["::ffff:1.1.1.1", "64:ff9b::1.1.1.1", "2002:101:101::1"] {
+            assert!(public(ip), "{} should be public", ip);
+        }
+    }
+}